@@ -0,0 +1,6 @@
+DELETE FROM calendars WHERE "source" <> 'google';
+
+ALTER TABLE calendars
+ALTER COLUMN "googleId" TYPE varchar(90);
+
+ALTER TABLE calendars DROP COLUMN "source";
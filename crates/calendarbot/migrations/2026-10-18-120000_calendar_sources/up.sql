@@ -0,0 +1,6 @@
+ALTER TABLE calendars
+ADD COLUMN "source" varchar(16) DEFAULT 'google' NOT NULL;
+
+-- ICS urls are way longer than google calendar ids
+ALTER TABLE calendars
+ALTER COLUMN "googleId" TYPE varchar(2048);
@@ -0,0 +1,2 @@
+ALTER TABLE calendars DROP COLUMN "authUsername";
+ALTER TABLE calendars DROP COLUMN "authSecret";
@@ -0,0 +1,6 @@
+-- A secret without username is used as a bearer token
+ALTER TABLE calendars
+ADD COLUMN "authUsername" varchar(256);
+
+ALTER TABLE calendars
+ADD COLUMN "authSecret" varchar(2048);
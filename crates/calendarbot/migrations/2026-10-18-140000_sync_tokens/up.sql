@@ -0,0 +1,2 @@
+ALTER TABLE calendars
+ADD COLUMN "syncToken" varchar(256);
@@ -0,0 +1,3 @@
+ALTER TABLE calendars
+ADD COLUMN "lastError" text,
+ADD COLUMN "failureCount" int4 DEFAULT 0 NOT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE guilds_calendars DROP COLUMN "failureWarning";
+
+ALTER TABLE calendars DROP COLUMN "lastSync";
@@ -0,0 +1,5 @@
+ALTER TABLE calendars
+ADD COLUMN "lastSync" timestamptz;
+
+ALTER TABLE guilds_calendars
+ADD COLUMN "failureWarning" int4 DEFAULT 3 NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE guilds_calendars DROP COLUMN "label";
@@ -0,0 +1,2 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "label" varchar(64);
@@ -0,0 +1,2 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "displayMode" varchar(16) DEFAULT 'compact' NOT NULL;
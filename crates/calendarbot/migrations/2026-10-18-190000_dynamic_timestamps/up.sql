@@ -0,0 +1,2 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "dynamicTimestamps" bool DEFAULT false NOT NULL;
@@ -0,0 +1,7 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "messageId" varchar(64);
+
+UPDATE guilds_calendars
+SET "messageId" = "messageIds"[1];
+
+ALTER TABLE guilds_calendars DROP COLUMN "messageIds";
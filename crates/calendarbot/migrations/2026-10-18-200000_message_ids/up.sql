@@ -0,0 +1,8 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "messageIds" varchar(64)[] DEFAULT '{}' NOT NULL;
+
+UPDATE guilds_calendars
+SET "messageIds" = ARRAY["messageId"]
+WHERE "messageId" IS NOT NULL;
+
+ALTER TABLE guilds_calendars DROP COLUMN "messageId";
@@ -0,0 +1,6 @@
+ALTER TABLE guilds_calendars
+DROP COLUMN "embedTitle",
+DROP COLUMN "embedColor",
+DROP COLUMN "dayFormat",
+DROP COLUMN "eventFormat",
+DROP COLUMN "embedFooter";
@@ -0,0 +1,6 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "embedTitle" varchar(256),
+ADD COLUMN "embedColor" int4,
+ADD COLUMN "dayFormat" varchar(64),
+ADD COLUMN "eventFormat" varchar(256),
+ADD COLUMN "embedFooter" varchar(2048);
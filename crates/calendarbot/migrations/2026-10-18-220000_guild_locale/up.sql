@@ -0,0 +1,2 @@
+ALTER TABLE guilds
+ADD COLUMN "locale" varchar(8);
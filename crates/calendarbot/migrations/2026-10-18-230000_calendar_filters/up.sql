@@ -0,0 +1,12 @@
+CREATE TABLE calendar_filters (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "guild_id" INTEGER NOT NULL,
+    "calendar_id" INTEGER NOT NULL,
+    "channelId" VARCHAR(64) NOT NULL,
+    "mode" VARCHAR(16) NOT NULL,
+    "kind" VARCHAR(16) NOT NULL,
+    "value" VARCHAR(256) NOT NULL DEFAULT '',
+
+    FOREIGN KEY ("guild_id", "calendar_id", "channelId")
+        REFERENCES guilds_calendars ("guild_id", "calendar_id", "channelId") ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+DROP TABLE reminders;
+
+ALTER TABLE guilds_calendars
+DROP COLUMN "reminderMinutes",
+DROP COLUMN "reminderRoleId";
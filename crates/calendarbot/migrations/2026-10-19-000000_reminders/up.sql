@@ -0,0 +1,22 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "reminderMinutes" INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN "reminderRoleId" VARCHAR(64);
+
+CREATE TABLE reminders (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "guild_id" INTEGER NOT NULL,
+    "calendar_id" INTEGER NOT NULL,
+    "channelId" VARCHAR(64) NOT NULL,
+    "eventId" VARCHAR(1024) NOT NULL,
+    "summary" TEXT NOT NULL,
+    "link" TEXT,
+    "eventStart" TIMESTAMPTZ NOT NULL,
+    "remindAt" TIMESTAMPTZ NOT NULL,
+    "sentAt" TIMESTAMPTZ,
+
+    UNIQUE ("calendar_id", "channelId", "eventId"),
+    FOREIGN KEY ("guild_id", "calendar_id", "channelId")
+        REFERENCES guilds_calendars ("guild_id", "calendar_id", "channelId") ON DELETE CASCADE
+);
+
+CREATE INDEX reminders_due ON reminders ("remindAt") WHERE "sentAt" IS NULL;
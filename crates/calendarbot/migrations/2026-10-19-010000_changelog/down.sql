@@ -0,0 +1,3 @@
+ALTER TABLE guilds_calendars
+DROP COLUMN "changelog",
+DROP COLUMN "changelogThreadId";
@@ -0,0 +1,3 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "changelog" BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN "changelogThreadId" VARCHAR(64);
@@ -0,0 +1,4 @@
+DROP TABLE scheduled_events;
+
+ALTER TABLE guilds_calendars
+DROP COLUMN "scheduledEvents";
@@ -0,0 +1,20 @@
+ALTER TABLE guilds_calendars
+ADD COLUMN "scheduledEvents" BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE scheduled_events (
+    "id" SERIAL PRIMARY KEY NOT NULL,
+    "guild_id" INTEGER NOT NULL,
+    "calendar_id" INTEGER NOT NULL,
+    "channelId" VARCHAR(64) NOT NULL,
+    "eventId" VARCHAR(1024) NOT NULL,
+    "discordEventId" VARCHAR(64) NOT NULL,
+    "name" VARCHAR(100) NOT NULL,
+    "description" VARCHAR(1000) NOT NULL,
+    "location" VARCHAR(100) NOT NULL,
+    "startTime" TIMESTAMPTZ NOT NULL,
+    "endTime" TIMESTAMPTZ NOT NULL,
+
+    UNIQUE ("calendar_id", "channelId", "eventId"),
+    FOREIGN KEY ("guild_id", "calendar_id", "channelId")
+        REFERENCES guilds_calendars ("guild_id", "calendar_id", "channelId") ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+ALTER TABLE guilds
+ADD COLUMN "eventRoleId" VARCHAR(64);
@@ -0,0 +1,2 @@
+-- The legacy names were never valid, there is nothing to restore
+SELECT 1;
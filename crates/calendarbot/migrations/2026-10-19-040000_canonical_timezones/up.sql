@@ -0,0 +1,4 @@
+-- The timezone choices used to write "Etc/Utc", which isn't an IANA name
+UPDATE guilds_calendars
+SET "timezone" = 'Etc/UTC'
+WHERE "timezone" IN ('Etc/Utc', 'Utc');
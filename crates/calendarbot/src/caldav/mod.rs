@@ -0,0 +1,317 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+mod xml;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use google_calendar3::chrono::{DateTime, Utc};
+use google_calendar3::hyper::header::{HeaderName, CONTENT_TYPE};
+use google_calendar3::hyper::{Method, StatusCode, Uri};
+use log::{trace, warn};
+
+use crate::ics::parse_events;
+use crate::sources::http::{resolve_uri, HttpClient, StatusError};
+use crate::sources::{events_in_any_timezone, CalendarAuth, CalendarSource};
+use crate::types::{CalendarEvent, CalendarEventSource};
+use xml::DavResponse;
+
+/// Calendar source for CalDAV servers (Radicale, Nextcloud, Baikal, ...)
+#[derive(Clone)]
+pub struct CalDavCalendar {
+    client: HttpClient,
+}
+
+impl CalDavCalendar {
+    pub fn new(client: HttpClient) -> CalDavCalendar {
+        CalDavCalendar { client }
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        uri: Uri,
+        depth: &str,
+        auth: Option<&CalendarAuth>,
+        body: &str,
+    ) -> Result<(Uri, Vec<DavResponse>)> {
+        let response = self
+            .client
+            .send(
+                Method::from_bytes(method.as_bytes())?,
+                uri,
+                auth,
+                &[
+                    (HeaderName::from_static("depth"), depth),
+                    (CONTENT_TYPE, "application/xml; charset=utf-8"),
+                ],
+                body,
+            )
+            .await?;
+
+        if response.status != StatusCode::MULTI_STATUS {
+            return Err(StatusError(response.status))
+                .context("Unexpected response from CalDAV server");
+        }
+
+        let body = std::str::from_utf8(&response.body)?;
+        Ok((response.uri, xml::parse_multistatus(body)?))
+    }
+
+    async fn propfind(
+        &self,
+        uri: Uri,
+        depth: &str,
+        auth: Option<&CalendarAuth>,
+    ) -> Result<(Uri, Vec<DavResponse>)> {
+        self.request("PROPFIND", uri, depth, auth, xml::PROPFIND_DISCOVERY)
+            .await
+    }
+
+    /// Finds the calendar collection from a calendar, principal or server url (RFC 6764 / RFC 4791 6.2.1)
+    async fn discover(&self, url: &str, auth: Option<&CalendarAuth>) -> Result<Uri> {
+        let uri: Uri = url.parse()?;
+
+        let (base, responses) = match self.propfind(uri.clone(), "0", auth).await {
+            Ok(result) => result,
+            Err(e) => {
+                trace!("PROPFIND failed on {}, trying well-known url: {:?}", uri, e);
+                self.propfind(resolve_uri(&uri, "/.well-known/caldav")?, "0", auth)
+                    .await?
+            }
+        };
+        let resource = responses
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Empty response from CalDAV server"))?;
+
+        if resource.is_calendar {
+            return Ok(base);
+        }
+
+        let calendar_home = match resource.calendar_home_set {
+            Some(home) => home,
+            None => {
+                let principal = resource
+                    .current_user_principal
+                    .ok_or_else(|| anyhow!("No calendar found at this url"))?;
+                let (_, responses) = self
+                    .propfind(resolve_uri(&base, &principal)?, "0", auth)
+                    .await?;
+                responses
+                    .into_iter()
+                    .find_map(|r| r.calendar_home_set)
+                    .ok_or_else(|| anyhow!("No calendar home found for this user"))?
+            }
+        };
+
+        let (base, responses) = self
+            .propfind(resolve_uri(&base, &calendar_home)?, "1", auth)
+            .await?;
+        let calendars: Vec<DavResponse> = responses.into_iter().filter(|r| r.is_calendar).collect();
+
+        match calendars.as_slice() {
+            [] => Err(anyhow!("No calendar found at this url")),
+            [calendar] => resolve_uri(&base, &calendar.href),
+            calendars => {
+                let list = calendars
+                    .iter()
+                    .map(|c| {
+                        let uri = resolve_uri(&base, &c.href)
+                            .map(|uri| uri.to_string())
+                            .unwrap_or_else(|_| c.href.clone());
+                        format!("- {}: <{}>", c.display_name.as_deref().unwrap_or("?"), uri)
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                Err(anyhow!(
+                    "Several calendars were found, please use one of these urls:\n{}",
+                    list
+                ))
+            }
+        }
+    }
+
+    /// Fetches the events of a calendar between `from` and `until`, recurring events are
+    /// returned as series
+    async fn query_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>> {
+        let (_, responses) = self
+            .request(
+                "REPORT",
+                calendar_id.parse()?,
+                "1",
+                auth,
+                &xml::calendar_query(from, until),
+            )
+            .await?;
+
+        let mut events = vec![];
+        for response in responses {
+            let Some(data) = response.calendar_data else {
+                continue;
+            };
+            match parse_events(data.as_bytes(), CalendarEventSource::CalDav) {
+                Ok(parsed) => events.extend(parsed),
+                Err(e) => warn!("Unable to parse {}: {:?}", response.href, e),
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl CalendarSource for CalDavCalendar {
+    fn kind(&self) -> CalendarEventSource {
+        CalendarEventSource::CalDav
+    }
+
+    async fn resolve_calendar_id(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+    ) -> Result<String> {
+        Ok(self.discover(calendar_id, auth).await?.to_string())
+    }
+
+    async fn get_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>> {
+        let events = self
+            .query_events(calendar_id, auth, Utc::now(), until)
+            .await?;
+
+        Ok(events_in_any_timezone(events, Utc::now(), until))
+    }
+
+    async fn search_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        text: &str,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>> {
+        let events = self
+            .query_events(calendar_id, auth, from, until)
+            .await?
+            .into_iter()
+            .filter(|event| event.contains_text(text))
+            .collect();
+
+        Ok(events_in_any_timezone(events, from, until))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::http::tests::{local_client, response, serve};
+
+    fn multistatus(responses: &str) -> String {
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">{}</d:multistatus>"#,
+            responses
+        );
+        response(
+            "207 Multi-Status",
+            &[("Content-Type", "application/xml")],
+            &body,
+        )
+    }
+
+    fn calendar(href: &str, name: &str) -> String {
+        format!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop>\
+            <d:resourcetype><d:collection/><c:calendar/></d:resourcetype>\
+            <d:displayname>{}</d:displayname>\
+            </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+            href, name
+        )
+    }
+
+    #[tokio::test]
+    async fn discover_from_principal() {
+        let (server, requests) = serve(vec![
+            multistatus(
+                "<d:response><d:href>/</d:href><d:propstat><d:prop>\
+                <d:current-user-principal><d:href>/principals/user/</d:href></d:current-user-principal>\
+                </d:prop></d:propstat></d:response>",
+            ),
+            multistatus(
+                "<d:response><d:href>/principals/user/</d:href><d:propstat><d:prop>\
+                <c:calendar-home-set><d:href>/calendars/user/</d:href></c:calendar-home-set>\
+                </d:prop></d:propstat></d:response>",
+            ),
+            multistatus(&format!(
+                "<d:response><d:href>/calendars/user/</d:href><d:propstat><d:prop>\
+                <d:resourcetype><d:collection/></d:resourcetype>\
+                </d:prop></d:propstat></d:response>{}",
+                calendar("work/", "Work")
+            )),
+        ])
+        .await;
+
+        let caldav = CalDavCalendar::new(local_client());
+        let calendar = caldav.discover(&server.to_string(), None).await.unwrap();
+        assert_eq!(
+            calendar,
+            resolve_uri(&server, "/calendars/user/work/").unwrap()
+        );
+
+        let requests = requests.await.unwrap();
+        assert!(requests[0].starts_with("PROPFIND / "));
+        assert!(requests[1].starts_with("PROPFIND /principals/user/ "));
+        assert!(requests[2].starts_with("PROPFIND /calendars/user/ "));
+        assert!(requests[2].to_ascii_lowercase().contains("depth: 1"));
+    }
+
+    #[tokio::test]
+    async fn discover_calendar_url() {
+        let (server, _) = serve(vec![multistatus(&calendar("/", "Work"))]).await;
+
+        let caldav = CalDavCalendar::new(local_client());
+        let calendar = caldav.discover(&server.to_string(), None).await.unwrap();
+        assert_eq!(calendar, server);
+    }
+
+    #[tokio::test]
+    async fn discover_several_calendars() {
+        let (server, _) = serve(vec![
+            multistatus(
+                "<d:response><d:href>/</d:href><d:propstat><d:prop>\
+                <c:calendar-home-set><d:href>/calendars/</d:href></c:calendar-home-set>\
+                </d:prop></d:propstat></d:response>",
+            ),
+            multistatus(&format!(
+                "{}{}",
+                calendar("/calendars/work/", "Work"),
+                calendar("/calendars/home/", "Home")
+            )),
+        ])
+        .await;
+
+        let caldav = CalDavCalendar::new(local_client());
+        let error = caldav
+            .discover(&server.to_string(), None)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("- Work: <"));
+        assert!(error.contains("/calendars/home/>"));
+    }
+}
@@ -0,0 +1,204 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use anyhow::Result;
+use google_calendar3::chrono::{DateTime, Utc};
+use roxmltree::{Document, Node};
+
+const DAV_NS: &str = "DAV:";
+const CALDAV_NS: &str = "urn:ietf:params:xml:ns:caldav";
+
+pub(crate) const PROPFIND_DISCOVERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:prop>
+    <D:resourcetype/>
+    <D:displayname/>
+    <D:current-user-principal/>
+    <C:calendar-home-set/>
+  </D:prop>
+</D:propfind>"#;
+
+/// REPORT body listing the events overlapping `start` to `end`
+pub(crate) fn calendar_query(start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:prop>
+    <C:calendar-data/>
+  </D:prop>
+  <C:filter>
+    <C:comp-filter name="VCALENDAR">
+      <C:comp-filter name="VEVENT">
+        <C:time-range start="{}" end="{}"/>
+      </C:comp-filter>
+    </C:comp-filter>
+  </C:filter>
+</C:calendar-query>"#,
+        start.format("%Y%m%dT%H%M%SZ"),
+        end.format("%Y%m%dT%H%M%SZ")
+    )
+}
+
+/// Properties of a resource found in a multistatus response
+#[derive(Debug, Default)]
+pub(crate) struct DavResponse {
+    pub href: String,
+    pub is_calendar: bool,
+    pub display_name: Option<String>,
+    pub current_user_principal: Option<String>,
+    pub calendar_home_set: Option<String>,
+    pub calendar_data: Option<String>,
+}
+
+/// Parses a 207 multistatus body (RFC 4918 13), properties with a non 200 status are ignored
+pub(crate) fn parse_multistatus(body: &str) -> Result<Vec<DavResponse>> {
+    let document = Document::parse(body)?;
+
+    let responses = document
+        .descendants()
+        .filter(|n| is(n, DAV_NS, "response"))
+        .filter_map(|response| {
+            let mut result = DavResponse {
+                href: href(&response)?,
+                ..Default::default()
+            };
+
+            for propstat in response.children().filter(|n| is(n, DAV_NS, "propstat")) {
+                let ok = child(&propstat, DAV_NS, "status")
+                    .and_then(|status| status.text())
+                    .is_none_or(|status| status.contains(" 200 "));
+                let Some(prop) = child(&propstat, DAV_NS, "prop") else {
+                    continue;
+                };
+                if !ok {
+                    continue;
+                }
+
+                for property in prop.children().filter(Node::is_element) {
+                    if is(&property, DAV_NS, "resourcetype") {
+                        result.is_calendar = child(&property, CALDAV_NS, "calendar").is_some();
+                    } else if is(&property, DAV_NS, "displayname") {
+                        result.display_name = property.text().map(str::to_string);
+                    } else if is(&property, DAV_NS, "current-user-principal") {
+                        result.current_user_principal = href(&property);
+                    } else if is(&property, CALDAV_NS, "calendar-home-set") {
+                        result.calendar_home_set = href(&property);
+                    } else if is(&property, CALDAV_NS, "calendar-data") {
+                        result.calendar_data = property.text().map(str::to_string);
+                    }
+                }
+            }
+
+            Some(result)
+        })
+        .collect();
+
+    Ok(responses)
+}
+
+fn is(node: &Node, namespace: &str, name: &str) -> bool {
+    node.tag_name().namespace() == Some(namespace) && node.tag_name().name() == name
+}
+
+fn child<'a, 'input>(
+    node: &Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| is(n, namespace, name))
+}
+
+fn href(node: &Node) -> Option<String> {
+    child(node, DAV_NS, "href")
+        .and_then(|href| href.text())
+        .map(|href| href.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use google_calendar3::chrono::TimeZone;
+
+    #[test]
+    fn multistatus_properties() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:cal="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/dav/calendars/user/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype><d:collection/></d:resourcetype>
+        <d:current-user-principal><d:href>/dav/principals/user/</d:href></d:current-user-principal>
+        <cal:calendar-home-set><d:href> /dav/calendars/user/ </d:href></cal:calendar-home-set>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop><d:displayname>Hidden</d:displayname></d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>work/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype><d:collection/><cal:calendar/></d:resourcetype>
+        <d:displayname>Work</d:displayname>
+        <cal:calendar-data>BEGIN:VCALENDAR
+END:VCALENDAR</cal:calendar-data>
+      </d:prop>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:propstat><d:prop/></d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+        let responses = parse_multistatus(body).unwrap();
+        // The response without href is skipped
+        assert_eq!(responses.len(), 2);
+
+        let home = &responses[0];
+        assert_eq!(home.href, "/dav/calendars/user/");
+        assert!(!home.is_calendar);
+        assert_eq!(home.display_name, None);
+        assert_eq!(
+            home.current_user_principal.as_deref(),
+            Some("/dav/principals/user/")
+        );
+        assert_eq!(
+            home.calendar_home_set.as_deref(),
+            Some("/dav/calendars/user/")
+        );
+
+        let calendar = &responses[1];
+        assert_eq!(calendar.href, "work/");
+        assert!(calendar.is_calendar);
+        assert_eq!(calendar.display_name.as_deref(), Some("Work"));
+        assert_eq!(
+            calendar.calendar_data.as_deref(),
+            Some("BEGIN:VCALENDAR\nEND:VCALENDAR")
+        );
+    }
+
+    #[test]
+    fn invalid_multistatus() {
+        assert!(parse_multistatus("<d:multistatus xmlns:d=\"DAV:\">").is_err());
+    }
+
+    #[test]
+    fn query_time_range() {
+        let start = Utc.with_ymd_and_hms(2026, 10, 18, 8, 30, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2026, 11, 1, 0, 0, 0).unwrap();
+        let query = calendar_query(start, end);
+
+        assert!(
+            query.contains(r#"<C:time-range start="20261018T083000Z" end="20261101T000000Z"/>"#)
+        );
+        assert!(Document::parse(&query).is_ok());
+    }
+}
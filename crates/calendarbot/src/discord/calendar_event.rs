@@ -6,17 +6,19 @@ This is free software, and you are welcome to redistribute it
  */
 
 use crate::discord::{Discord, LocalCache};
+use crate::events::{CalendarWarningEvent, ChangelogEvent, ReminderEvent};
+use crate::i18n::Text;
+use crate::sources::FailureKind;
 use crate::UpdateCalendarEvent;
 
-use crate::schema::calendars::dsl as calendars;
 use crate::schema::guilds_calendars::dsl as guilds_calendars;
-use crate::types::CalendarEvent;
+use crate::types::{escape_markdown, CalendarEvent, EventChange};
 use anyhow::Result;
 use diesel::prelude::*;
 use diesel_async::pooled_connection::deadpool::Pool;
 use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl;
-use log::{debug, error};
+use log::{debug, error, warn};
 use poise::serenity_prelude as serenity;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
@@ -26,7 +28,7 @@ impl Discord {
     async fn send_or_edit_message(
         channel_id: u64,
         message_id: Option<u64>,
-        embed: serenity::CreateEmbed,
+        embeds: Vec<serenity::CreateEmbed>,
         cache: LocalCache,
     ) -> Result<serenity::MessageId> {
         let channel = serenity::ChannelId::new(channel_id);
@@ -38,7 +40,7 @@ impl Discord {
                 .edit_message(
                     channel,
                     msg_id,
-                    &serenity::EditMessage::new().add_embed(embed.clone()),
+                    &serenity::EditMessage::new().embeds(embeds.clone()),
                     Vec::new(),
                 )
                 .await;
@@ -52,7 +54,7 @@ impl Discord {
 
         debug!("Send new message");
         match channel
-            .send_message(cache, serenity::CreateMessage::new().add_embed(embed))
+            .send_message(cache, serenity::CreateMessage::new().embeds(embeds))
             .await
         {
             Err(e) => Err(e.into()),
@@ -60,6 +62,47 @@ impl Discord {
         }
     }
 
+    /// Send or edit one message per page of embeds,
+    /// then delete the messages of the pages that are not needed anymore.
+    /// Returns the ids of the calendar messages, even when a page failed
+    async fn send_or_edit_messages(
+        channel_id: u64,
+        message_ids: &[u64],
+        pages: Vec<Vec<serenity::CreateEmbed>>,
+        cache: LocalCache,
+    ) -> (Vec<serenity::MessageId>, Result<()>) {
+        let mut msg_ids = vec![];
+        for (index, embeds) in pages.into_iter().enumerate() {
+            let message_id = message_ids.get(index).copied();
+            match Discord::send_or_edit_message(channel_id, message_id, embeds, cache.clone()).await
+            {
+                Ok(msg_id) => msg_ids.push(msg_id),
+                Err(e) => {
+                    // The messages of the next pages are kept for the next update
+                    msg_ids.extend(
+                        message_ids
+                            .iter()
+                            .skip(index)
+                            .map(|message_id| serenity::MessageId::new(*message_id)),
+                    );
+                    return (msg_ids, Err(e));
+                }
+            }
+        }
+
+        let channel = serenity::ChannelId::new(channel_id);
+        for message_id in message_ids.iter().skip(msg_ids.len()) {
+            let result = channel
+                .delete_message(&cache.client, serenity::MessageId::new(*message_id))
+                .await;
+            if let Err(e) = result {
+                warn!("Unable to delete message ({}): {}", message_id, e);
+            }
+        }
+
+        (msg_ids, Ok(()))
+    }
+
     pub(crate) fn calendar_events_thread(
         mut calendar_rx: mpsc::Receiver<UpdateCalendarEvent>,
         cache: Arc<Mutex<Option<LocalCache>>>,
@@ -80,47 +123,179 @@ impl Discord {
 
                 let cache = cache.as_ref().lock().await.clone().unwrap();
 
-                let embed = CalendarEvent::to_embed(
+                let pages = CalendarEvent::to_embed(
                     event.new_events.clone(),
                     event.calendar_options.clone(),
                 );
 
-                for (channel_id, message_id) in event.discord_channel_and_message_ids {
-                    debug!(target: &channel_id.to_string(), "Handling new_events with message_id: {:?}", message_id);
-                    let result = Discord::send_or_edit_message(
+                for (channel_id, message_ids) in event.discord_channel_and_message_ids {
+                    debug!(target: &channel_id.to_string(), "Handling new_events with message_ids: {:?}", message_ids);
+                    let (msg_ids, result) = Discord::send_or_edit_messages(
                         channel_id,
-                        message_id,
-                        embed.clone(),
+                        &message_ids,
+                        pages.clone(),
                         cache.clone(),
                     )
                     .await;
 
-                    match result {
-                        Err(e) => {
-                            error!("Failed to send or edit message: {}, calendar: {}, channel_id: {:?}, message_id: {:?}", e, event.calendar_id, channel_id, message_id);
+                    // Messages sent before a failure would be sent again on the next update
+                    let msg_ids: Vec<u64> = msg_ids.iter().map(|id| id.get()).collect();
+                    if msg_ids != message_ids {
+                        // Every calendar of the channel shares the messages
+                        let res = diesel::update(guilds_calendars::guilds_calendars)
+                            .filter(guilds_calendars::channelId.eq(channel_id.to_string()))
+                            .set(
+                                guilds_calendars::messageIds
+                                    .eq(msg_ids.iter().map(u64::to_string).collect::<Vec<_>>()),
+                            )
+                            .execute(&mut db)
+                            .await;
+
+                        if let Err(e) = res {
+                            error!("Unable to update message ids in database: {}", e);
                         }
-                        Ok(msg_id) => {
-                            if Some(msg_id.get()) != message_id {
-                                let db_cal_id = calendars::calendars
-                                    .filter(calendars::googleId.eq(event.calendar_id.clone()))
-                                    .select(calendars::id)
-                                    .first::<i32>(&mut db)
-                                    .await
-                                    .expect("Unable to get calendar id from database");
-
-                                diesel::update(guilds_calendars::guilds_calendars)
-                                    .filter(
-                                        guilds_calendars::channelId
-                                            .eq(channel_id.to_string())
-                                            .and(guilds_calendars::calendar_id.eq(db_cal_id)),
-                                    )
-                                    .set(guilds_calendars::messageId.eq(msg_id.get().to_string()))
-                                    .execute(&mut db)
-                                    .await
-                                    .expect("Unable to update message id in database");
-                            }
+                    }
+
+                    if let Err(e) = result {
+                        error!("Failed to send or edit message: {}, calendar: {}, channel_id: {:?}, message_ids: {:?}", e, event.calendar_id, channel_id, message_ids);
+                    }
+                }
+            }
+        });
+    }
+
+    pub(crate) fn calendar_warnings_thread(
+        mut warning_rx: mpsc::Receiver<CalendarWarningEvent>,
+        cache: Arc<Mutex<Option<LocalCache>>>,
+    ) {
+        tokio::spawn(async move {
+            while let Some(warning) = warning_rx.recv().await {
+                let cache = cache.as_ref().lock().await.clone().unwrap();
+
+                for (channel_id, label, locale) in warning.channels {
+                    let calendar = match label {
+                        Some(label) => Text::NamedCalendar.format(locale, &[("label", &label)]),
+                        None => Text::ThisCalendar.get(locale).to_string(),
+                    };
+                    let content = match &warning.error {
+                        Some(error) => {
+                            let error = match error {
+                                FailureKind::Status(status) => {
+                                    Text::FailureStatus.format(locale, &[("status", status)])
+                                }
+                                FailureKind::Network => {
+                                    Text::FailureNetwork.get(locale).to_string()
+                                }
+                                FailureKind::InvalidData => {
+                                    Text::FailureInvalidData.get(locale).to_string()
+                                }
+                                FailureKind::Unknown => {
+                                    Text::FailureUnknown.get(locale).to_string()
+                                }
+                            };
+                            Text::UpdateFailing.format(
+                                locale,
+                                &[
+                                    ("calendar", &calendar),
+                                    ("failures", &warning.failures),
+                                    ("error", &error),
+                                ],
+                            )
                         }
+                        None => Text::UpdateRecovered.format(
+                            locale,
+                            &[("calendar", &calendar), ("failures", &warning.failures)],
+                        ),
                     };
+
+                    // Labels are written by the guild and could mention anyone
+                    let result = serenity::ChannelId::new(channel_id)
+                        .send_message(
+                            &cache,
+                            serenity::CreateMessage::new()
+                                .content(content)
+                                .allowed_mentions(serenity::CreateAllowedMentions::new()),
+                        )
+                        .await;
+
+                    if let Err(e) = result {
+                        error!("Failed to send calendar warning to {}: {}", channel_id, e);
+                    }
+                }
+            }
+        });
+    }
+
+    pub(crate) fn reminders_thread(
+        mut reminder_rx: mpsc::Receiver<ReminderEvent>,
+        cache: Arc<Mutex<Option<LocalCache>>>,
+    ) {
+        tokio::spawn(async move {
+            while let Some(reminder) = reminder_rx.recv().await {
+                let cache = cache.as_ref().lock().await.clone().unwrap();
+
+                let timestamp = reminder.start.timestamp();
+                let mut content = Text::Reminder.format(
+                    reminder.locale,
+                    &[
+                        ("summary", &escape_markdown(&reminder.summary)),
+                        (
+                            "time",
+                            &format!("<t:{}:R> (<t:{}:t>)", timestamp, timestamp),
+                        ),
+                    ],
+                );
+                if let Some(link) = &reminder.link {
+                    content = format!("{}\n{}", content, link);
+                }
+
+                // Only the configured role is pinged, never everyone mentioned in the summary
+                let mut mentions = serenity::CreateAllowedMentions::new();
+                if let Some(role_id) = reminder.role_id {
+                    content = format!("<@&{}> {}", role_id, content);
+                    mentions = mentions.roles(vec![serenity::RoleId::new(role_id)]);
+                }
+
+                let result = serenity::ChannelId::new(reminder.channel_id)
+                    .send_message(
+                        &cache,
+                        serenity::CreateMessage::new()
+                            .content(content)
+                            .allowed_mentions(mentions),
+                    )
+                    .await;
+
+                if let Err(e) = result {
+                    error!("Failed to send reminder to {}: {}", reminder.channel_id, e);
+                }
+            }
+        });
+    }
+
+    pub(crate) fn changelog_thread(
+        mut changelog_rx: mpsc::Receiver<ChangelogEvent>,
+        cache: Arc<Mutex<Option<LocalCache>>>,
+    ) {
+        tokio::spawn(async move {
+            while let Some(changelog) = changelog_rx.recv().await {
+                let cache = cache.as_ref().lock().await.clone().unwrap();
+
+                let content =
+                    EventChange::to_message(&changelog.changes, &changelog.calendar_options);
+                let result = serenity::ChannelId::new(changelog.channel_id)
+                    .send_message(
+                        &cache,
+                        serenity::CreateMessage::new()
+                            .content(content)
+                            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+                    )
+                    .await;
+
+                if let Err(e) = result {
+                    error!(
+                        "Failed to send changelog to {}: {}",
+                        changelog.channel_id, e
+                    );
                 }
             }
         });
@@ -5,6 +5,9 @@ This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
 
+use super::{autocomplete_calendar, channel_calendars, command_locale, find_calendar};
+use crate::discord::scheduled_events::delete_scheduled_events;
+use crate::i18n::Text;
 use crate::schema::calendars::dsl as calendars;
 use crate::schema::guilds_calendars::dsl as guilds_calendars;
 use crate::ApplicationContext;
@@ -20,76 +23,127 @@ pub async fn delete(
     #[channel_types("Text")]
     #[description = "Channel (defaults to the current channel)"]
     channel: Option<serenity::GuildChannel>,
+    #[description = "Calendar to remove (defaults to every calendar of the channel)"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: Option<String>,
 ) -> Result<()> {
     let channel = match channel {
         Some(c) => c,
         None => ctx.guild_channel().await.unwrap(),
     };
 
+    let locale = command_locale(ctx).await;
     let mut db = ctx.data().db.get().await?;
 
-    let res = guilds_calendars::guilds_calendars
-        .filter(guilds_calendars::channelId.eq(channel.id.get().to_string()))
-        .select((guilds_calendars::calendar_id, guilds_calendars::messageId))
-        .first::<(i32, Option<String>)>(&mut db)
-        .await;
-
-    if res.is_err() {
-        let _ = ctx.reply("This channel doesn't have a calendar").await?;
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    if subscriptions.is_empty() {
+        let _ = ctx.reply(Text::NoCalendar.get(locale)).await?;
         return Ok(());
     }
-    let res = res?;
-
-    let calendar_id = res.0;
-
-    // Deleting the calendar message
-    if let Some(message_id) = res.1 {
-        let message_id = serenity::MessageId::new(message_id.parse::<u64>()?);
-
-        let res = channel.delete_messages(&ctx.http(), vec![message_id]).await;
 
-        if res.is_err() {
-            warn!("Unable to delete message (maybe the bot is missing the MANAGE_MESSAGE permission?): {:?}", res);
+    let removed = match calendar {
+        None => subscriptions.iter().collect::<Vec<_>>(),
+        Some(value) => match find_calendar(&subscriptions, &value) {
+            Some(calendar) => vec![calendar],
+            None => {
+                let _ = ctx.reply(Text::CalendarNotDisplayed.get(locale)).await?;
+                return Ok(());
+            }
+        },
+    };
+    let remaining = subscriptions.len() - removed.len();
+
+    // Deleting the calendar messages once the channel has no calendar left
+    let message_ids = subscriptions
+        .iter()
+        .map(|(guild_calendar, _)| &guild_calendar.messageIds)
+        .find(|message_ids| !message_ids.is_empty());
+    if let (0, Some(message_ids)) = (remaining, message_ids) {
+        let message_ids = message_ids
+            .iter()
+            .map(|message_id| Ok(serenity::MessageId::new(message_id.parse::<u64>()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Bulk deletion refuses messages older than two weeks and single messages
+        for message_id in message_ids {
+            let res = channel.id.delete_message(&ctx.http(), message_id).await;
+
+            if let Err(e) = res {
+                warn!("Unable to delete message {}: {:?}", message_id, e);
+            }
         }
     }
 
-    // Remove the calendar from the database
-    let del = diesel::delete(
-        guilds_calendars::guilds_calendars
-            .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
-    )
-    .execute(&mut db)
-    .await;
-
-    if del.is_err() {
-        let _ = ctx.reply("Unable to delete calendar").await?;
-        error!("Unable to delete calendar: {:?}", del);
-        return Ok(());
-    }
+    for (guild_calendar, calendar) in removed {
+        let calendar_id = calendar.id;
+
+        // The mirrored Discord events would be left behind
+        if guild_calendar.scheduledEvents {
+            if let Some(guild_id) = ctx.guild_id() {
+                let res = delete_scheduled_events(
+                    &mut db,
+                    ctx.http(),
+                    guild_id.get(),
+                    calendar_id,
+                    &guild_calendar.channelId,
+                )
+                .await;
+                if let Err(e) = res {
+                    warn!("Unable to delete scheduled events: {:?}", e);
+                }
+            }
+        }
 
-    // Remove the calendar from the database if it's not used anymore
-    // (no other guild or channel is using it)
-    let res = guilds_calendars::guilds_calendars
-        .filter(guilds_calendars::calendar_id.eq(calendar_id))
-        .select(guilds_calendars::calendar_id)
-        .first::<i32>(&mut db)
+        // Remove the calendar from the database
+        let del = diesel::delete(
+            guilds_calendars::guilds_calendars
+                .filter(guilds_calendars::channelId.eq(channel.id.get().to_string()))
+                .filter(guilds_calendars::calendar_id.eq(calendar_id)),
+        )
+        .execute(&mut db)
         .await;
 
-    if res.is_err() {
-        // The calendar is not used anymore
-        // Remove it from the database
-        let del = diesel::delete(calendars::calendars.filter(calendars::id.eq(calendar_id)))
-            .execute(&mut db)
-            .await;
         if del.is_err() {
-            error!("Failed to delete calendar from Calendar table: {:?}", del);
-            ctx.reply("Unable to delete calendar").await?;
+            let _ = ctx.reply(Text::UnableToDelete.get(locale)).await?;
+            error!("Unable to delete calendar: {:?}", del);
+            return Ok(());
+        }
+
+        // Remove the calendar from the database if it's not used anymore
+        // (no other guild or channel is using it)
+        let res = guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::calendar_id.eq(calendar_id))
+            .select(guilds_calendars::calendar_id)
+            .first::<i32>(&mut db)
+            .await;
+
+        if res.is_err() {
+            // The calendar is not used anymore
+            // Remove it from the database
+            let del = diesel::delete(calendars::calendars.filter(calendars::id.eq(calendar_id)))
+                .execute(&mut db)
+                .await;
+            if del.is_err() {
+                error!("Failed to delete calendar from Calendar table: {:?}", del);
+                ctx.reply(Text::UnableToDelete.get(locale)).await?;
+            }
         }
     }
 
+    // The message is updated without the removed calendar
+    if remaining > 0 {
+        diesel::update(
+            guilds_calendars::guilds_calendars
+                .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
+        )
+        .set(guilds_calendars::forceUpdate.eq(true))
+        .execute(&mut db)
+        .await?;
+    }
+
     ctx.send(
         poise::CreateReply::default()
-            .content("Successfully deleted")
+            .content(Text::SuccessfullyDeleted.get(locale))
             .reply(true)
             .ephemeral(true),
     )
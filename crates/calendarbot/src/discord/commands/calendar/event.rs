@@ -0,0 +1,701 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use super::{
+    autocomplete_calendar, calendar_name, calendar_source, channel_calendars, command_locale,
+    find_calendar, request, timezone, MAX_CHOICE_LENGTH,
+};
+use crate::events::CalendarCommands;
+use crate::i18n::{Locale, Text};
+use crate::models::{Calendar, GuildCalendar};
+use crate::schema::guilds;
+use crate::schema::guilds_calendars::dsl as guilds_calendars;
+use crate::sources::update_calendar_event::display_window;
+use crate::sources::{CalendarAuth, EventDraft};
+use crate::types::{
+    escape_markdown, parse_duration, parse_quick_event, truncate, CalendarEvent,
+    CalendarEventSource, EventTime,
+};
+use crate::ApplicationContext;
+use anyhow::{anyhow, Result};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use google_calendar3::chrono::{NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use log::{trace, warn};
+use poise::serenity_prelude as serenity;
+use serenity::Mentionable;
+use std::time::Duration;
+
+/// How long the form waits to be submitted
+const MODAL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long the preview of `/event quick` waits to be confirmed
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Format of the days of all-day events in the preview, e.g. "Friday 16 October 2026"
+const PREVIEW_DAY_FORMAT: &str = "%A %-d %B %Y";
+
+/// Format of the start shown in the form and in the suggestions
+const START_FORMAT: &str = "%Y-%m-%d %H:%M";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Other accepted formats of the start
+const START_FORMATS: [&str; 3] = [START_FORMAT, "%d.%m.%Y %H:%M", "%d/%m/%Y %H:%M"];
+const DATE_FORMATS: [&str; 3] = [DATE_FORMAT, "%d.%m.%Y", "%d/%m/%Y"];
+
+/// Discord modals are limited to five inputs, the date and time share one
+#[derive(Debug, poise::Modal)]
+#[name = "Event"]
+struct EventModal {
+    #[name = "Title"]
+    #[max_length = 1024]
+    title: String,
+    #[name = "Start (YYYY-MM-DD HH:MM)"]
+    #[placeholder = "Only the date for an all-day event"]
+    start: String,
+    #[name = "Duration (defaults to 1h, or 1d)"]
+    #[placeholder = "e.g. 45m, 1h30 or 2d"]
+    duration: Option<String>,
+    #[name = "Description"]
+    #[paragraph]
+    #[max_length = 4000]
+    description: Option<String>,
+    #[name = "Location"]
+    #[max_length = 1024]
+    location: Option<String>,
+}
+
+impl EventModal {
+    /// Fills the form with an event, times are in the timezone of the channel
+    fn from_event(event: &CalendarEvent, timezone: &Tz) -> Self {
+        EventModal {
+            title: event.summary.clone(),
+            start: event
+                .start
+                .map(|start| format_start(start, timezone))
+                .unwrap_or_default(),
+            duration: event
+                .start
+                .zip(event.end)
+                .map(|(start, end)| format_duration(start, end, timezone)),
+            description: Some(event.description.clone()).filter(|d| !d.is_empty()),
+            location: event.location.clone(),
+        }
+    }
+
+    /// Reads the form, the error is the message to show
+    fn to_draft(&self, timezone: &Tz, locale: Locale) -> std::result::Result<EventDraft, String> {
+        let Some(start) = parse_start(&self.start, timezone) else {
+            return Err(Text::InvalidEventStart.format(locale, &[("start", &self.start.trim())]));
+        };
+        let duration = match self.duration.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(duration) => match parse_duration(duration) {
+                Some(duration) => Some(duration),
+                None => {
+                    return Err(Text::InvalidDuration.format(locale, &[("duration", &duration)]))
+                }
+            },
+        };
+
+        let end = match start {
+            EventTime::Date(date) => {
+                // Rounded up to whole days
+                let days = duration.map_or(1, |duration| {
+                    (duration.num_minutes() + 24 * 60 - 1) / (24 * 60)
+                });
+                EventTime::Date(date + TimeDelta::days(days))
+            }
+            EventTime::DateTime(date_time) => {
+                EventTime::DateTime(date_time + duration.unwrap_or(TimeDelta::hours(1)))
+            }
+        };
+
+        let optional = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        Ok(EventDraft {
+            summary: self.title.trim().to_string(),
+            description: optional(&self.description),
+            location: optional(&self.location),
+            start,
+            end,
+            timezone: timezone.name().to_string(),
+        })
+    }
+}
+
+/// Parses a start in the timezone of the channel, a date alone is an all-day event
+fn parse_start(value: &str, timezone: &Tz) -> Option<EventTime> {
+    let value = value.trim();
+    if let Some(date_time) = START_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        return date_time
+            .and_local_timezone(*timezone)
+            .earliest()
+            .map(|date_time| EventTime::DateTime(date_time.to_utc()));
+    }
+
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .map(EventTime::Date)
+}
+
+/// Formats a start like the form expects it
+fn format_start(time: EventTime, timezone: &Tz) -> String {
+    match time {
+        EventTime::Date(date) => date.format(DATE_FORMAT).to_string(),
+        EventTime::DateTime(date_time) => date_time
+            .with_timezone(timezone)
+            .format(START_FORMAT)
+            .to_string(),
+    }
+}
+
+/// Formats the duration of an event like the form expects it
+fn format_duration(start: EventTime, end: EventTime, timezone: &Tz) -> String {
+    if let (EventTime::Date(start), EventTime::Date(end)) = (start, end) {
+        return format!("{}d", (end - start).num_days());
+    }
+
+    let minutes = (end.to_utc(timezone) - start.to_utc(timezone)).num_minutes();
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h{:02}", hours, minutes),
+    }
+}
+
+/// Formats a start or end in the preview, timestamps are shown in the timezone of each member
+fn preview_time(time: EventTime, locale: Locale) -> String {
+    match time {
+        EventTime::Date(date) => locale.format_date(date, PREVIEW_DAY_FORMAT),
+        EventTime::DateTime(date_time) => format!("<t:{}:F>", date_time.timestamp()),
+    }
+}
+
+fn is_writable(calendar: &Calendar) -> bool {
+    calendar_source(calendar).is_some_and(CalendarEventSource::is_writable)
+}
+
+/// Returns the events displayed in the channel, from the synced events when they are fresh
+async fn upcoming_events(
+    ctx: ApplicationContext<'_>,
+    (guild_calendar, calendar): &(GuildCalendar, Calendar),
+) -> Result<Vec<CalendarEvent>> {
+    let source = calendar_source(calendar).ok_or_else(|| anyhow!("Unknown calendar source"))?;
+    let timezone = timezone(guild_calendar)?;
+    request(ctx, |resp| CalendarCommands::GetUpcomingEvents {
+        id: calendar.id,
+        source,
+        calendar_id: calendar.googleId.clone(),
+        auth: CalendarAuth::from_calendar(calendar),
+        until: Utc::now() + display_window(std::slice::from_ref(guild_calendar)),
+        timezone,
+        resp,
+    })
+    .await
+}
+
+/// Value of an event in the suggestions, the event id alone doesn't tell its calendar
+fn event_value(calendar: &Calendar, event: &CalendarEvent) -> String {
+    format!("{}:{}", calendar.id, event.id)
+}
+
+/// Finds an event of the writable calendars of a channel from the value of `autocomplete_event`
+async fn find_event<'a>(
+    ctx: ApplicationContext<'_>,
+    calendars: &'a [(GuildCalendar, Calendar)],
+    value: &str,
+) -> Result<Option<(&'a (GuildCalendar, Calendar), CalendarEvent)>> {
+    let Some((calendar_id, event_id)) = value.split_once(':') else {
+        return Ok(None);
+    };
+    let Some(subscription) = calendars
+        .iter()
+        .find(|(_, calendar)| calendar.id.to_string() == calendar_id && is_writable(calendar))
+    else {
+        return Ok(None);
+    };
+
+    let event = upcoming_events(ctx, subscription)
+        .await?
+        .into_iter()
+        .find(|event| event.id == event_id);
+    Ok(event.map(|event| (subscription, event)))
+}
+
+/// Suggests the upcoming events of the writable calendars of the current channel
+async fn autocomplete_event(
+    ctx: ApplicationContext<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let Ok(mut db) = ctx.data().db.get().await else {
+        return vec![];
+    };
+    let Ok(calendars) = channel_calendars(&mut db, ctx.channel_id().get()).await else {
+        return vec![];
+    };
+
+    let partial = partial.to_lowercase();
+    let mut choices = vec![];
+    for subscription in calendars.iter().filter(|(_, c)| is_writable(c)) {
+        let (guild_calendar, calendar) = subscription;
+        let Ok(timezone) = timezone(guild_calendar) else {
+            continue;
+        };
+        let events = match upcoming_events(ctx, subscription).await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Unable to suggest the events of {}: {:?}", calendar.id, e);
+                continue;
+            }
+        };
+
+        for event in events {
+            let value = event_value(calendar, &event);
+            // Longer values are refused by Discord
+            if value.len() > MAX_CHOICE_LENGTH {
+                continue;
+            }
+            let event = CalendarEvent {
+                calendar_label: guild_calendar.label.clone(),
+                ..event
+            };
+            let name = format!(
+                "{} {}",
+                event
+                    .start
+                    .map(|start| format_start(start, &timezone))
+                    .unwrap_or_default(),
+                event.display_summary()
+            );
+            if name.to_lowercase().contains(&partial) {
+                choices.push(serenity::AutocompleteChoice::new(
+                    name.chars().take(MAX_CHOICE_LENGTH).collect::<String>(),
+                    value,
+                ));
+            }
+        }
+    }
+
+    choices
+}
+
+/// Whether the author can write to the calendars: members with the event role of the guild,
+/// or with the Manage Server permission. Replies why when they can't
+async fn check_event_role(
+    ctx: ApplicationContext<'_>,
+    db: &mut AsyncPgConnection,
+    locale: Locale,
+) -> Result<bool> {
+    let guild_id = ctx.guild_id().ok_or_else(|| anyhow!("Guild not found"))?;
+    let role_id = guilds::table
+        .filter(guilds::discordId.eq(guild_id.get().to_string()))
+        .select(guilds::eventRoleId)
+        .first::<Option<String>>(db)
+        .await
+        .optional()?
+        .flatten()
+        .and_then(|role_id| role_id.parse::<u64>().ok())
+        .map(serenity::RoleId::new);
+
+    let member = ctx.interaction.member.as_deref();
+    let can_manage = member
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild());
+    let has_role = role_id
+        .zip(member)
+        .is_some_and(|(role_id, member)| member.roles.contains(&role_id));
+    if can_manage || has_role {
+        return Ok(true);
+    }
+
+    let message = match role_id {
+        Some(role_id) => Text::EventRoleRequired.format(locale, &[("role", &role_id.mention())]),
+        None => Text::ManageServerRequired.get(locale).to_string(),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(false)
+}
+
+/// Finds the calendar of the channel to add an event to, the only writable one when none
+/// is given. Replies why when there is none
+async fn writable_calendar<'a>(
+    ctx: ApplicationContext<'_>,
+    locale: Locale,
+    subscriptions: &'a [(GuildCalendar, Calendar)],
+    calendar: Option<String>,
+) -> Result<Option<&'a (GuildCalendar, Calendar)>> {
+    if subscriptions.is_empty() {
+        let _ = ctx.reply(Text::NoCalendar.get(locale)).await?;
+        return Ok(None);
+    }
+
+    let text = match calendar {
+        Some(value) => match find_calendar(subscriptions, &value) {
+            Some(subscription) if is_writable(&subscription.1) => return Ok(Some(subscription)),
+            Some(_) => Text::CalendarNotWritable,
+            None => Text::CalendarNotDisplayed,
+        },
+        None => {
+            let writable: Vec<_> = subscriptions
+                .iter()
+                .filter(|(_, calendar)| is_writable(calendar))
+                .collect();
+            match writable.as_slice() {
+                [subscription] => return Ok(Some(*subscription)),
+                [] => Text::NoWritableCalendar,
+                _ => Text::CalendarRequired,
+            }
+        }
+    };
+    let _ = ctx.reply(text.get(locale)).await?;
+    Ok(None)
+}
+
+/// Creates an event in a calendar, returns its identifier
+async fn create_event(
+    ctx: ApplicationContext<'_>,
+    calendar: &Calendar,
+    event: &EventDraft,
+) -> Result<String> {
+    let source = calendar_source(calendar).ok_or_else(|| anyhow!("Unknown calendar source"))?;
+    let event_id = request(ctx, |resp| CalendarCommands::CreateEvent {
+        source,
+        calendar_id: calendar.googleId.clone(),
+        auth: CalendarAuth::from_calendar(calendar),
+        event: event.clone(),
+        resp,
+    })
+    .await?;
+    trace!("Created event {} in calendar {}", event_id, calendar.id);
+    Ok(event_id)
+}
+
+/// Every channel showing the calendar is displayed again on the next poll
+async fn refresh_calendar(db: &mut AsyncPgConnection, calendar_id: i32) -> Result<()> {
+    diesel::update(
+        guilds_calendars::guilds_calendars.filter(guilds_calendars::calendar_id.eq(calendar_id)),
+    )
+    .set(guilds_calendars::forceUpdate.eq(true))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Google calendar",
+    subcommands("create", "quick", "edit", "cancel"),
+    subcommand_required
+)]
+pub async fn event(_: ApplicationContext<'_>) -> Result<()> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn create(
+    ctx: ApplicationContext<'_>,
+    #[description = "Calendar of this channel (needed when it shows several Google calendars)"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    if !check_event_role(ctx, &mut db, locale).await? {
+        return Ok(());
+    }
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let Some(subscription) = writable_calendar(ctx, locale, &subscriptions, calendar).await? else {
+        return Ok(());
+    };
+    let (guild_calendar, calendar) = subscription;
+    let timezone = timezone(guild_calendar)?;
+
+    let Some(form) =
+        poise::execute_modal::<_, _, EventModal>(ctx, None, Some(MODAL_TIMEOUT)).await?
+    else {
+        return Ok(());
+    };
+    let event = match form.to_draft(&timezone, locale) {
+        Ok(event) => event,
+        Err(message) => {
+            let _ = ctx.reply(message).await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = create_event(ctx, calendar, &event).await {
+        let _ = ctx
+            .reply(Text::EventWriteFailed.format(locale, &[("error", &e)]))
+            .await?;
+        return Ok(());
+    }
+    refresh_calendar(&mut db, calendar.id).await?;
+
+    let _ = ctx
+        .reply(Text::EventCreated.format(locale, &[("summary", &escape_markdown(&event.summary))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn quick(
+    ctx: ApplicationContext<'_>,
+    #[description = "e.g. Team retro next friday 14:00-15:30 @ room B"]
+    #[max_length = 1000]
+    text: String,
+    #[description = "Calendar of this channel (needed when it shows several Google calendars)"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    if !check_event_role(ctx, &mut db, locale).await? {
+        return Ok(());
+    }
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let Some(subscription) = writable_calendar(ctx, locale, &subscriptions, calendar).await? else {
+        return Ok(());
+    };
+    let (guild_calendar, calendar) = subscription;
+    let timezone = timezone(guild_calendar)?;
+
+    let Some(quick_event) = parse_quick_event(&text, Utc::now().with_timezone(&timezone)) else {
+        let _ = ctx.reply(Text::QuickEventNotUnderstood.get(locale)).await?;
+        return Ok(());
+    };
+    let event = EventDraft {
+        summary: quick_event.summary,
+        description: None,
+        location: quick_event.location,
+        start: quick_event.start,
+        end: quick_event.end,
+        timezone: timezone.name().to_string(),
+    };
+
+    // The end of all-day events is shown as their last day
+    let end = match event.end {
+        EventTime::Date(date) => EventTime::Date(date - TimeDelta::days(1)),
+        end => end,
+    };
+    let mut embed = serenity::CreateEmbed::new()
+        .title(truncate(&event.summary, 256))
+        .description(Text::CreateEventPreview.format(
+            locale,
+            &[("calendar", &calendar_name(guild_calendar, calendar))],
+        ))
+        .field(
+            Text::EventStart.get(locale),
+            preview_time(event.start, locale),
+            true,
+        )
+        .field(Text::EventEnd.get(locale), preview_time(end, locale), true);
+    if let Some(location) = &event.location {
+        embed = embed.field(
+            Text::EventLocation.get(locale),
+            truncate(location, 1024),
+            false,
+        );
+    }
+
+    let confirm_id = format!("{}-confirm", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+    let buttons = serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(&confirm_id)
+            .label(Text::Create.get(locale))
+            .style(serenity::ButtonStyle::Success),
+        serenity::CreateButton::new(&cancel_id)
+            .label(Text::Cancel.get(locale))
+            .style(serenity::ButtonStyle::Secondary),
+    ]);
+    let preview = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(vec![buttons])
+                .ephemeral(true),
+        )
+        .await?;
+
+    let prefix = ctx.id().to_string();
+    let interaction = serenity::ComponentInteractionCollector::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(CONFIRM_TIMEOUT)
+        .filter(move |interaction| interaction.data.custom_id.starts_with(&prefix))
+        .await;
+
+    let content = match interaction {
+        Some(interaction) if interaction.data.custom_id == confirm_id => {
+            interaction
+                .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+                .await?;
+            match create_event(ctx, calendar, &event).await {
+                Ok(_) => {
+                    refresh_calendar(&mut db, calendar.id).await?;
+                    Text::EventCreated
+                        .format(locale, &[("summary", &escape_markdown(&event.summary))])
+                }
+                Err(e) => Text::EventWriteFailed.format(locale, &[("error", &e)]),
+            }
+        }
+        Some(interaction) => {
+            interaction
+                .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+                .await?;
+            Text::EventNotCreated.get(locale).to_string()
+        }
+        None => Text::EventNotCreated.get(locale).to_string(),
+    };
+    preview
+        .edit(
+            poise::Context::Application(ctx),
+            poise::CreateReply::default()
+                .content(content)
+                .components(vec![]),
+        )
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn edit(
+    ctx: ApplicationContext<'_>,
+    #[description = "Upcoming event of this channel"]
+    #[autocomplete = "autocomplete_event"]
+    event: String,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    if !check_event_role(ctx, &mut db, locale).await? {
+        return Ok(());
+    }
+
+    // The form has to be the first response, the event is fetched before
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let Some(((guild_calendar, calendar), current)) =
+        find_event(ctx, &subscriptions, &event).await?
+    else {
+        let _ = ctx.reply(Text::EventNotFound.get(locale)).await?;
+        return Ok(());
+    };
+    let timezone = timezone(guild_calendar)?;
+
+    let defaults = EventModal::from_event(&current, &timezone);
+    let Some(form) =
+        poise::execute_modal::<_, _, EventModal>(ctx, Some(defaults), Some(MODAL_TIMEOUT)).await?
+    else {
+        return Ok(());
+    };
+    let draft = match form.to_draft(&timezone, locale) {
+        Ok(draft) => draft,
+        Err(message) => {
+            let _ = ctx.reply(message).await?;
+            return Ok(());
+        }
+    };
+
+    let source = calendar_source(calendar).ok_or_else(|| anyhow!("Unknown calendar source"))?;
+    let result = request(ctx, |resp| CalendarCommands::UpdateEvent {
+        source,
+        calendar_id: calendar.googleId.clone(),
+        auth: CalendarAuth::from_calendar(calendar),
+        event_id: current.id.clone(),
+        event: draft.clone(),
+        resp,
+    })
+    .await;
+    if let Err(e) = result {
+        let _ = ctx
+            .reply(Text::EventWriteFailed.format(locale, &[("error", &e)]))
+            .await?;
+        return Ok(());
+    }
+    trace!("Updated event {} in calendar {}", current.id, calendar.id);
+    refresh_calendar(&mut db, calendar.id).await?;
+
+    let _ = ctx
+        .reply(Text::EventUpdated.format(locale, &[("summary", &escape_markdown(&draft.summary))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn cancel(
+    ctx: ApplicationContext<'_>,
+    #[description = "Upcoming event of this channel"]
+    #[autocomplete = "autocomplete_event"]
+    event: String,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    if !check_event_role(ctx, &mut db, locale).await? {
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let Some(((_, calendar), current)) = find_event(ctx, &subscriptions, &event).await? else {
+        let _ = ctx.reply(Text::EventNotFound.get(locale)).await?;
+        return Ok(());
+    };
+
+    let source = calendar_source(calendar).ok_or_else(|| anyhow!("Unknown calendar source"))?;
+    let result = request(ctx, |resp| CalendarCommands::DeleteEvent {
+        source,
+        calendar_id: calendar.googleId.clone(),
+        auth: CalendarAuth::from_calendar(calendar),
+        event_id: current.id.clone(),
+        resp,
+    })
+    .await;
+    if let Err(e) = result {
+        let _ = ctx
+            .reply(Text::EventWriteFailed.format(locale, &[("error", &e)]))
+            .await?;
+        return Ok(());
+    }
+    trace!("Cancelled event {} in calendar {}", current.id, calendar.id);
+    refresh_calendar(&mut db, calendar.id).await?;
+
+    let _ = ctx
+        .reply(
+            Text::EventCancelled.format(locale, &[("summary", &escape_markdown(&current.summary))]),
+        )
+        .await?;
+    Ok(())
+}
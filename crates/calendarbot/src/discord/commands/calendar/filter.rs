@@ -0,0 +1,288 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use super::{
+    autocomplete_calendar, calendar_name, channel_calendars, command_locale, find_calendar,
+};
+use crate::i18n::Text;
+use crate::models::CalendarFilter;
+use crate::schema::calendar_filters::dsl as calendar_filters;
+use crate::schema::guilds_calendars::dsl as guilds_calendars;
+use crate::types::{parse_filter_regex, FilterKind, FilterMode};
+use crate::ApplicationContext;
+use anyhow::{anyhow, Result};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::trace;
+use poise::serenity_prelude as serenity;
+
+/// Discord limits the name and value of a choice to 100 characters
+const MAX_CHOICE_LENGTH: usize = 100;
+
+/// Returns the filters of a channel
+async fn channel_filters(
+    db: &mut AsyncPgConnection,
+    channel_id: u64,
+) -> Result<Vec<CalendarFilter>> {
+    Ok(calendar_filters::calendar_filters
+        .filter(calendar_filters::channelId.eq(channel_id.to_string()))
+        .order(calendar_filters::id)
+        .load(db)
+        .await?)
+}
+
+/// Describes a filter, e.g. `#3 exclude summary "Focus time"`
+fn filter_description(filter: &CalendarFilter) -> String {
+    if filter.value.is_empty() {
+        format!("#{} {} {}", filter.id, filter.mode, filter.kind)
+    } else {
+        format!(
+            "#{} {} {} \"{}\"",
+            filter.id, filter.mode, filter.kind, filter.value
+        )
+    }
+}
+
+/// The channel is displayed again with its new filters
+async fn force_update(db: &mut AsyncPgConnection, channel_id: u64) -> Result<()> {
+    diesel::update(
+        guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::channelId.eq(channel_id.to_string())),
+    )
+    .set(guilds_calendars::forceUpdate.eq(true))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Suggests the filters of the current channel
+async fn autocomplete_filter(
+    ctx: ApplicationContext<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let Ok(mut db) = ctx.data().db.get().await else {
+        return vec![];
+    };
+    let Ok(filters) = channel_filters(&mut db, ctx.channel_id().get()).await else {
+        return vec![];
+    };
+
+    let partial = partial.to_lowercase();
+    filters
+        .iter()
+        .map(|filter| (filter_description(filter), filter.id))
+        .filter(|(description, _)| description.to_lowercase().contains(&partial))
+        .map(|(description, id)| {
+            serenity::AutocompleteChoice::new(
+                description
+                    .chars()
+                    .take(MAX_CHOICE_LENGTH)
+                    .collect::<String>(),
+                id.to_string(),
+            )
+        })
+        .collect()
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Google calendar",
+    subcommands("add", "list", "remove", "clear"),
+    subcommand_required
+)]
+pub async fn filter(_: ApplicationContext<'_>) -> Result<()> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn add(
+    ctx: ApplicationContext<'_>,
+    #[description = "Show only the matching events, or hide them"] mode: FilterMode,
+    #[description = "What the filter looks at"] kind: FilterKind,
+    #[description = "Text, regex, colour ID or email address (not needed for declined)"]
+    #[max_length = 256]
+    value: Option<String>,
+    #[description = "Calendar of this channel (defaults to every calendar of the channel)"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    if subscriptions.is_empty() {
+        let _ = ctx.reply(Text::NoCalendar.get(locale)).await?;
+        return Ok(());
+    }
+    let filtered = match calendar {
+        None => subscriptions.iter().collect::<Vec<_>>(),
+        Some(value) => match find_calendar(&subscriptions, &value) {
+            Some(calendar) => vec![calendar],
+            None => {
+                let _ = ctx.reply(Text::CalendarNotDisplayed.get(locale)).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let value = value
+        .map(|value| value.trim().to_string())
+        .unwrap_or_default();
+    if kind.needs_value() && value.is_empty() {
+        let _ = ctx.reply(Text::FilterValueRequired.get(locale)).await?;
+        return Ok(());
+    }
+    if kind == FilterKind::Regex {
+        if let Err(e) = parse_filter_regex(&value) {
+            let _ = ctx
+                .reply(Text::InvalidRegex.format(locale, &[("error", &e)]))
+                .await?;
+            return Ok(());
+        }
+    }
+    let value = if kind.needs_value() {
+        value
+    } else {
+        String::new()
+    };
+
+    trace!(
+        "Adding filter {} {} {:?} in channel {:?}",
+        mode,
+        kind,
+        value,
+        channel.id.get()
+    );
+
+    for (guild_calendar, _) in filtered {
+        diesel::insert_into(calendar_filters::calendar_filters)
+            .values((
+                calendar_filters::guild_id.eq(guild_calendar.guild_id),
+                calendar_filters::calendar_id.eq(guild_calendar.calendar_id),
+                calendar_filters::channelId.eq(&guild_calendar.channelId),
+                calendar_filters::mode.eq(mode.to_string()),
+                calendar_filters::kind.eq(kind.to_string()),
+                calendar_filters::value.eq(&value),
+            ))
+            .execute(&mut db)
+            .await?;
+    }
+    force_update(&mut db, channel.id.get()).await?;
+
+    let _ = ctx.reply(Text::FilterAdded.get(locale)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn list(ctx: ApplicationContext<'_>) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let filters = channel_filters(&mut db, channel.id.get()).await?;
+    if filters.is_empty() {
+        let _ = ctx.reply(Text::NoFilters.get(locale)).await?;
+        return Ok(());
+    }
+
+    let mut embed = serenity::CreateEmbed::new().title(Text::Filters.get(locale));
+    for (guild_calendar, calendar) in &subscriptions {
+        let lines: Vec<String> = filters
+            .iter()
+            .filter(|filter| filter.calendar_id == calendar.id)
+            .map(filter_description)
+            .collect();
+        if lines.is_empty() {
+            continue;
+        }
+
+        let name: String = calendar_name(guild_calendar, calendar)
+            .chars()
+            .take(256)
+            .collect();
+        let value: String = lines.join("\n").chars().take(1024).collect();
+        embed = embed.field(name, value, false);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn remove(
+    ctx: ApplicationContext<'_>,
+    #[description = "Filter of this channel"]
+    #[autocomplete = "autocomplete_filter"]
+    filter: String,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let filter_id = filter.trim_start_matches('#').parse::<i32>().ok();
+    let deleted = diesel::delete(
+        calendar_filters::calendar_filters
+            .filter(calendar_filters::channelId.eq(channel.id.get().to_string()))
+            .filter(calendar_filters::id.nullable().eq(filter_id)),
+    )
+    .execute(&mut db)
+    .await?;
+
+    if deleted == 0 {
+        let _ = ctx.reply(Text::FilterNotFound.get(locale)).await?;
+        return Ok(());
+    }
+    force_update(&mut db, channel.id.get()).await?;
+
+    let _ = ctx.reply(Text::FilterRemoved.get(locale)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn clear(
+    ctx: ApplicationContext<'_>,
+    #[description = "Calendar of this channel (defaults to every calendar of the channel)"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let calendar_id = match calendar {
+        None => None,
+        Some(value) => {
+            let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+            match find_calendar(&subscriptions, &value) {
+                Some((guild_calendar, _)) => Some(guild_calendar.calendar_id),
+                None => {
+                    let _ = ctx.reply(Text::CalendarNotDisplayed.get(locale)).await?;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let mut query = diesel::delete(calendar_filters::calendar_filters)
+        .filter(calendar_filters::channelId.eq(channel.id.get().to_string()))
+        .into_boxed();
+    if let Some(calendar_id) = calendar_id {
+        query = query.filter(calendar_filters::calendar_id.eq(calendar_id));
+    }
+    query.execute(&mut db).await?;
+    force_update(&mut db, channel.id.get()).await?;
+
+    let _ = ctx.reply(Text::FiltersCleared.get(locale)).await?;
+    Ok(())
+}
@@ -0,0 +1,239 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use super::{
+    calendar_name, calendar_source, channel_calendars, command_locale, find_calendar,
+    reply_ephemeral, request, start_of_day, timezone,
+};
+use crate::discord::pagination::Pages;
+use crate::events::CalendarCommands;
+use crate::i18n::Text;
+use crate::serenity;
+use crate::sources::CalendarAuth;
+use crate::types::{
+    parse_day, parse_duration, parse_hours, CalendarEventSource, Period, WorkingHours,
+};
+use crate::ApplicationContext;
+use anyhow::Result;
+use google_calendar3::chrono::{NaiveDate, NaiveTime, TimeDelta, Utc};
+use std::collections::BTreeMap;
+
+/// Days searched by default, the first one included
+const DEFAULT_DAYS: i64 = 7;
+
+/// Longest range searched, the free/busy API refuses long ranges
+const MAX_DAYS: i64 = 31;
+
+/// Days shown on each page
+const PAGE_DAYS: usize = 7;
+
+/// Format of the days of the slots, e.g. "Fri 16 Oct"
+const SLOT_DAY_FORMAT: &str = "%a %-d %b";
+
+/// Calendar whose busy periods are asked
+struct BusyCalendar {
+    name: String,
+    source: CalendarEventSource,
+    calendar_id: String,
+    auth: Option<CalendarAuth>,
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn freebusy(
+    ctx: ApplicationContext<'_>,
+    #[description = "Length of the meeting, e.g. 30m or 1h30"]
+    #[max_length = 16]
+    duration: String,
+    #[description = "Labels or ids of the calendars, separated by commas (defaults to this channel's)"]
+    #[max_length = 1000]
+    calendars: Option<String>,
+    #[description = "First day (defaults to today)"]
+    #[max_length = 64]
+    from: Option<String>,
+    #[description = "Last day (defaults to a week from the first day)"]
+    #[max_length = 64]
+    to: Option<String>,
+    #[description = "Working hours (defaults to 09:00-17:00)"]
+    #[max_length = 64]
+    hours: Option<String>,
+    #[description = "Look for slots on weekends too (defaults to no)"] weekends: Option<bool>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let subscriptions = {
+        let mut db = ctx.data().db.get().await?;
+        channel_calendars(&mut db, ctx.channel_id().get()).await?
+    };
+    let Some((guild_calendar, _)) = subscriptions.first() else {
+        return reply_ephemeral(ctx, Text::NoCalendar.get(locale).to_string()).await;
+    };
+    let timezone = timezone(guild_calendar)?;
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+
+    let Some(length) = parse_duration(&duration) else {
+        let message = Text::InvalidDuration.format(locale, &[("duration", &duration)]);
+        return reply_ephemeral(ctx, message).await;
+    };
+
+    let working_hours = match &hours {
+        Some(hours) => parse_hours(hours).filter(|(start, end)| start < end),
+        None => NaiveTime::from_hms_opt(9, 0, 0).zip(NaiveTime::from_hms_opt(17, 0, 0)),
+    };
+    let Some((start, end)) = working_hours else {
+        let hours = hours.unwrap_or_default();
+        let message = Text::InvalidHours.format(locale, &[("hours", &hours)]);
+        return reply_ephemeral(ctx, message).await;
+    };
+
+    let parse = |value: &Option<String>, default: NaiveDate| match value {
+        None => Ok(default),
+        Some(value) => parse_day(value, today)
+            .ok_or_else(|| Text::InvalidDate.format(locale, &[("date", value)])),
+    };
+    let first_day = match parse(&from, today) {
+        Ok(first_day) => first_day,
+        Err(message) => return reply_ephemeral(ctx, message).await,
+    };
+    let last_day = match parse(&to, first_day + TimeDelta::days(DEFAULT_DAYS - 1)) {
+        Ok(last_day) => last_day,
+        Err(message) => return reply_ephemeral(ctx, message).await,
+    };
+    if last_day < first_day {
+        return reply_ephemeral(ctx, Text::InvalidDateRange.get(locale).to_string()).await;
+    }
+    if (last_day - first_day).num_days() >= MAX_DAYS {
+        let message = Text::FreeBusyRangeTooLong.format(locale, &[("days", &MAX_DAYS)]);
+        return reply_ephemeral(ctx, message).await;
+    }
+
+    // Calendars of other channels or guilds can only be read by managers
+    let can_manage = ctx
+        .interaction
+        .member
+        .as_deref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild());
+    let mut busy_calendars = vec![];
+    let values = match &calendars {
+        Some(calendars) => calendars
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => subscriptions
+            .iter()
+            .map(|(_, calendar)| calendar.id.to_string())
+            .collect::<Vec<String>>(),
+    };
+    for value in values {
+        let busy_calendar = match find_calendar(&subscriptions, &value) {
+            Some((guild_calendar, calendar)) => {
+                calendar_source(calendar).map(|source| BusyCalendar {
+                    name: calendar_name(guild_calendar, calendar),
+                    source,
+                    calendar_id: calendar.googleId.clone(),
+                    auth: CalendarAuth::from_calendar(calendar),
+                })
+            }
+            None if can_manage && value.contains('@') => Some(BusyCalendar {
+                name: value.clone(),
+                source: CalendarEventSource::GoogleCalendar,
+                calendar_id: value.clone(),
+                auth: None,
+            }),
+            None => None,
+        };
+        match busy_calendar {
+            Some(busy_calendar) => busy_calendars.push(busy_calendar),
+            None => {
+                let message = Text::UnknownFreeBusyCalendar.format(locale, &[("calendar", &value)]);
+                return reply_ephemeral(ctx, message).await;
+            }
+        }
+    }
+
+    // Sources can be slow to answer
+    ctx.defer_ephemeral().await?;
+    let from = start_of_day(first_day, &timezone);
+    let until = start_of_day(last_day + TimeDelta::days(1), &timezone);
+    let mut busy: Vec<Period> = vec![];
+    for calendar in busy_calendars {
+        let periods = request(ctx, |resp| CalendarCommands::GetBusyPeriods {
+            source: calendar.source,
+            calendar_id: calendar.calendar_id,
+            auth: calendar.auth,
+            from,
+            until,
+            resp,
+        })
+        .await;
+        // Slots would be wrongly shown as free
+        match periods {
+            Ok(periods) => busy.extend(periods),
+            Err(e) => {
+                let message = Text::FreeBusyFailed
+                    .format(locale, &[("calendar", &calendar.name), ("error", &e)]);
+                return reply_ephemeral(ctx, message).await;
+            }
+        }
+    }
+
+    let working_hours = WorkingHours {
+        first_day,
+        last_day,
+        start,
+        end,
+        weekends: weekends.unwrap_or(false),
+        timezone,
+    };
+    let slots = working_hours.free_slots(busy, length, Utc::now());
+    let duration = duration.trim().replace('`', "");
+    if slots.is_empty() {
+        let message = Text::NoFreeSlots.format(
+            locale,
+            &[
+                ("duration", &duration),
+                ("from", &locale.format_date(first_day, SLOT_DAY_FORMAT)),
+                ("to", &locale.format_date(last_day, SLOT_DAY_FORMAT)),
+            ],
+        );
+        return reply_ephemeral(ctx, message).await;
+    }
+
+    let mut days: BTreeMap<NaiveDate, Vec<String>> = BTreeMap::new();
+    for (start, end) in slots {
+        let (start, end) = (start.with_timezone(&timezone), end.with_timezone(&timezone));
+        days.entry(start.date_naive()).or_default().push(format!(
+            "{}–{}",
+            start.format("%H:%M"),
+            end.format("%H:%M")
+        ));
+    }
+    let lines = days
+        .into_iter()
+        .map(|(day, slots)| {
+            format!(
+                "**{}** {}",
+                locale.format_date(day, SLOT_DAY_FORMAT),
+                slots.join(", ")
+            )
+        })
+        .collect::<Vec<String>>();
+
+    let title = Text::FreeSlots.format(
+        locale,
+        &[("duration", &duration), ("timezone", &timezone.name())],
+    );
+    let pages = lines
+        .chunks(PAGE_DAYS)
+        .map(|lines| {
+            serenity::CreateEmbed::new()
+                .title(&title)
+                .description(lines.join("\n"))
+        })
+        .collect();
+    Pages::new(pages, locale).ephemeral(true).send(ctx).await
+}
@@ -6,9 +6,174 @@ This is free software, and you are welcome to redistribute it
  */
 
 mod delete;
+mod event;
+mod filter;
+mod freebusy;
 mod new;
+mod query;
 mod set;
+mod status;
 
 pub use delete::delete;
+pub use event::event;
+pub use filter::filter;
 pub use new::new;
+pub use query::calendar;
 pub use set::set;
+
+use crate::events::CalendarCommands;
+use crate::i18n::Locale;
+use crate::models::{Calendar, GuildCalendar};
+use crate::schema::{calendars, guilds, guilds_calendars};
+use crate::types::CalendarEventSource;
+use crate::ApplicationContext;
+use anyhow::{anyhow, Result};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use google_calendar3::chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use google_calendar3::hyper::Uri;
+use poise::serenity_prelude as serenity;
+use tokio::sync::oneshot;
+
+/// Discord limits the name and value of a choice to 100 characters
+const MAX_CHOICE_LENGTH: usize = 100;
+
+fn calendar_source(calendar: &Calendar) -> Option<CalendarEventSource> {
+    calendar.source.parse().ok()
+}
+
+fn timezone(guild_calendar: &GuildCalendar) -> Result<Tz> {
+    guild_calendar
+        .timezone
+        .parse()
+        .map_err(|e| anyhow!("Failed to parse timezone: {}", e))
+}
+
+/// Sends a command to the calendar sources and waits for its response
+async fn request<T>(
+    ctx: ApplicationContext<'_>,
+    command: impl FnOnce(oneshot::Sender<Result<T>>) -> CalendarCommands,
+) -> Result<T> {
+    let (resp_tx, resp_rx) = oneshot::channel();
+    ctx.data()
+        .calendar_tx
+        .clone()
+        .send(command(resp_tx))
+        .await?;
+    resp_rx.await?
+}
+
+/// Start of a day in the timezone of the channel
+fn start_of_day(day: NaiveDate, timezone: &Tz) -> DateTime<Utc> {
+    let midnight = day.and_time(NaiveTime::MIN);
+    midnight
+        .and_local_timezone(*timezone)
+        .earliest()
+        .map_or_else(|| midnight.and_utc(), |date_time| date_time.to_utc())
+}
+
+/// Replies with a message only shown to the author of the command
+async fn reply_ephemeral(ctx: ApplicationContext<'_>, content: String) -> Result<()> {
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Returns the calendars displayed in a channel
+async fn channel_calendars(
+    db: &mut AsyncPgConnection,
+    channel_id: u64,
+) -> Result<Vec<(GuildCalendar, Calendar)>> {
+    Ok(guilds_calendars::table
+        .inner_join(calendars::table)
+        .filter(guilds_calendars::channelId.eq(channel_id.to_string()))
+        .select((GuildCalendar::as_select(), Calendar::as_select()))
+        .order(calendars::id)
+        .load(db)
+        .await?)
+}
+
+/// Language of the responses, the one of the guild or else the one of the user
+async fn command_locale(ctx: ApplicationContext<'_>) -> Locale {
+    let guild_locale = match (ctx.guild_id(), ctx.data().db.get().await) {
+        (Some(guild_id), Ok(mut db)) => guilds::table
+            .filter(guilds::discordId.eq(guild_id.get().to_string()))
+            .select(guilds::locale)
+            .first::<Option<String>>(&mut db)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|locale| locale.parse().ok()),
+        _ => None,
+    };
+
+    guild_locale
+        .or_else(|| Locale::from_discord(ctx.locale()?))
+        .unwrap_or_default()
+}
+
+/// Host of an url, without the path and query which may contain access tokens
+fn url_host(url: &str) -> Option<String> {
+    let uri: Uri = url.parse().ok()?;
+    uri.host().map(str::to_string)
+}
+
+/// Name of a calendar in a channel, its label followed by its identifier.
+/// Calendars fetched from an url are named by their label or their host
+fn calendar_name(guild_calendar: &GuildCalendar, calendar: &Calendar) -> String {
+    match calendar_source(calendar) {
+        Some(CalendarEventSource::GoogleCalendar) | None => match &guild_calendar.label {
+            Some(label) => format!("{} {}", label, calendar.googleId),
+            None => calendar.googleId.clone(),
+        },
+        Some(_) => guild_calendar
+            .label
+            .clone()
+            .or_else(|| url_host(&calendar.googleId))
+            .unwrap_or_else(|| calendar.source.clone()),
+    }
+}
+
+/// Finds a calendar of a channel from the value of `autocomplete_calendar`,
+/// its identifier or its label
+fn find_calendar<'a>(
+    calendars: &'a [(GuildCalendar, Calendar)],
+    value: &str,
+) -> Option<&'a (GuildCalendar, Calendar)> {
+    calendars.iter().find(|(guild_calendar, calendar)| {
+        calendar.id.to_string() == value
+            || calendar.googleId == value
+            || guild_calendar.label.as_deref() == Some(value)
+    })
+}
+
+/// Suggests the calendars of the current channel
+async fn autocomplete_calendar(
+    ctx: ApplicationContext<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    let Ok(mut db) = ctx.data().db.get().await else {
+        return vec![];
+    };
+    let Ok(calendars) = channel_calendars(&mut db, ctx.channel_id().get()).await else {
+        return vec![];
+    };
+
+    let partial = partial.to_lowercase();
+    calendars
+        .iter()
+        .map(|(guild_calendar, calendar)| (calendar_name(guild_calendar, calendar), calendar.id))
+        .filter(|(name, _)| name.to_lowercase().contains(&partial))
+        .map(|(name, id)| {
+            serenity::AutocompleteChoice::new(
+                name.chars().take(MAX_CHOICE_LENGTH).collect::<String>(),
+                id.to_string(),
+            )
+        })
+        .collect()
+}
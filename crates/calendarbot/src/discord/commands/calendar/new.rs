@@ -5,82 +5,174 @@ This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
 
+use super::{channel_calendars, command_locale};
 use crate::events::CalendarCommands;
+use crate::i18n::Text;
+use crate::models::Calendar;
 use crate::schema::calendars::dsl as calendars;
 use crate::schema::guilds::dsl as guilds;
 use crate::schema::guilds_calendars::dsl as guilds_calendars;
-use crate::types::TimezoneChoices;
+use crate::sources::secrets::encrypt_secret;
+use crate::sources::CalendarAuth;
+use crate::types::{autocomplete_timezone, parse_timezone, CalendarEventSource};
 use crate::ApplicationContext;
 use anyhow::Result;
 use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
+use diesel::PgExpressionMethods;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::error;
 use poise::serenity_prelude as serenity;
 use tokio::sync::oneshot;
 
-#[poise::command(slash_command, guild_only, category = "Google calendar")]
+#[derive(Debug, poise::Modal)]
+#[name = "Calendar credentials"]
+struct CredentialsModal {
+    #[name = "Username (empty for a bearer token)"]
+    username: Option<String>,
+    #[name = "Password or token"]
+    secret: String,
+}
+
+/// Finds a calendar already stored with the same source and credentials
+async fn find_calendar(
+    db: &mut AsyncPgConnection,
+    calendar_id: &str,
+    source: CalendarEventSource,
+    auth: Option<&CalendarAuth>,
+) -> Option<i32> {
+    // Secrets are encrypted with a random nonce, they are compared once decrypted
+    calendars::calendars
+        .filter(calendars::googleId.eq(calendar_id))
+        .filter(calendars::source.eq(source.to_string()))
+        .filter(calendars::authUsername.is_not_distinct_from(auth.and_then(|a| a.username())))
+        .select(Calendar::as_select())
+        .load(db)
+        .await
+        .ok()?
+        .into_iter()
+        .find(|calendar| CalendarAuth::from_calendar(calendar).as_ref() == auth)
+        .map(|calendar| calendar.id)
+}
+
+#[allow(clippy::too_many_arguments)]
+/// Only members able to manage channels can make the bot request an url
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Google calendar",
+    required_permissions = "MANAGE_CHANNELS"
+)]
 pub async fn new(
     ctx: ApplicationContext<'_>,
-    #[description = "Google Calendar ID"] calendar_id: String,
+    #[description = "Google Calendar ID, ICS or CalDAV URL"] calendar_id: String,
     #[channel_types("Text")]
     #[description = "Channel (defaults to the current channel)"]
     channel: Option<serenity::GuildChannel>,
-    #[description = "Timezone (defaults to UTC)"] timezone: TimezoneChoices,
+    #[description = "Timezone, e.g. Europe/Zurich (defaults to the timezone of the channel)"]
+    #[autocomplete = "autocomplete_timezone"]
+    timezone: Option<String>,
     #[description = "Number of days to display (defaults to 8)"] num_displayed_days: Option<u8>,
     #[description = "Skip weekends (default to false)"] skip_weekend: Option<bool>,
     #[description = "Show days if there are no events (defaults to false)"]
     show_if_no_events: Option<bool>,
+    #[description = "Calendar source (guessed by default)"] source: Option<CalendarEventSource>,
+    #[description = "Ask for credentials, for CalDAV or private feeds (defaults to false)"]
+    authenticate: Option<bool>,
+    #[description = "Label or emoji shown before the events of this calendar"]
+    #[max_length = 64]
+    label: Option<String>,
 ) -> Result<()> {
     let channel = match channel {
         Some(c) => c,
         None => ctx.guild_channel().await.unwrap(),
     };
 
-    let mut db = ctx.data().db.get().await?;
+    let locale = command_locale(ctx).await;
 
-    // Checking if the channel as calendar
-    let res = guilds_calendars::guilds_calendars
-        .filter(guilds_calendars::channelId.eq(channel.id.get().to_string()))
-        .select(guilds_calendars::channelId)
-        .first::<String>(&mut db)
-        .await;
+    let mut db = ctx.data().db.get().await?;
 
-    if res.is_ok() {
-        let _ = ctx.reply("This channel already has a calendar").await?;
-        return Ok(());
-    }
+    // Calendars added to a channel that already has some share its settings and message
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let channel_settings = subscriptions
+        .iter()
+        .map(|(guild_calendar, _)| guild_calendar.clone())
+        .next();
 
-    // Checking if the calendar is already present in db
-    let res = calendars::calendars
-        .filter(calendars::googleId.eq(&calendar_id))
-        .select(calendars::id)
-        .first::<i32>(&mut db)
-        .await;
+    let timezone = match timezone {
+        Some(timezone) => match parse_timezone(&timezone) {
+            Some(timezone) => timezone,
+            None => {
+                let _ = ctx
+                    .reply(Text::UnknownTimezone.format(locale, &[("timezone", &timezone)]))
+                    .await?;
+                return Ok(());
+            }
+        },
+        None => match channel_settings.as_ref().map(super::timezone) {
+            Some(timezone) => timezone?,
+            None => {
+                let _ = ctx.reply(Text::TimezoneRequired.get(locale)).await?;
+                return Ok(());
+            }
+        },
+    };
 
-    let check_if_valid = res.is_err();
+    let source = source.unwrap_or_else(|| CalendarEventSource::from_calendar_id(&calendar_id));
 
-    let db_cal_id = match res {
-        Ok(id) => Some(id),
-        Err(_) => None,
+    // Credentials are asked in a modal so they don't show up in the command options
+    let auth = if authenticate.unwrap_or(false) {
+        match poise::execute_modal::<_, _, CredentialsModal>(ctx, None, None).await? {
+            Some(credentials) => CalendarAuth::new(credentials.username, Some(credentials.secret)),
+            None => return Ok(()),
+        }
+    } else {
+        None
     };
 
-    // Checking if the calendar ID is valid and accessible from gcalendar
-    if check_if_valid {
+    // Checking if the calendar is already present in db
+    let mut db_cal_id = find_calendar(&mut db, &calendar_id, source, auth.as_ref()).await;
+    let mut calendar_id = calendar_id;
+
+    // Checking if the calendar ID is valid and accessible from its source
+    if db_cal_id.is_none() {
         let (resp_tx, resp_rx) = oneshot::channel();
-        let cmd = CalendarCommands::VerifyCalendarId {
+        let cmd = CalendarCommands::ResolveCalendarId {
+            source,
             calendar_id: calendar_id.clone(),
+            auth: auth.clone(),
             resp: resp_tx,
         };
 
         ctx.defer().await?;
 
-        ctx.data().gcalendar_tx.clone().send(cmd).await?;
+        ctx.data().calendar_tx.clone().send(cmd).await?;
 
-        let is_valid = resp_rx.await.unwrap_or(Ok(false)).unwrap_or(false);
-
-        if !is_valid {
-            let _ = ctx.reply("Invalid calendar ID").await?;
-            return Ok(());
+        match resp_rx.await {
+            Ok(Ok(resolved_id)) => calendar_id = resolved_id,
+            Ok(Err(e)) => {
+                let _ = ctx
+                    .reply(Text::InvalidCalendar.format(locale, &[("error", &e)]))
+                    .await?;
+                return Ok(());
+            }
+            Err(_) => {
+                let _ = ctx.reply(Text::InvalidCalendarId.get(locale)).await?;
+                return Ok(());
+            }
         }
+
+        // The resolved calendar might already be known
+        db_cal_id = find_calendar(&mut db, &calendar_id, source, auth.as_ref()).await;
+    }
+
+    if subscriptions
+        .iter()
+        .any(|(_, calendar)| Some(calendar.id) == db_cal_id)
+    {
+        let _ = ctx
+            .reply(Text::CalendarAlreadyDisplayed.get(locale))
+            .await?;
+        return Ok(());
     }
 
     // Checking if the guild is already present in db
@@ -107,8 +199,22 @@ pub async fn new(
     // Inserting calendar into db
     let db_cal_id = match db_cal_id {
         None => {
+            let secret = match auth.as_ref().map(|a| encrypt_secret(a.secret())) {
+                Some(Ok(secret)) => Some(secret),
+                Some(Err(e)) => {
+                    error!("Unable to encrypt calendar secret: {:?}", e);
+                    let _ = ctx.reply(Text::CredentialsUnavailable.get(locale)).await?;
+                    return Ok(());
+                }
+                None => None,
+            };
             diesel::insert_into(calendars::calendars)
-                .values(calendars::googleId.eq(&calendar_id))
+                .values((
+                    calendars::googleId.eq(&calendar_id),
+                    calendars::source.eq(source.to_string()),
+                    calendars::authUsername.eq(auth.as_ref().and_then(|a| a.username())),
+                    calendars::authSecret.eq(secret),
+                ))
                 .returning(calendars::id)
                 .get_result::<i32>(&mut db)
                 .await?
@@ -116,23 +222,92 @@ pub async fn new(
         Some(id) => id,
     };
 
+    let nb_displayed_days = num_displayed_days
+        .map(i32::from)
+        .or(channel_settings.as_ref().map(|c| c.nbDisplayedDays))
+        .unwrap_or(7);
+    let skip_weekend = skip_weekend
+        .or(channel_settings.as_ref().map(|c| c.skipWeekend))
+        .unwrap_or(false);
+    let skip_empty_days = show_if_no_events
+        .map(|show| !show)
+        .or(channel_settings.as_ref().map(|c| c.skipEmptyDays))
+        .unwrap_or(false);
+
     // Inserting guild_calendar into db
     diesel::insert_into(guilds_calendars::guilds_calendars)
         .values((
             guilds_calendars::guild_id.eq(guild_id),
             guilds_calendars::calendar_id.eq(db_cal_id),
             guilds_calendars::channelId.eq(channel.id.get().to_string()),
-            guilds_calendars::timezone.eq(timezone.to_normalized_string()),
-            guilds_calendars::nbDisplayedDays.eq(num_displayed_days.unwrap_or(7) as i32),
-            guilds_calendars::skipWeekend.eq(skip_weekend.unwrap_or(false)),
-            guilds_calendars::skipEmptyDays.eq(!show_if_no_events.unwrap_or(true)),
+            guilds_calendars::timezone.eq(timezone.name()),
+            guilds_calendars::nbDisplayedDays.eq(nb_displayed_days),
+            guilds_calendars::skipWeekend.eq(skip_weekend),
+            guilds_calendars::skipEmptyDays.eq(skip_empty_days),
+            guilds_calendars::label.eq(label.filter(|label| !label.trim().is_empty())),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::messageIds.eq(c.messageIds.clone())),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::pollInterval.eq(c.pollInterval)),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::failureWarning.eq(c.failureWarning)),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::displayMode.eq(c.displayMode.clone())),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::dynamicTimestamps.eq(c.dynamicTimestamps)),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::reminderMinutes.eq(c.reminderMinutes)),
+            guilds_calendars::reminderRoleId.eq(channel_settings
+                .as_ref()
+                .and_then(|c| c.reminderRoleId.clone())),
+            channel_settings
+                .as_ref()
+                .map(|c| guilds_calendars::changelog.eq(c.changelog)),
+            guilds_calendars::changelogThreadId.eq(channel_settings
+                .as_ref()
+                .and_then(|c| c.changelogThreadId.clone())),
+            // Empty for the first calendar of the channel, which uses the default template
+            guilds_calendars::embedTitle
+                .eq(channel_settings.as_ref().and_then(|c| c.embedTitle.clone())),
+            guilds_calendars::embedColor.eq(channel_settings.as_ref().and_then(|c| c.embedColor)),
+            guilds_calendars::dayFormat
+                .eq(channel_settings.as_ref().and_then(|c| c.dayFormat.clone())),
+            guilds_calendars::eventFormat.eq(channel_settings
+                .as_ref()
+                .and_then(|c| c.eventFormat.clone())),
+            guilds_calendars::embedFooter.eq(channel_settings
+                .as_ref()
+                .and_then(|c| c.embedFooter.clone())),
+        ))
+        .execute(&mut db)
+        .await?;
+
+    // The other calendars of the channel follow the new settings
+    if channel_settings.is_some() {
+        diesel::update(
+            guilds_calendars::guilds_calendars
+                .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
+        )
+        .set((
+            guilds_calendars::timezone.eq(timezone.name()),
+            guilds_calendars::nbDisplayedDays.eq(nb_displayed_days),
+            guilds_calendars::skipWeekend.eq(skip_weekend),
+            guilds_calendars::skipEmptyDays.eq(skip_empty_days),
+            guilds_calendars::forceUpdate.eq(true),
         ))
         .execute(&mut db)
         .await?;
+    }
 
     ctx.send(
         poise::CreateReply::default()
-            .content("Successfully added")
+            .content(Text::SuccessfullyAdded.get(locale))
             .reply(true)
             .ephemeral(true),
     )
@@ -0,0 +1,379 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use super::freebusy::freebusy;
+use super::status::status;
+use super::{
+    calendar_source, channel_calendars, command_locale, reply_ephemeral, request, start_of_day,
+    timezone,
+};
+use crate::discord::pagination::Pages;
+use crate::events::CalendarCommands;
+use crate::i18n::{Locale, Text};
+use crate::models::{Calendar, GuildCalendar};
+use crate::serenity;
+use crate::sources::update_calendar_event::channel_filters;
+use crate::sources::{events_between, CalendarAuth};
+use crate::types::{
+    escape_markdown, parse_day, truncate, CalendarEvent, CalendarEventSource, CalendarOptions,
+    EventFilter, EventTime,
+};
+use crate::ApplicationContext;
+use anyhow::Result;
+use chrono_tz::Tz;
+use google_calendar3::chrono::{NaiveDate, TimeDelta, Utc};
+use log::warn;
+use tokio::sync::oneshot;
+
+/// Number of events shown by `/calendar next` by default
+const DEFAULT_NEXT_EVENTS: usize = 5;
+
+/// How far ahead `/calendar next` looks for events
+const NEXT_DAYS: i64 = 60;
+
+/// Days shown by `/calendar week`, today included
+const WEEK_DAYS: i64 = 7;
+
+/// Events shown on each page of `/calendar search`
+const SEARCH_PAGE_SIZE: usize = 10;
+
+/// Days searched before and after today by default
+const SEARCH_DAYS: i64 = 365;
+
+/// Format of the days in the search results, e.g. "Fri 16 Oct 2026"
+const RESULT_DAY_FORMAT: &str = "%a %-d %b %Y";
+
+/// Longest summary or location shown in the search results
+const MAX_RESULT_LENGTH: usize = 100;
+
+type EventsResponder = oneshot::Sender<Result<Vec<CalendarEvent>>>;
+
+/// Events asked for
+enum Query {
+    /// Events of the days between both dates, included
+    Days(NaiveDate, NaiveDate),
+    /// Next events, including the ongoing ones
+    Next(usize),
+}
+
+/// Returns whether the event is not over at the start of the day
+fn ends_after(event: &CalendarEvent, day: NaiveDate, timezone: &Tz) -> bool {
+    match event.end.or(event.start) {
+        // The end date of all-day events is exclusive
+        Some(EventTime::Date(end)) => end > day,
+        Some(EventTime::DateTime(end)) => end > start_of_day(day, timezone),
+        None => false,
+    }
+}
+
+/// Fetches the events of every calendar of the current channel with the filters and labels
+/// of the channel, `command` asks the events of a calendar
+async fn channel_events(
+    ctx: ApplicationContext<'_>,
+    subscriptions: &[(GuildCalendar, Calendar)],
+    timezone: &Tz,
+    command: impl Fn(&Calendar, CalendarEventSource, EventsResponder) -> CalendarCommands,
+) -> Result<Vec<CalendarEvent>> {
+    let mut db = ctx.data().db.get().await?;
+    let filters = channel_filters(&mut db, &[ctx.channel_id().to_string()]).await?;
+
+    let mut events = vec![];
+    for (guild_calendar, calendar) in subscriptions {
+        let Some(source) = calendar_source(calendar) else {
+            warn!("Unknown source of calendar {}", calendar.id);
+            continue;
+        };
+        let calendar_events = match request(ctx, |resp| command(calendar, source, resp)).await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Unable to get the events of {}: {:?}", calendar.id, e);
+                continue;
+            }
+        };
+
+        let filters = filters
+            .get(&(guild_calendar.calendar_id, guild_calendar.channelId.clone()))
+            .map_or(&[][..], Vec::as_slice);
+        events.extend(
+            calendar_events
+                .into_iter()
+                .filter(|event| EventFilter::keep(filters, event))
+                .map(|event| CalendarEvent {
+                    calendar_label: guild_calendar.label.clone(),
+                    ..event
+                }),
+        );
+    }
+    events.sort_by_key(|event| event.start.map(|start| start.to_utc(timezone)));
+
+    Ok(events)
+}
+
+/// Replies privately with the events of the current channel, `query` is given today in the
+/// timezone of the channel and returns the message to show when the query is invalid
+async fn reply_events(
+    ctx: ApplicationContext<'_>,
+    query: impl FnOnce(NaiveDate, Locale) -> std::result::Result<Query, String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let subscriptions = {
+        let mut db = ctx.data().db.get().await?;
+        channel_calendars(&mut db, ctx.channel_id().get()).await?
+    };
+    let Some((guild_calendar, _)) = subscriptions.first() else {
+        return reply_ephemeral(ctx, Text::NoCalendar.get(locale).to_string()).await;
+    };
+
+    // Calendars of a channel share its settings
+    let options = CalendarOptions::try_from(guild_calendar.clone())?;
+    let timezone = options.timezone;
+    let now = Utc::now();
+    let today = now.with_timezone(&timezone).date_naive();
+    let query = match query(today, locale) {
+        Ok(query) => query,
+        Err(message) => return reply_ephemeral(ctx, message).await,
+    };
+
+    let until = match query {
+        Query::Days(_, last_day) => start_of_day(last_day + TimeDelta::days(1), &timezone),
+        Query::Next(_) => now + TimeDelta::days(NEXT_DAYS),
+    };
+
+    // Calendars that are not cached are fetched, which can be slow
+    ctx.defer_ephemeral().await?;
+    let events = channel_events(ctx, &subscriptions, &timezone, |calendar, source, resp| {
+        CalendarCommands::GetUpcomingEvents {
+            id: calendar.id,
+            source,
+            calendar_id: calendar.googleId.clone(),
+            auth: CalendarAuth::from_calendar(calendar),
+            until,
+            timezone,
+            resp,
+        }
+    })
+    .await?;
+
+    let (events, last_day) = match query {
+        Query::Days(first_day, last_day) => {
+            let events = events
+                .into_iter()
+                .filter(|event| ends_after(event, first_day, &timezone))
+                // All-day events are shown from the first day on
+                .map(|event| CalendarEvent {
+                    start: event.start.map(|start| match start {
+                        EventTime::Date(date) => EventTime::Date(date.max(first_day)),
+                        start => start,
+                    }),
+                    ..event
+                })
+                .collect::<Vec<_>>();
+            (events, last_day)
+        }
+        Query::Next(count) => {
+            let events = events.into_iter().take(count).collect::<Vec<_>>();
+            let last_day = events
+                .iter()
+                .filter_map(|event| event.start)
+                .map(|start| start.date_naive(&timezone))
+                .max()
+                .unwrap_or(today)
+                .max(today);
+            (events, last_day)
+        }
+    };
+
+    if events.is_empty() {
+        return reply_ephemeral(ctx, Text::NoEvents.get(locale).to_string()).await;
+    }
+
+    let options = CalendarOptions {
+        num_of_days: (last_day - today).num_days() as i32,
+        skip_weekend: false,
+        show_if_no_events: false,
+        locale,
+        ..options
+    };
+    for embeds in CalendarEvent::to_embed(events, options) {
+        let reply = embeds.into_iter().fold(
+            poise::CreateReply::default().ephemeral(true),
+            |reply, embed| reply.embed(embed),
+        );
+        ctx.send(reply).await?;
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Google calendar",
+    subcommands(
+        "today", "tomorrow", "next", "week", "day", "search", "freebusy", "status"
+    ),
+    subcommand_required
+)]
+pub async fn calendar(_: ApplicationContext<'_>) -> Result<()> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn today(ctx: ApplicationContext<'_>) -> Result<()> {
+    reply_events(ctx, |today, _| Ok(Query::Days(today, today))).await
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn tomorrow(ctx: ApplicationContext<'_>) -> Result<()> {
+    reply_events(ctx, |today, _| {
+        let tomorrow = today + TimeDelta::days(1);
+        Ok(Query::Days(tomorrow, tomorrow))
+    })
+    .await
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn next(
+    ctx: ApplicationContext<'_>,
+    #[description = "Number of events (defaults to 5)"]
+    #[min = 1]
+    #[max = 25]
+    count: Option<usize>,
+) -> Result<()> {
+    let count = count.unwrap_or(DEFAULT_NEXT_EVENTS);
+    reply_events(ctx, |_, _| Ok(Query::Next(count))).await
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn week(ctx: ApplicationContext<'_>) -> Result<()> {
+    reply_events(ctx, |today, _| {
+        Ok(Query::Days(today, today + TimeDelta::days(WEEK_DAYS - 1)))
+    })
+    .await
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn day(
+    ctx: ApplicationContext<'_>,
+    #[description = "Day, e.g. friday, 20.10 or 2026-10-20"]
+    #[max_length = 64]
+    date: String,
+) -> Result<()> {
+    reply_events(ctx, |today, locale| match parse_day(&date, today) {
+        Some(day) if day < today => Err(Text::PastDate.get(locale).to_string()),
+        Some(day) => Ok(Query::Days(day, day)),
+        None => Err(Text::InvalidDate.format(locale, &[("date", &date)])),
+    })
+    .await
+}
+
+/// One line of the search results, e.g. "**Fri 16 Oct 2026** Retro — Room B"
+fn result_line(event: &CalendarEvent, locale: Locale) -> String {
+    let when = match event.start {
+        Some(EventTime::Date(date)) => locale.format_date(date, RESULT_DAY_FORMAT),
+        Some(EventTime::DateTime(date_time)) => format!("<t:{}:f>", date_time.timestamp()),
+        None => String::new(),
+    };
+    let summary = escape_markdown(&truncate(&event.display_summary(), MAX_RESULT_LENGTH));
+    match &event.location {
+        Some(location) if !location.is_empty() => format!(
+            "**{}** {} — {}",
+            when,
+            summary,
+            escape_markdown(&truncate(location, MAX_RESULT_LENGTH))
+        ),
+        _ => format!("**{}** {}", when, summary),
+    }
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn search(
+    ctx: ApplicationContext<'_>,
+    #[description = "Text to find in the title, description or location"]
+    #[max_length = 100]
+    query: String,
+    #[description = "First day (defaults to a year ago)"]
+    #[max_length = 64]
+    from: Option<String>,
+    #[description = "Last day (defaults to a year from now)"]
+    #[max_length = 64]
+    to: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let subscriptions = {
+        let mut db = ctx.data().db.get().await?;
+        channel_calendars(&mut db, ctx.channel_id().get()).await?
+    };
+    let Some((guild_calendar, _)) = subscriptions.first() else {
+        return reply_ephemeral(ctx, Text::NoCalendar.get(locale).to_string()).await;
+    };
+
+    let timezone = timezone(guild_calendar)?;
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+    let parse = |value: &Option<String>, default: i64| match value {
+        None => Ok(today + TimeDelta::days(default)),
+        Some(value) => parse_day(value, today)
+            .ok_or_else(|| Text::InvalidDate.format(locale, &[("date", value)])),
+    };
+    let (first_day, last_day) = match (parse(&from, -SEARCH_DAYS), parse(&to, SEARCH_DAYS)) {
+        (Ok(first_day), Ok(last_day)) => (first_day, last_day),
+        (Err(message), _) | (_, Err(message)) => return reply_ephemeral(ctx, message).await,
+    };
+    if last_day < first_day {
+        return reply_ephemeral(ctx, Text::InvalidDateRange.get(locale).to_string()).await;
+    }
+
+    // Past events are never cached, the sources are always asked
+    ctx.defer_ephemeral().await?;
+    // Shown between backticks
+    let shown_query = query.replace('`', "");
+    let from = start_of_day(first_day, &timezone);
+    let until = start_of_day(last_day + TimeDelta::days(1), &timezone);
+    let events = channel_events(ctx, &subscriptions, &timezone, |calendar, source, resp| {
+        CalendarCommands::SearchEvents {
+            source,
+            calendar_id: calendar.googleId.clone(),
+            auth: CalendarAuth::from_calendar(calendar),
+            text: query.clone(),
+            from,
+            until,
+            resp,
+        }
+    })
+    .await?;
+    // Sources return the all-day events of the days around in any timezone
+    let events = events_between(events, from, until, &timezone);
+
+    if events.is_empty() {
+        let message = Text::NoSearchResults.format(
+            locale,
+            &[
+                ("query", &shown_query),
+                ("from", &locale.format_date(first_day, RESULT_DAY_FORMAT)),
+                ("to", &locale.format_date(last_day, RESULT_DAY_FORMAT)),
+            ],
+        );
+        return reply_ephemeral(ctx, message).await;
+    }
+
+    let title = truncate(
+        &Text::SearchResults.format(locale, &[("query", &shown_query)]),
+        256,
+    );
+    let pages = events
+        .chunks(SEARCH_PAGE_SIZE)
+        .map(|events| {
+            serenity::CreateEmbed::new().title(&title).description(
+                events
+                    .iter()
+                    .map(|event| result_line(event, locale))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+        })
+        .collect();
+    Pages::new(pages, locale).ephemeral(true).send(ctx).await
+}
@@ -4,16 +4,25 @@ Calendarbot  Copyright (C) 2023 Zbinden Yohan
 This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
+use super::{autocomplete_calendar, channel_calendars, command_locale, find_calendar};
+use crate::discord::scheduled_events::delete_scheduled_events;
+use crate::i18n::{Locale, Text};
 use crate::models::GuildCalendar;
+use crate::schema::guilds;
 use crate::schema::guilds_calendars as guilds_calendars_all;
 use crate::schema::guilds_calendars::dsl as guilds_calendars;
-use crate::types::TimezoneChoices;
+use crate::types::{
+    autocomplete_timezone, parse_color, parse_timezone, validate_day_format, validate_event_format,
+    validate_footer, validate_title, DisplayMode,
+};
 use crate::ApplicationContext;
 use anyhow::{anyhow, Result};
 use diesel::prelude::*;
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use log::trace;
+use poise::serenity_prelude as serenity;
 
+#[allow(clippy::too_many_arguments)]
 async fn update_settings(
     db: &mut AsyncPgConnection,
     channel_id: u64,
@@ -21,6 +30,9 @@ async fn update_settings(
     nb_displayed_days: Option<i32>,
     skip_weekends: Option<bool>,
     skip_empty_days: Option<bool>,
+    poll_interval: Option<i32>,
+    display_mode: Option<DisplayMode>,
+    dynamic_timestamps: Option<bool>,
 ) -> Result<()> {
     let default_values = guilds_calendars::guilds_calendars
         .filter(guilds_calendars::channelId.eq(channel_id.to_string()))
@@ -45,6 +57,18 @@ async fn update_settings(
         values.skipEmptyDays = skip_empty_days;
     }
 
+    if let Some(poll_interval) = poll_interval {
+        values.pollInterval = poll_interval;
+    }
+
+    if let Some(display_mode) = display_mode {
+        values.displayMode = display_mode.to_string();
+    }
+
+    if let Some(dynamic_timestamps) = dynamic_timestamps {
+        values.dynamicTimestamps = dynamic_timestamps;
+    }
+
     match diesel::update(
         guilds_calendars::guilds_calendars
             .filter(guilds_calendars::channelId.eq(channel_id.to_string())),
@@ -54,6 +78,9 @@ async fn update_settings(
         guilds_calendars::nbDisplayedDays.eq(values.nbDisplayedDays),
         guilds_calendars::skipWeekend.eq(values.skipWeekend),
         guilds_calendars::skipEmptyDays.eq(values.skipEmptyDays),
+        guilds_calendars::pollInterval.eq(values.pollInterval),
+        guilds_calendars::displayMode.eq(&values.displayMode),
+        guilds_calendars::dynamicTimestamps.eq(values.dynamicTimestamps),
         guilds_calendars::forceUpdate.eq(true),
     ))
     .execute(db)
@@ -94,7 +121,7 @@ where
     match result {
         Ok(value) => Ok(value),
         Err(diesel::result::Error::NotFound) => {
-            ctx.reply("This channel doesn't have a calendar")
+            ctx.reply(Text::NoCalendar.get(command_locale(*ctx).await))
                 .await
                 .map_err(|e| anyhow!(e))?; // Handle the reply error
             Err(diesel::result::Error::NotFound.into())
@@ -107,7 +134,23 @@ where
     slash_command,
     guild_only,
     category = "Google calendar",
-    subcommands("timezone", "nb_displayed_days", "skip_weekend", "show_if_no_events"),
+    subcommands(
+        "timezone",
+        "nb_displayed_days",
+        "skip_weekend",
+        "show_if_no_events",
+        "poll_interval",
+        "failure_warning",
+        "label",
+        "display_mode",
+        "dynamic_timestamps",
+        "template",
+        "language",
+        "reminder",
+        "changelog",
+        "scheduled_events",
+        "event_role"
+    ),
     subcommand_required
 )]
 pub async fn set(_: ApplicationContext<'_>) -> Result<()> {
@@ -117,10 +160,19 @@ pub async fn set(_: ApplicationContext<'_>) -> Result<()> {
 #[poise::command(slash_command, guild_only, category = "Google calendar")]
 pub async fn timezone(
     ctx: ApplicationContext<'_>,
-    #[description = "Timezone (defaults to UTC)"] timezone: TimezoneChoices,
+    #[description = "Timezone, e.g. Europe/Zurich"]
+    #[autocomplete = "autocomplete_timezone"]
+    timezone: String,
 ) -> Result<()> {
+    let locale = command_locale(ctx).await;
     let channel = ctx.guild_channel().await.unwrap();
-    let timezone = timezone.to_normalized_string();
+    let Some(timezone) = parse_timezone(&timezone) else {
+        let _ = ctx
+            .reply(Text::UnknownTimezone.format(locale, &[("timezone", &timezone)]))
+            .await?;
+        return Ok(());
+    };
+    let timezone = timezone.name().to_string();
     let mut db = ctx.data().db.get().await?;
 
     let old_timezone: String = get_settings(
@@ -131,7 +183,11 @@ pub async fn timezone(
     )
     .await?;
     if old_timezone == timezone {
-        let _ = ctx.reply("Timezone already set to this value").await?;
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet.format(locale, &[("setting", &Text::Timezone.get(locale))]),
+            )
+            .await?;
         return Ok(());
     }
 
@@ -142,11 +198,27 @@ pub async fn timezone(
         channel.id.get()
     );
 
-    let res = update_settings(&mut db, channel.id.get(), Some(timezone), None, None, None).await;
+    let res = update_settings(
+        &mut db,
+        channel.id.get(),
+        Some(timezone),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
 
     match res {
         Ok(_) => {
-            let _ = ctx.reply("Timezone updated").await?;
+            let _ = ctx
+                .reply(
+                    Text::SettingUpdated
+                        .format(locale, &[("setting", &Text::Timezone.get(locale))]),
+                )
+                .await?;
             Ok(())
         }
         Err(e) => Err(e),
@@ -158,6 +230,7 @@ pub async fn nb_displayed_days(
     ctx: ApplicationContext<'_>,
     #[description = "Days (defaults to UTC)"] days: u8,
 ) -> Result<()> {
+    let locale = command_locale(ctx).await;
     let channel = ctx.guild_channel().await;
     let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
     let mut db = ctx.data().db.get().await?;
@@ -175,7 +248,10 @@ pub async fn nb_displayed_days(
 
     if old_nb_displayed_days == days {
         let _ = ctx
-            .reply("Number of displayed days already set to this value")
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::DisplayedDays.get(locale))]),
+            )
             .await?;
         return Ok(());
     }
@@ -194,10 +270,17 @@ pub async fn nb_displayed_days(
         Some(days as i32),
         None,
         None,
+        None,
+        None,
+        None,
     )
     .await
     .map_err(|e| anyhow!(e))?;
-    let _ = ctx.reply("Number of displayed days updated").await?;
+    let _ = ctx
+        .reply(
+            Text::SettingUpdated.format(locale, &[("setting", &Text::DisplayedDays.get(locale))]),
+        )
+        .await?;
     Ok(())
 }
 
@@ -206,6 +289,7 @@ pub async fn skip_weekend(
     ctx: ApplicationContext<'_>,
     #[description = "Skip weekends"] skip_weekend: bool,
 ) -> Result<()> {
+    let locale = command_locale(ctx).await;
     let channel = ctx.guild_channel().await;
     let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
     let mut db = ctx.data().db.get().await?;
@@ -219,7 +303,12 @@ pub async fn skip_weekend(
     .await?;
 
     if old_skip_weekend == skip_weekend {
-        let _ = ctx.reply("Skip weekends already set to this value").await?;
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::SkipWeekends.get(locale))]),
+            )
+            .await?;
         return Ok(());
     }
 
@@ -237,10 +326,15 @@ pub async fn skip_weekend(
         None,
         Some(skip_weekend),
         None,
+        None,
+        None,
+        None,
     )
     .await
     .map_err(|e| anyhow!(e))?;
-    let _ = ctx.reply("Skip weekends updated").await?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::SkipWeekends.get(locale))]))
+        .await?;
     Ok(())
 }
 
@@ -249,6 +343,7 @@ pub async fn show_if_no_events(
     ctx: ApplicationContext<'_>,
     #[description = "Show days if there are no events"] show_if_no_events: bool,
 ) -> Result<()> {
+    let locale = command_locale(ctx).await;
     let channel = ctx.guild_channel().await;
     let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
     let mut db = ctx.data().db.get().await?;
@@ -263,7 +358,10 @@ pub async fn show_if_no_events(
 
     if old_skip_empty_days != show_if_no_events {
         let _ = ctx
-            .reply("Show if no events already set to this value")
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::ShowIfNoEvents.get(locale))]),
+            )
             .await?;
         return Ok(());
     }
@@ -282,9 +380,729 @@ pub async fn show_if_no_events(
         None,
         None,
         Some(!show_if_no_events),
+        None,
+        None,
+        None,
+    )
+    .await
+    .map_err(|e| anyhow!(e))?;
+    let _ = ctx
+        .reply(
+            Text::SettingUpdated.format(locale, &[("setting", &Text::ShowIfNoEvents.get(locale))]),
+        )
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn poll_interval(
+    ctx: ApplicationContext<'_>,
+    #[description = "Minutes between two refreshes of the calendar"] minutes: u16,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let min_poll_interval = ctx.data().min_poll_interval;
+    if i32::from(minutes) < min_poll_interval {
+        let _ = ctx
+            .reply(Text::PollIntervalTooShort.format(locale, &[("minutes", &min_poll_interval)]))
+            .await?;
+        return Ok(());
+    }
+
+    let old_poll_interval: i32 = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::pollInterval,
+    )
+    .await?;
+
+    if old_poll_interval == i32::from(minutes) {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::PollInterval.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing poll interval from {:?} to {:?} for channel {:?}",
+        old_poll_interval,
+        minutes,
+        channel.id.get()
+    );
+
+    update_settings(
+        &mut db,
+        channel.id.get(),
+        None,
+        None,
+        None,
+        None,
+        Some(i32::from(minutes)),
+        None,
+        None,
+    )
+    .await
+    .map_err(|e| anyhow!(e))?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::PollInterval.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn failure_warning(
+    ctx: ApplicationContext<'_>,
+    #[description = "Consecutive failed updates before warning the channel (0 to disable)"]
+    failures: u16,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let old_failure_warning: i32 = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::failureWarning,
+    )
+    .await?;
+
+    if old_failure_warning == i32::from(failures) {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::FailureWarning.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing failure warning from {:?} to {:?} for channel {:?}",
+        old_failure_warning,
+        failures,
+        channel.id.get()
+    );
+
+    // Warnings don't change the calendar message, no update is forced
+    diesel::update(
+        guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
+    )
+    .set(guilds_calendars::failureWarning.eq(i32::from(failures)))
+    .execute(&mut db)
+    .await?;
+    let _ = ctx
+        .reply(
+            Text::SettingUpdated.format(locale, &[("setting", &Text::FailureWarning.get(locale))]),
+        )
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn label(
+    ctx: ApplicationContext<'_>,
+    #[description = "Calendar of this channel"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: String,
+    #[description = "Label or emoji shown before its events (removed if empty)"]
+    #[max_length = 64]
+    label: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let Some((guild_calendar, _)) = find_calendar(&subscriptions, &calendar) else {
+        let _ = ctx.reply(Text::CalendarNotDisplayed.get(locale)).await?;
+        return Ok(());
+    };
+
+    let label = label.filter(|label| !label.trim().is_empty());
+    if guild_calendar.label == label {
+        let _ = ctx
+            .reply(Text::SettingAlreadySet.format(locale, &[("setting", &Text::Label.get(locale))]))
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing label from {:?} to {:?} for calendar {:?} in channel {:?}",
+        guild_calendar.label,
+        label,
+        guild_calendar.calendar_id,
+        channel.id.get()
+    );
+
+    diesel::update(
+        guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::channelId.eq(channel.id.get().to_string()))
+            .filter(guilds_calendars::calendar_id.eq(guild_calendar.calendar_id)),
+    )
+    .set((
+        guilds_calendars::label.eq(label),
+        guilds_calendars::forceUpdate.eq(true),
+    ))
+    .execute(&mut db)
+    .await?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::Label.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn display_mode(
+    ctx: ApplicationContext<'_>,
+    #[description = "Display mode"] display_mode: DisplayMode,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let old_display_mode: String = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::displayMode,
+    )
+    .await?;
+
+    if old_display_mode == display_mode.to_string() {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::DisplayMode.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing display mode from {:?} to {:?} for channel {:?}",
+        old_display_mode,
+        display_mode,
+        channel.id.get()
+    );
+
+    update_settings(
+        &mut db,
+        channel.id.get(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(display_mode),
+        None,
     )
     .await
     .map_err(|e| anyhow!(e))?;
-    let _ = ctx.reply("Show if no events updated").await?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::DisplayMode.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn dynamic_timestamps(
+    ctx: ApplicationContext<'_>,
+    #[description = "Show event times in the timezone of each member"] dynamic_timestamps: bool,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let old_dynamic_timestamps: bool = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::dynamicTimestamps,
+    )
+    .await?;
+
+    if old_dynamic_timestamps == dynamic_timestamps {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::DynamicTimestamps.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing dynamic timestamps from {:?} to {:?} for channel {:?}",
+        old_dynamic_timestamps,
+        dynamic_timestamps,
+        channel.id.get()
+    );
+
+    update_settings(
+        &mut db,
+        channel.id.get(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(dynamic_timestamps),
+    )
+    .await
+    .map_err(|e| anyhow!(e))?;
+    let _ = ctx
+        .reply(
+            Text::SettingUpdated
+                .format(locale, &[("setting", &Text::DynamicTimestamps.get(locale))]),
+        )
+        .await?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn template(
+    ctx: ApplicationContext<'_>,
+    #[description = "Title of the embed (defaults to Events)"]
+    #[max_length = 256]
+    title: Option<String>,
+    #[description = "Colour of the embed, e.g. #b74700"] color: Option<String>,
+    #[description = "Day header format, e.g. **%A** - %e %B"]
+    #[max_length = 64]
+    day_format: Option<String>,
+    #[description = "Event line, with {time} {start} {end} {summary} {location} {description} {link}"]
+    #[max_length = 256]
+    event_format: Option<String>,
+    #[description = "Footer of the embed"]
+    #[max_length = 2048]
+    footer: Option<String>,
+    #[description = "Go back to the default template before applying the other options"]
+    reset: Option<bool>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    let Some((current, _)) = <[_]>::first(&subscriptions) else {
+        let _ = ctx.reply(Text::NoCalendar.get(locale)).await?;
+        return Ok(());
+    };
+
+    let validated = (|| -> Result<_> {
+        Ok((
+            title.map(|t| validate_title(&t, locale)).transpose()?,
+            color.map(|c| parse_color(&c, locale)).transpose()?,
+            day_format
+                .map(|f| validate_day_format(&f, locale))
+                .transpose()?,
+            event_format
+                .map(|f| validate_event_format(&f, locale))
+                .transpose()?,
+            footer.map(|f| validate_footer(&f, locale)).transpose()?,
+        ))
+    })();
+    let (title, color, day_format, event_format, footer) = match validated {
+        Ok(values) => values,
+        Err(e) => {
+            let _ = ctx.reply(e.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let reset = reset.unwrap_or(false);
+    let keep = |value: &Option<String>| if reset { None } else { value.clone() };
+    let title = title.or(keep(&current.embedTitle));
+    let color = color
+        .map(|color| color as i32)
+        .or(if reset { None } else { current.embedColor });
+    let day_format = day_format.or(keep(&current.dayFormat));
+    let event_format = event_format.or(keep(&current.eventFormat));
+    let footer = footer.or(keep(&current.embedFooter));
+
+    trace!(
+        "Changing template to {:?} {:?} {:?} {:?} {:?} for channel {:?}",
+        title,
+        color,
+        day_format,
+        event_format,
+        footer,
+        channel.id.get()
+    );
+
+    diesel::update(
+        guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
+    )
+    .set((
+        guilds_calendars::embedTitle.eq(title),
+        guilds_calendars::embedColor.eq(color),
+        guilds_calendars::dayFormat.eq(day_format),
+        guilds_calendars::eventFormat.eq(event_format),
+        guilds_calendars::embedFooter.eq(footer),
+        guilds_calendars::forceUpdate.eq(true),
+    ))
+    .execute(&mut db)
+    .await?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::Template.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn language(
+    ctx: ApplicationContext<'_>,
+    #[description = "Language of the server (defaults to the language of each user)"]
+    language: Option<Locale>,
+) -> Result<()> {
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow!("Guild not found"))?
+        .get()
+        .to_string();
+    let language = language.map(|language| language.to_string());
+    let mut db = ctx.data().db.get().await?;
+
+    trace!(
+        "Changing language to {:?} for guild {:?}",
+        language,
+        guild_id
+    );
+
+    let updated = diesel::update(guilds::table.filter(guilds::discordId.eq(&guild_id)))
+        .set(guilds::locale.eq(&language))
+        .execute(&mut db)
+        .await?;
+    if updated == 0 {
+        diesel::insert_into(guilds::table)
+            .values((
+                guilds::discordId.eq(&guild_id),
+                guilds::locale.eq(&language),
+            ))
+            .execute(&mut db)
+            .await?;
+    }
+
+    // The calendars of the guild are displayed again in the new language
+    diesel::update(
+        guilds_calendars::guilds_calendars.filter(
+            guilds_calendars::guild_id.eq_any(
+                guilds::table
+                    .filter(guilds::discordId.eq(&guild_id))
+                    .select(guilds::id),
+            ),
+        ),
+    )
+    .set(guilds_calendars::forceUpdate.eq(true))
+    .execute(&mut db)
+    .await?;
+
+    let locale = command_locale(ctx).await;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::Language.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn reminder(
+    ctx: ApplicationContext<'_>,
+    #[description = "Minutes before the start of events to post a reminder (0 to disable)"]
+    #[max = 10080]
+    minutes: u16,
+    #[description = "Role pinged by the reminders"] role: Option<serenity::Role>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let old_minutes: i32 = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::reminderMinutes,
+    )
+    .await?;
+    let old_role_id: Option<String> = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::reminderRoleId,
+    )
+    .await?;
+
+    let role_id = role
+        .filter(|_| minutes > 0)
+        .map(|role| role.id.get().to_string());
+    if old_minutes == i32::from(minutes) && old_role_id == role_id {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::Reminders.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing reminders from {:?} minutes ({:?}) to {:?} minutes ({:?}) for channel {:?}",
+        old_minutes,
+        old_role_id,
+        minutes,
+        role_id,
+        channel.id.get()
+    );
+
+    // The update schedules the reminders right away
+    diesel::update(
+        guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
+    )
+    .set((
+        guilds_calendars::reminderMinutes.eq(i32::from(minutes)),
+        guilds_calendars::reminderRoleId.eq(role_id),
+        guilds_calendars::forceUpdate.eq(true),
+    ))
+    .execute(&mut db)
+    .await?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::Reminders.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn changelog(
+    ctx: ApplicationContext<'_>,
+    #[description = "Post the added, moved and cancelled events"] changelog: bool,
+    #[description = "Thread to post the changes in (defaults to this channel)"]
+    #[channel_types("PublicThread", "PrivateThread")]
+    thread: Option<serenity::GuildChannel>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let old_changelog: bool = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::changelog,
+    )
+    .await?;
+    let old_thread_id: Option<String> = get_settings(
+        &mut db,
+        &ctx,
+        channel.id.get().to_string(),
+        guilds_calendars::changelogThreadId,
+    )
+    .await?;
+
+    let thread_id = thread
+        .filter(|_| changelog)
+        .map(|thread| thread.id.get().to_string());
+    if old_changelog == changelog && old_thread_id == thread_id {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::Changelog.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing changelog from {:?} ({:?}) to {:?} ({:?}) for channel {:?}",
+        old_changelog,
+        old_thread_id,
+        changelog,
+        thread_id,
+        channel.id.get()
+    );
+
+    // The changelog doesn't change the calendar message, no update is forced
+    diesel::update(
+        guilds_calendars::guilds_calendars
+            .filter(guilds_calendars::channelId.eq(channel.id.get().to_string())),
+    )
+    .set((
+        guilds_calendars::changelog.eq(changelog),
+        guilds_calendars::changelogThreadId.eq(thread_id),
+    ))
+    .execute(&mut db)
+    .await?;
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::Changelog.get(locale))]))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn scheduled_events(
+    ctx: ApplicationContext<'_>,
+    #[description = "Mirror the displayed events as Discord events"] scheduled_events: bool,
+    #[description = "Calendar of this channel (defaults to every calendar of the channel)"]
+    #[autocomplete = "autocomplete_calendar"]
+    calendar: Option<String>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let guild_id = ctx.guild_id().ok_or_else(|| anyhow!("Guild not found"))?;
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+    let mut db = ctx.data().db.get().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    if subscriptions.is_empty() {
+        let _ = ctx.reply(Text::NoCalendar.get(locale)).await?;
+        return Ok(());
+    }
+    let selected = match calendar {
+        None => subscriptions.iter().collect::<Vec<_>>(),
+        Some(value) => match find_calendar(&subscriptions, &value) {
+            Some(calendar) => vec![calendar],
+            None => {
+                let _ = ctx.reply(Text::CalendarNotDisplayed.get(locale)).await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let changed: Vec<&GuildCalendar> = selected
+        .into_iter()
+        .map(|(guild_calendar, _)| guild_calendar)
+        .filter(|guild_calendar| guild_calendar.scheduledEvents != scheduled_events)
+        .collect();
+    if changed.is_empty() {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::ScheduledEvents.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    // Replying first, deleting many Discord events takes longer than an interaction may wait
+    ctx.defer().await?;
+    for guild_calendar in changed {
+        trace!(
+            "Changing scheduled events to {:?} for calendar {:?} in channel {:?}",
+            scheduled_events,
+            guild_calendar.calendar_id,
+            channel.id.get()
+        );
+
+        // The events are created by the next update
+        diesel::update(
+            guilds_calendars::guilds_calendars
+                .filter(guilds_calendars::channelId.eq(channel.id.get().to_string()))
+                .filter(guilds_calendars::calendar_id.eq(guild_calendar.calendar_id)),
+        )
+        .set((
+            guilds_calendars::scheduledEvents.eq(scheduled_events),
+            guilds_calendars::forceUpdate.eq(true),
+        ))
+        .execute(&mut db)
+        .await?;
+
+        if !scheduled_events {
+            delete_scheduled_events(
+                &mut db,
+                ctx.http(),
+                guild_id.get(),
+                guild_calendar.calendar_id,
+                &guild_calendar.channelId,
+            )
+            .await?;
+        }
+    }
+
+    let _ = ctx
+        .reply(
+            Text::SettingUpdated.format(locale, &[("setting", &Text::ScheduledEvents.get(locale))]),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Only members allowed to manage the server choose who can write to the calendars
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Google calendar",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn event_role(
+    ctx: ApplicationContext<'_>,
+    #[description = "Role allowed to create, edit and cancel events (defaults to the members able to manage the server)"]
+    role: Option<serenity::Role>,
+) -> Result<()> {
+    let locale = command_locale(ctx).await;
+    let guild_id = ctx
+        .guild_id()
+        .ok_or_else(|| anyhow!("Guild not found"))?
+        .get()
+        .to_string();
+    let role_id = role.map(|role| role.id.get().to_string());
+    let mut db = ctx.data().db.get().await?;
+
+    let old_role_id = guilds::table
+        .filter(guilds::discordId.eq(&guild_id))
+        .select(guilds::eventRoleId)
+        .first::<Option<String>>(&mut db)
+        .await
+        .optional()?;
+    if old_role_id.as_ref() == Some(&role_id) {
+        let _ = ctx
+            .reply(
+                Text::SettingAlreadySet
+                    .format(locale, &[("setting", &Text::EventRole.get(locale))]),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    trace!(
+        "Changing event role from {:?} to {:?} for guild {:?}",
+        old_role_id.flatten(),
+        role_id,
+        guild_id
+    );
+
+    let updated = diesel::update(guilds::table.filter(guilds::discordId.eq(&guild_id)))
+        .set(guilds::eventRoleId.eq(&role_id))
+        .execute(&mut db)
+        .await?;
+    if updated == 0 {
+        diesel::insert_into(guilds::table)
+            .values((
+                guilds::discordId.eq(&guild_id),
+                guilds::eventRoleId.eq(&role_id),
+            ))
+            .execute(&mut db)
+            .await?;
+    }
+
+    let _ = ctx
+        .reply(Text::SettingUpdated.format(locale, &[("setting", &Text::EventRole.get(locale))]))
+        .await?;
     Ok(())
 }
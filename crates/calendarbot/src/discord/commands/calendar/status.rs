@@ -0,0 +1,154 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use super::{calendar_name, channel_calendars, command_locale, url_host};
+use crate::events::CalendarCommands;
+use crate::i18n::Text;
+use crate::ApplicationContext;
+use anyhow::{anyhow, Result};
+use google_calendar3::chrono::{DateTime, Utc};
+use log::warn;
+use poise::serenity_prelude as serenity;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+use tokio::sync::oneshot;
+
+/// Longest error shown, embed fields are limited to 1024 characters
+const MAX_ERROR_LENGTH: usize = 600;
+
+/// Embed field names are limited to 256 characters
+const MAX_NAME_LENGTH: usize = 256;
+
+/// Urls of calendar feeds may contain access tokens
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[a-z]+://[^\s]+").unwrap());
+
+/// Formats a date as a discord timestamp, shown in the timezone of each user
+fn timestamp(date_time: DateTime<Utc>) -> String {
+    format!("<t:{0}:f> (<t:{0}:R>)", date_time.timestamp())
+}
+
+/// Replaces the urls of an error by their host
+fn redact_urls(error: &str) -> String {
+    URL_REGEX
+        .replace_all(error, |captures: &Captures| {
+            url_host(&captures[0]).unwrap_or_default()
+        })
+        .into_owned()
+}
+
+/// Only shows the calendars of the current channel, to keep them private
+#[poise::command(slash_command, guild_only, category = "Google calendar")]
+pub async fn status(ctx: ApplicationContext<'_>) -> Result<()> {
+    let channel = ctx.guild_channel().await;
+    let channel = channel.ok_or_else(|| anyhow!("Channel not found"))?;
+
+    let locale = command_locale(ctx).await;
+    let mut db = ctx.data().db.get().await?;
+
+    let subscriptions = channel_calendars(&mut db, channel.id.get()).await?;
+    if subscriptions.is_empty() {
+        let _ = ctx.reply(Text::NoCalendar.get(locale)).await?;
+        return Ok(());
+    }
+
+    let mut embed = serenity::CreateEmbed::new().title(Text::CalendarStatus.get(locale));
+
+    for (guild_calendar, calendar) in &subscriptions {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        ctx.data()
+            .calendar_tx
+            .clone()
+            .send(CalendarCommands::GetPollStatus {
+                calendar_id: calendar.id,
+                resp: resp_tx,
+            })
+            .await?;
+        let poll_status = match resp_rx.await {
+            Ok(Ok(status)) => status,
+            Ok(Err(e)) => {
+                warn!("Unable to get poll status: {:?}", e);
+                None
+            }
+            Err(e) => {
+                warn!("Unable to get poll status: {:?}", e);
+                None
+            }
+        };
+
+        let mut lines = vec![
+            format!("**{}:** {}", Text::Source.get(locale), calendar.source),
+            format!(
+                "**{}:** {}",
+                Text::LastSync.get(locale),
+                calendar
+                    .lastSync
+                    .map_or(Text::Never.get(locale).to_string(), timestamp)
+            ),
+            format!(
+                "**{}:** {}",
+                Text::NextPoll.get(locale),
+                poll_status.map_or(Text::NotScheduled.get(locale).to_string(), |status| {
+                    timestamp(status.next_poll)
+                })
+            ),
+            format!(
+                "**{}:** {}",
+                Text::DisplayedEvents.get(locale),
+                poll_status
+                    .and_then(|status| status.event_count)
+                    .map_or(Text::Unknown.get(locale).to_string(), |count| {
+                        count.to_string()
+                    })
+            ),
+            format!(
+                "**{}:** {}",
+                Text::ConsecutiveFailures.get(locale),
+                calendar.failureCount
+            ),
+        ];
+        if let Some(error) = &calendar.lastError {
+            let error: String = redact_urls(error).chars().take(MAX_ERROR_LENGTH).collect();
+            lines.push(format!("**{}:** {}", Text::LastError.get(locale), error));
+        }
+
+        let name: String = calendar_name(guild_calendar, calendar)
+            .chars()
+            .take(MAX_NAME_LENGTH)
+            .collect();
+        embed = embed.field(name, lines.join("\n"), false);
+    }
+
+    // Settings are shared by the calendars of the channel
+    let failure_warning = subscriptions
+        .iter()
+        .map(|(guild_calendar, _)| guild_calendar.failureWarning)
+        .next()
+        .unwrap_or_default();
+    embed = embed.description(match failure_warning {
+        0 => Text::FailureWarningsDisabled.get(locale).to_string(),
+        n => Text::FailureWarningsAfter.format(locale, &[("failures", &n)]),
+    });
+
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_redacted() {
+        assert_eq!(
+            redact_urls("Unable to fetch https://cal.example.com/feed.ics?token=abc"),
+            "Unable to fetch cal.example.com"
+        );
+        assert_eq!(redact_urls("Too many redirects"), "Too many redirects");
+    }
+}
@@ -11,7 +11,10 @@ use poise::serenity_prelude as serenity;
 use tokio::sync::mpsc::Receiver;
 
 use crate::discord::LocalCache;
-use crate::{discord::commands, discord::Discord, events::UpdateCalendarEvent, types};
+use crate::events::{
+    CalendarWarningEvent, ChangelogEvent, ReminderEvent, ScheduledEventsSync, UpdateCalendarEvent,
+};
+use crate::{discord::commands, discord::Discord, types};
 
 async fn on_error(error: poise::FrameworkError<'_, types::GlobalData, Error>) {
     // This is our custom error handler
@@ -34,6 +37,10 @@ impl Discord {
     pub async fn init(
         &mut self,
         calendar_rx: Receiver<UpdateCalendarEvent>,
+        calendar_warning_rx: Receiver<CalendarWarningEvent>,
+        reminder_rx: Receiver<ReminderEvent>,
+        changelog_rx: Receiver<ChangelogEvent>,
+        scheduled_events_rx: Receiver<ScheduledEventsSync>,
         data: types::GlobalData,
     ) -> serenity::Client {
         let cache_clone = self.cache.clone();
@@ -47,6 +54,9 @@ impl Discord {
                     commands::calendar::new(),
                     commands::calendar::delete(),
                     commands::calendar::set(),
+                    commands::calendar::filter(),
+                    commands::calendar::event(),
+                    commands::calendar::calendar(),
                 ],
                 on_error: |error| Box::pin(async move { on_error(error).await }),
                 pre_command: |ctx| {
@@ -89,6 +99,14 @@ impl Discord {
                         cache_clone.clone(),
                         data.db.clone(),
                     );
+                    Discord::calendar_warnings_thread(calendar_warning_rx, cache_clone.clone());
+                    Discord::reminders_thread(reminder_rx, cache_clone.clone());
+                    Discord::changelog_thread(changelog_rx, cache_clone.clone());
+                    Discord::scheduled_events_thread(
+                        scheduled_events_rx,
+                        cache_clone.clone(),
+                        data.db.clone(),
+                    );
 
                     debug!("Registering commands..");
                     poise::builtins::register_globally(ctx, &framework.options().commands).await?;
@@ -10,6 +10,8 @@ pub mod init;
 mod calendar_event;
 mod commands;
 mod local_cache;
+mod pagination;
+mod scheduled_events;
 
 use local_cache::LocalCache;
 use std::sync::Arc;
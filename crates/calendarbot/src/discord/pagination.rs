@@ -0,0 +1,120 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::i18n::{Locale, Text};
+use crate::serenity;
+use crate::ApplicationContext;
+use anyhow::Result;
+use std::time::Duration;
+
+/// How long the buttons keep working after their last use
+const PAGINATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Embeds sent as a single message, browsed with previous and next buttons
+pub struct Pages {
+    pages: Vec<serenity::CreateEmbed>,
+    locale: Locale,
+    ephemeral: bool,
+}
+
+impl Pages {
+    pub fn new(pages: Vec<serenity::CreateEmbed>, locale: Locale) -> Self {
+        Pages {
+            pages,
+            locale,
+            ephemeral: false,
+        }
+    }
+
+    /// Only shows the message to the author of the command
+    pub fn ephemeral(self, ephemeral: bool) -> Self {
+        Pages { ephemeral, ..self }
+    }
+
+    /// Embed of a page, with its number in the footer
+    fn page(&self, index: usize) -> serenity::CreateEmbed {
+        let footer = Text::Page.format(
+            self.locale,
+            &[("page", &(index + 1)), ("count", &self.pages.len())],
+        );
+        self.pages[index]
+            .clone()
+            .footer(serenity::CreateEmbedFooter::new(footer))
+    }
+
+    fn buttons(&self, prefix: &str, index: usize) -> Vec<serenity::CreateActionRow> {
+        vec![serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new(format!("{}previous", prefix))
+                .label(Text::Previous.get(self.locale))
+                .style(serenity::ButtonStyle::Secondary)
+                .disabled(index == 0),
+            serenity::CreateButton::new(format!("{}next", prefix))
+                .label(Text::Next.get(self.locale))
+                .style(serenity::ButtonStyle::Secondary)
+                .disabled(index + 1 >= self.pages.len()),
+        ])]
+    }
+
+    /// Replies with the first page and follows the buttons until they are left unused,
+    /// the buttons are then removed
+    pub async fn send(self, ctx: ApplicationContext<'_>) -> Result<()> {
+        if self.pages.is_empty() {
+            return Ok(());
+        }
+
+        let prefix = format!("{}-page-", ctx.id());
+        let mut reply = poise::CreateReply::default()
+            .embed(self.page(0))
+            .ephemeral(self.ephemeral);
+        if self.pages.len() > 1 {
+            reply = reply.components(self.buttons(&prefix, 0));
+        }
+        let handle = ctx.send(reply).await?;
+        if self.pages.len() == 1 {
+            return Ok(());
+        }
+
+        let mut index: usize = 0;
+        loop {
+            let filter_prefix = prefix.clone();
+            let interaction = serenity::ComponentInteractionCollector::new(ctx.serenity_context())
+                .author_id(ctx.author().id)
+                .channel_id(ctx.channel_id())
+                .timeout(PAGINATION_TIMEOUT)
+                .filter(move |interaction| interaction.data.custom_id.starts_with(&filter_prefix))
+                .await;
+            let Some(interaction) = interaction else {
+                break;
+            };
+
+            match interaction.data.custom_id.strip_prefix(&prefix) {
+                Some("previous") => index = index.saturating_sub(1),
+                Some("next") => index = (index + 1).min(self.pages.len() - 1),
+                _ => {}
+            }
+            interaction
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::UpdateMessage(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .embed(self.page(index))
+                            .components(self.buttons(&prefix, index)),
+                    ),
+                )
+                .await?;
+        }
+
+        handle
+            .edit(
+                poise::Context::Application(ctx),
+                poise::CreateReply::default()
+                    .embed(self.page(index))
+                    .components(vec![]),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use crate::discord::{Discord, LocalCache};
+use crate::events::{MirroredEvent, ScheduledEventsSync};
+use crate::models::ScheduledEvent;
+use crate::schema::scheduled_events;
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use google_calendar3::chrono::{DateTime, Utc};
+use log::{debug, error, warn};
+use poise::serenity_prelude as serenity;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+/// Discord limit of scheduled events per guild, shared by every calendar and the members
+const MAX_GUILD_SCHEDULED_EVENTS: usize = 100;
+
+/// Whether Discord answered that the scheduled event doesn't exist (anymore)
+fn is_not_found(error: &serenity::Error) -> bool {
+    matches!(
+        error,
+        serenity::Error::Http(serenity::HttpError::UnsuccessfulRequest(response))
+            if response.status_code == serenity::StatusCode::NOT_FOUND
+    )
+}
+
+/// Converts an instant to a Discord timestamp
+fn timestamp(date_time: DateTime<Utc>) -> Result<serenity::Timestamp> {
+    Ok(serenity::Timestamp::from_unix_timestamp(
+        date_time.timestamp(),
+    )?)
+}
+
+/// Whether the scheduled event already shows the calendar event
+fn is_up_to_date(scheduled_event: &ScheduledEvent, event: &MirroredEvent) -> bool {
+    scheduled_event.name == event.name
+        && scheduled_event.description == event.description
+        && scheduled_event.location == event.location
+        && scheduled_event.startTime == event.start
+        && scheduled_event.endTime == event.end
+}
+
+/// Deletes the scheduled events mirroring a calendar in a channel
+pub(crate) async fn delete_scheduled_events(
+    db: &mut AsyncPgConnection,
+    http: &serenity::Http,
+    discord_guild_id: u64,
+    calendar_id: i32,
+    channel_id: &str,
+) -> Result<()> {
+    let guild = serenity::GuildId::new(discord_guild_id);
+    let mirrored = scheduled_events::table
+        .filter(scheduled_events::calendar_id.eq(calendar_id))
+        .filter(scheduled_events::channelId.eq(channel_id))
+        .select(ScheduledEvent::as_select())
+        .load(db)
+        .await?;
+
+    for scheduled_event in mirrored {
+        if let Ok(discord_event_id) = scheduled_event.discordEventId.parse::<u64>() {
+            let res = guild
+                .delete_scheduled_event(http, serenity::ScheduledEventId::new(discord_event_id))
+                .await;
+            match res {
+                Err(e) if !is_not_found(&e) => {
+                    warn!(
+                        "Unable to delete scheduled event {}: {}",
+                        discord_event_id, e
+                    )
+                }
+                _ => {}
+            }
+        }
+        diesel::delete(&scheduled_event).execute(db).await?;
+    }
+
+    Ok(())
+}
+
+impl Discord {
+    pub(crate) fn scheduled_events_thread(
+        mut sync_rx: mpsc::Receiver<ScheduledEventsSync>,
+        cache: Arc<Mutex<Option<LocalCache>>>,
+        db: Pool<AsyncPgConnection>,
+    ) {
+        tokio::spawn(async move {
+            let mut db = match db.get().await {
+                Ok(db) => db,
+                Err(e) => {
+                    error!("Unable to get db connection from poolmanager: {:?}", e);
+                    return;
+                }
+            };
+
+            while let Some(sync) = sync_rx.recv().await {
+                let cache = cache.as_ref().lock().await.clone().unwrap();
+
+                let calendar_id = sync.calendar_id;
+                if let Err(e) = Discord::sync_scheduled_events(&mut db, &cache.client, sync).await {
+                    error!(
+                        "Unable to sync scheduled events of calendar {}: {:?}",
+                        calendar_id, e
+                    );
+                }
+            }
+        });
+    }
+
+    /// Creates, edits and deletes the scheduled events of a calendar in a channel
+    /// so they match its upcoming events
+    async fn sync_scheduled_events(
+        db: &mut AsyncPgConnection,
+        http: &serenity::Http,
+        sync: ScheduledEventsSync,
+    ) -> Result<()> {
+        let guild = serenity::GuildId::new(sync.discord_guild_id);
+        let now = Utc::now();
+
+        let mirrored = scheduled_events::table
+            .filter(scheduled_events::calendar_id.eq(sync.calendar_id))
+            .filter(scheduled_events::channelId.eq(&sync.channel_id))
+            .select(ScheduledEvent::as_select())
+            .load(db)
+            .await?;
+
+        let mut events: BTreeMap<String, MirroredEvent> = sync
+            .events
+            .into_iter()
+            .map(|event| (event.event_id.clone(), event))
+            .collect();
+
+        for scheduled_event in mirrored {
+            let discord_event_id = match scheduled_event.discordEventId.parse::<u64>() {
+                Ok(discord_event_id) => serenity::ScheduledEventId::new(discord_event_id),
+                Err(e) => {
+                    warn!("Unable to parse scheduled event id: {:?}", e);
+                    diesel::delete(&scheduled_event).execute(db).await?;
+                    continue;
+                }
+            };
+
+            let Some(event) = events.remove(&scheduled_event.eventId) else {
+                // Cancelled, over or filtered out
+                debug!("Deleting scheduled event {}", discord_event_id);
+                let res = guild.delete_scheduled_event(http, discord_event_id).await;
+                match res {
+                    Err(e) if !is_not_found(&e) => {
+                        warn!(
+                            "Unable to delete scheduled event {}: {}",
+                            discord_event_id, e
+                        );
+                        continue;
+                    }
+                    _ => {}
+                }
+                diesel::delete(&scheduled_event).execute(db).await?;
+                continue;
+            };
+
+            // Discord doesn't accept a start in the past
+            if is_up_to_date(&scheduled_event, &event)
+                || scheduled_event.startTime <= now
+                || event.start <= now
+            {
+                continue;
+            }
+
+            debug!("Editing scheduled event {}", discord_event_id);
+            let res = guild
+                .edit_scheduled_event(
+                    http,
+                    discord_event_id,
+                    serenity::EditScheduledEvent::new()
+                        .name(&event.name)
+                        .description(&event.description)
+                        .location(&event.location)
+                        .start_time(timestamp(event.start)?)
+                        .end_time(timestamp(event.end)?),
+                )
+                .await;
+            match res {
+                Ok(_) => {
+                    diesel::update(&scheduled_event)
+                        .set((
+                            scheduled_events::name.eq(&event.name),
+                            scheduled_events::description.eq(&event.description),
+                            scheduled_events::location.eq(&event.location),
+                            scheduled_events::startTime.eq(event.start),
+                            scheduled_events::endTime.eq(event.end),
+                        ))
+                        .execute(db)
+                        .await?;
+                }
+                // Deleted from Discord, it is created again
+                Err(e) if is_not_found(&e) => {
+                    diesel::delete(&scheduled_event).execute(db).await?;
+                    events.insert(event.event_id.clone(), event);
+                }
+                Err(e) => warn!("Unable to edit scheduled event {}: {}", discord_event_id, e),
+            }
+        }
+
+        // The soonest events are created first when the guild has no room for every one
+        let mut events: Vec<MirroredEvent> = events
+            .into_values()
+            .filter(|event| event.start > now)
+            .collect();
+        if events.is_empty() {
+            return Ok(());
+        }
+        events.sort_by_key(|event| event.start);
+        let scheduled = guild.scheduled_events(http, false).await?.len();
+        let mut budget = MAX_GUILD_SCHEDULED_EVENTS.saturating_sub(scheduled);
+
+        for (index, event) in events.iter().enumerate() {
+            if budget == 0 {
+                warn!(
+                    "Guild {} has too many scheduled events, {} events of calendar {} are not mirrored",
+                    sync.discord_guild_id,
+                    events.len() - index,
+                    sync.calendar_id
+                );
+                break;
+            }
+
+            debug!("Creating scheduled event for {}", event.event_id);
+            let mut builder = serenity::CreateScheduledEvent::new(
+                serenity::ScheduledEventType::External,
+                &event.name,
+                timestamp(event.start)?,
+            )
+            .end_time(timestamp(event.end)?)
+            .location(&event.location);
+            if !event.description.is_empty() {
+                builder = builder.description(&event.description);
+            }
+
+            let discord_event = match guild.create_scheduled_event(http, builder).await {
+                Ok(discord_event) => discord_event,
+                Err(e) => {
+                    // Most likely the Manage Events permission is missing or the event is refused
+                    error!(
+                        "Unable to create scheduled event in guild {} for calendar {}: {}",
+                        sync.discord_guild_id, sync.calendar_id, e
+                    );
+                    continue;
+                }
+            };
+            budget -= 1;
+
+            diesel::insert_into(scheduled_events::table)
+                .values((
+                    scheduled_events::guild_id.eq(sync.guild_id),
+                    scheduled_events::calendar_id.eq(sync.calendar_id),
+                    scheduled_events::channelId.eq(&sync.channel_id),
+                    scheduled_events::eventId.eq(&event.event_id),
+                    scheduled_events::discordEventId.eq(discord_event.id.get().to_string()),
+                    scheduled_events::name.eq(&event.name),
+                    scheduled_events::description.eq(&event.description),
+                    scheduled_events::location.eq(&event.location),
+                    scheduled_events::startTime.eq(event.start),
+                    scheduled_events::endTime.eq(event.end),
+                ))
+                .execute(db)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
@@ -5,8 +5,12 @@ This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
 
-use crate::types::{CalendarEvent, CalendarOptions};
+use crate::i18n::Locale;
+use crate::sources::{CalendarAuth, EventDraft, FailureKind, PollStatus};
+use crate::types::{CalendarEvent, CalendarEventSource, CalendarOptions, EventChange, Period};
 use anyhow::Result;
+use chrono_tz::Tz;
+use google_calendar3::chrono::{DateTime, Utc};
 use tokio::sync::oneshot::Sender;
 
 pub struct VerifyCalendarEvent {
@@ -15,9 +19,71 @@ pub struct VerifyCalendarEvent {
 
 #[derive(Debug)]
 pub enum CalendarCommands {
-    VerifyCalendarId {
+    /// Checks a calendar and responds with the identifier to store
+    ResolveCalendarId {
+        source: CalendarEventSource,
         calendar_id: String,
-        resp: Responder<bool>,
+        auth: Option<CalendarAuth>,
+        resp: Responder<String>,
+    },
+    /// Responds with the polling state of a calendar, `None` if it wasn't polled yet
+    GetPollStatus {
+        calendar_id: i32,
+        resp: Responder<Option<PollStatus>>,
+    },
+    /// Responds with the upcoming events of a calendar starting before `until`,
+    /// from the cache when it is fresh enough or else from the source.
+    /// All-day events start and end at midnight in `timezone`
+    GetUpcomingEvents {
+        id: i32,
+        source: CalendarEventSource,
+        calendar_id: String,
+        auth: Option<CalendarAuth>,
+        until: DateTime<Utc>,
+        timezone: Tz,
+        resp: Responder<Vec<CalendarEvent>>,
+    },
+    /// Responds with the events between `from` and `until` containing `text`
+    SearchEvents {
+        source: CalendarEventSource,
+        calendar_id: String,
+        auth: Option<CalendarAuth>,
+        text: String,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        resp: Responder<Vec<CalendarEvent>>,
+    },
+    /// Responds with when a calendar is busy between `from` and `until`
+    GetBusyPeriods {
+        source: CalendarEventSource,
+        calendar_id: String,
+        auth: Option<CalendarAuth>,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        resp: Responder<Vec<Period>>,
+    },
+    /// Creates an event and responds with its identifier
+    CreateEvent {
+        source: CalendarEventSource,
+        calendar_id: String,
+        auth: Option<CalendarAuth>,
+        event: EventDraft,
+        resp: Responder<String>,
+    },
+    UpdateEvent {
+        source: CalendarEventSource,
+        calendar_id: String,
+        auth: Option<CalendarAuth>,
+        event_id: String,
+        event: EventDraft,
+        resp: Responder<()>,
+    },
+    DeleteEvent {
+        source: CalendarEventSource,
+        calendar_id: String,
+        auth: Option<CalendarAuth>,
+        event_id: String,
+        resp: Responder<()>,
     },
 }
 
@@ -27,5 +93,54 @@ pub struct UpdateCalendarEvent {
     pub calendar_id: String,
     pub new_events: Vec<CalendarEvent>,
     pub calendar_options: CalendarOptions,
-    pub discord_channel_and_message_ids: Vec<(u64, Option<u64>)>,
+    pub discord_channel_and_message_ids: Vec<(u64, Vec<u64>)>,
+}
+
+/// Warns the channels of a calendar that it can't be updated, or that it works again
+pub struct CalendarWarningEvent {
+    /// Channels to warn, with the label of the calendar in each of them and their language
+    pub channels: Vec<(u64, Option<String>, Locale)>,
+    pub failures: i32,
+    /// `None` once the calendar was updated again
+    pub error: Option<FailureKind>,
+}
+
+/// Reminds a channel that an event starts soon
+pub struct ReminderEvent {
+    pub channel_id: u64,
+    /// Role pinged by the reminder
+    pub role_id: Option<u64>,
+    pub summary: String,
+    pub start: DateTime<Utc>,
+    pub link: Option<String>,
+    pub locale: Locale,
+}
+
+/// Tells a channel which of its events were added, moved or cancelled
+pub struct ChangelogEvent {
+    /// Channel or thread the changes are posted in
+    pub channel_id: u64,
+    pub changes: Vec<EventChange>,
+    pub calendar_options: CalendarOptions,
+}
+
+/// Event mirrored as a Discord scheduled event
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirroredEvent {
+    pub event_id: String,
+    pub name: String,
+    pub description: String,
+    pub location: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Discord scheduled events a calendar should have in a channel,
+/// the others created for it are deleted
+pub struct ScheduledEventsSync {
+    pub guild_id: i32,
+    pub discord_guild_id: u64,
+    pub calendar_id: i32,
+    pub channel_id: String,
+    pub events: Vec<MirroredEvent>,
 }
@@ -5,42 +5,50 @@ This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
 
-pub mod update_calendar_event;
-pub mod worker_thread;
-
-use diesel_async::pooled_connection::deadpool::Pool;
-use diesel_async::AsyncPgConnection;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use google_calendar3::api::{Event, EventDateTime, FreeBusyRequest, FreeBusyRequestItem};
+use google_calendar3::chrono::{DateTime, Utc};
 use google_calendar3::hyper::client::HttpConnector;
-use google_calendar3::{hyper, hyper_rustls, oauth2, CalendarHub, Result};
-use std::collections::BTreeMap;
-use tokio::sync::mpsc::{Receiver, Sender};
+use google_calendar3::{hyper, hyper_rustls, oauth2, CalendarHub, Error, Result};
+use log::{info, trace, warn};
+use regex::Regex;
 
-use crate::events::{CalendarCommands, UpdateCalendarEvent};
-use crate::types::CalendarEvent;
+use crate::sources::{CalendarAuth, CalendarSource, EventChanges, EventDraft, SyncResult};
+use crate::types::{CalendarEvent, CalendarEventSource, EventTime, Period};
 
-pub struct GCalendar {
-    pub hub: CalendarHub<hyper_rustls::HttpsConnector<HttpConnector>>,
-    pub db: Pool<AsyncPgConnection>,
-    events_cache: BTreeMap<String, Vec<CalendarEvent>>,
-    calendar_update_tx: Sender<UpdateCalendarEvent>,
+/// Events listed by `GCalendar::list_events`
+#[derive(Copy, Clone)]
+enum ListQuery<'a> {
+    /// Events changed since the sync token was issued
+    Changes(&'a str),
+    /// Events not over at `from` and starting before `until`, matching the free text search
+    /// of the API when given
+    Between {
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        text: Option<&'a str>,
+    },
 }
 
-impl Clone for GCalendar {
-    fn clone(&self) -> Self {
-        Self {
-            hub: self.hub.clone(),
-            db: self.db.clone(),
-            events_cache: self.events_cache.clone(),
-            calendar_update_tx: self.calendar_update_tx.clone(),
+impl ListQuery<'_> {
+    /// Upcoming events starting before `until`
+    fn upcoming(until: DateTime<Utc>) -> Self {
+        ListQuery::Between {
+            from: Utc::now(),
+            until,
+            text: None,
         }
     }
 }
 
+#[derive(Clone)]
+pub struct GCalendar {
+    pub hub: CalendarHub<hyper_rustls::HttpsConnector<HttpConnector>>,
+}
+
 impl GCalendar {
-    pub async fn new(
-        db: Pool<AsyncPgConnection>,
-        calendar_update_tx: Sender<UpdateCalendarEvent>,
-    ) -> Result<GCalendar> {
+    pub async fn new() -> Result<GCalendar> {
         let env = std::env::var("GOOGLE_CALENDAR_SERVICE_FILE")
             .expect("GOOGLE_CALENDAR_SERVICE_FILE not set");
 
@@ -63,16 +71,282 @@ impl GCalendar {
             ),
             authenticator,
         );
-        Ok(GCalendar {
-            db,
-            hub,
-            events_cache: BTreeMap::new(),
-            calendar_update_tx,
+        Ok(GCalendar { hub })
+    }
+
+    /// Lists the events of a calendar, going through every page.
+    /// Recurring events are expanded into single instances.
+    /// The events can only be ordered by the API when no sync token is needed
+    async fn list_events(
+        &self,
+        calendar_id: &str,
+        query: ListQuery<'_>,
+        order_by_start: bool,
+    ) -> Result<(Vec<Event>, Option<String>)> {
+        let mut items = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut request = self.hub.events().list(calendar_id).single_events(true);
+            request = match query {
+                ListQuery::Changes(sync_token) => request.sync_token(sync_token),
+                ListQuery::Between { from, until, text } => {
+                    let request = request.time_min(from).time_max(until);
+                    match text {
+                        Some(text) => request.q(text),
+                        None => request,
+                    }
+                }
+            };
+            if order_by_start {
+                request = request.order_by("startTime");
+            }
+            if let Some(page_token) = &page_token {
+                request = request.page_token(page_token);
+            }
+
+            let events = request.doit().await?.1;
+            items.extend(events.items.unwrap_or_default());
+
+            match events.next_page_token {
+                Some(next_page_token) => page_token = Some(next_page_token),
+                None => return Ok((items, events.next_sync_token)),
+            }
+        }
+    }
+}
+
+/// Converts a start or end written from Discord to the format of the API
+fn event_date_time(time: EventTime, timezone: &str) -> EventDateTime {
+    match time {
+        EventTime::Date(date) => EventDateTime {
+            date: Some(date),
+            ..Default::default()
+        },
+        EventTime::DateTime(date_time) => EventDateTime {
+            date_time: Some(date_time),
+            time_zone: Some(timezone.to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+impl From<&EventDraft> for Event {
+    fn from(draft: &EventDraft) -> Self {
+        Event {
+            summary: Some(draft.summary.clone()),
+            description: draft.description.clone(),
+            location: draft.location.clone(),
+            start: Some(event_date_time(draft.start, &draft.timezone)),
+            end: Some(event_date_time(draft.end, &draft.timezone)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Converts the events of a calendar, events that can't be converted are skipped
+fn convert_events(calendar_id: &str, events: Vec<Event>) -> Vec<CalendarEvent> {
+    events
+        .into_iter()
+        .filter_map(|event| {
+            let id = event.id.clone();
+            CalendarEvent::try_from(event)
+                .inspect_err(|e| warn!("Skipping event {:?} of {}: {:?}", id, calendar_id, e))
+                .ok()
         })
+        .collect()
+}
+
+/// Returns whether the error is a 410 Gone, meaning the sync token expired
+fn is_gone(error: &Error) -> bool {
+    match error {
+        Error::BadRequest(value) => value["error"]["code"] == 410,
+        Error::Failure(response) => response.status() == hyper::StatusCode::GONE,
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl CalendarSource for GCalendar {
+    fn kind(&self) -> CalendarEventSource {
+        CalendarEventSource::GoogleCalendar
+    }
+
+    async fn resolve_calendar_id(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+    ) -> anyhow::Result<String> {
+        static CALENDAR_ID_REGEX: &str = r"^(\w+\.){0,3}\w+@(\w+\.){0,3}\w+$";
+
+        let re = Regex::new(CALENDAR_ID_REGEX).unwrap();
+
+        if !re.is_match(calendar_id) {
+            return Err(anyhow!("Invalid calendar ID"));
+        }
+
+        trace!("Calendar ID is valid, checking if accessible");
+        let result = self.hub.calendars().get(calendar_id).doit().await;
+
+        match result {
+            Err(e) => {
+                info!("{:?}", e);
+                Err(anyhow!("Calendar is not accessible"))
+            }
+            Ok(_) => Ok(calendar_id.to_string()),
+        }
+    }
+
+    async fn get_events(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<CalendarEvent>> {
+        let (events, _) = self
+            .list_events(calendar_id, ListQuery::upcoming(until), true)
+            .await?;
+        Ok(convert_events(calendar_id, events))
+    }
+
+    async fn search_events(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        text: &str,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<CalendarEvent>> {
+        let query = ListQuery::Between {
+            from,
+            until,
+            text: Some(text),
+        };
+        let (events, _) = self.list_events(calendar_id, query, true).await?;
+        Ok(convert_events(calendar_id, events))
+    }
+
+    async fn busy_periods(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<Period>> {
+        let request = FreeBusyRequest {
+            items: Some(vec![FreeBusyRequestItem {
+                id: Some(calendar_id.to_string()),
+            }]),
+            time_min: Some(from),
+            time_max: Some(until),
+            ..Default::default()
+        };
+        let response = self.hub.freebusy().query(request).doit().await?.1;
+
+        let calendar = response
+            .calendars
+            .and_then(|mut calendars| calendars.remove(calendar_id))
+            .ok_or_else(|| anyhow!("No free/busy information for {}", calendar_id))?;
+        // Unreadable calendars are reported there instead of failing the request
+        if let Some(error) = calendar.errors.unwrap_or_default().first() {
+            return Err(anyhow!(
+                "Unable to get the free/busy information of {}: {}",
+                calendar_id,
+                error.reason.as_deref().unwrap_or("unknown error")
+            ));
+        }
+
+        Ok(calendar
+            .busy
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|period| period.start.zip(period.end))
+            .collect())
+    }
+
+    async fn sync_events(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        sync_token: Option<&str>,
+        until: DateTime<Utc>,
+    ) -> anyhow::Result<SyncResult> {
+        let (events, next_sync_token) = match sync_token {
+            None => {
+                self.list_events(calendar_id, ListQuery::upcoming(until), false)
+                    .await?
+            }
+            Some(sync_token) => match self
+                .list_events(calendar_id, ListQuery::Changes(sync_token), false)
+                .await
+            {
+                Err(e) if is_gone(&e) => {
+                    info!("Sync token of {} expired, doing a full sync", calendar_id);
+                    return self.sync_events(calendar_id, None, None, until).await;
+                }
+                result => result?,
+            },
+        };
+
+        let (cancelled, updated): (Vec<Event>, Vec<Event>) = events
+            .into_iter()
+            .partition(|event| event.status.as_deref() == Some("cancelled") && event.id.is_some());
+        let cancelled = cancelled.into_iter().filter_map(|event| event.id).collect();
+        let updated = convert_events(calendar_id, updated);
+
+        let changes = match sync_token {
+            None => EventChanges::Full(updated),
+            Some(_) => EventChanges::Incremental { updated, cancelled },
+        };
+
+        Ok(SyncResult {
+            changes,
+            sync_token: next_sync_token,
+        })
+    }
+
+    async fn create_event(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        event: &EventDraft,
+    ) -> anyhow::Result<String> {
+        let (_, event) = self
+            .hub
+            .events()
+            .insert(Event::from(event), calendar_id)
+            .doit()
+            .await?;
+        event.id.ok_or_else(|| anyhow!("Event id is missing"))
+    }
+
+    async fn update_event(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        event_id: &str,
+        event: &EventDraft,
+    ) -> anyhow::Result<()> {
+        // Attendees, reminders and recurrences are left as they are
+        self.hub
+            .events()
+            .patch(Event::from(event), calendar_id, event_id)
+            .doit()
+            .await?;
+        Ok(())
     }
 
-    pub fn init_threads(self, worker_thread_rx: Receiver<CalendarCommands>) -> Self {
-        self.new_update_calendars_thread()
-            .new_worker_thread(worker_thread_rx)
+    async fn delete_event(
+        &self,
+        calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        event_id: &str,
+    ) -> anyhow::Result<()> {
+        self.hub
+            .events()
+            .delete(calendar_id, event_id)
+            .doit()
+            .await?;
+        Ok(())
     }
 }
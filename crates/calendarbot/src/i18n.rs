@@ -0,0 +1,826 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::types::DEFAULT_DAY_FORMAT;
+use anyhow::anyhow;
+use google_calendar3::chrono::{Datelike, NaiveDate};
+use regex::{Captures, Regex};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// Language of the calendar messages and of the command responses
+#[derive(
+    Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, poise::ChoiceParameter,
+)]
+pub enum Locale {
+    #[default]
+    #[name = "English"]
+    En,
+    #[name = "Français"]
+    Fr,
+    #[name = "Deutsch"]
+    De,
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locale::En => write!(f, "en"),
+            Locale::Fr => write!(f, "fr"),
+            Locale::De => write!(f, "de"),
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Locale::En),
+            "fr" => Ok(Locale::Fr),
+            "de" => Ok(Locale::De),
+            _ => Err(anyhow!("Unknown locale: {}", s)),
+        }
+    }
+}
+
+const WEEKDAYS: [[&str; 7]; 3] = [
+    [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ],
+    [
+        "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+    ],
+    [
+        "Montag",
+        "Dienstag",
+        "Mittwoch",
+        "Donnerstag",
+        "Freitag",
+        "Samstag",
+        "Sonntag",
+    ],
+];
+
+const SHORT_WEEKDAYS: [[&str; 7]; 3] = [
+    ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+    ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+];
+
+const MONTHS: [[&str; 12]; 3] = [
+    [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ],
+    [
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ],
+    [
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ],
+];
+
+const SHORT_MONTHS: [[&str; 12]; 3] = [
+    [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ],
+    [
+        "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.",
+        "déc.",
+    ],
+    [
+        "Jan.", "Feb.", "März", "Apr.", "Mai", "Juni", "Juli", "Aug.", "Sept.", "Okt.", "Nov.",
+        "Dez.",
+    ],
+];
+
+impl Locale {
+    /// Finds the locale of a Discord locale code like `fr` or `en-US`
+    pub fn from_discord(locale: &str) -> Option<Locale> {
+        locale.split('-').next()?.parse().ok()
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Formats a date like chrono, with the names of the days and months in this locale
+    pub fn format_date(self, date: NaiveDate, format: &str) -> String {
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        let month = date.month0() as usize;
+
+        let mut localized = String::with_capacity(format.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                localized.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('A') => localized.push_str(WEEKDAYS[self.index()][weekday]),
+                Some('a') => localized.push_str(SHORT_WEEKDAYS[self.index()][weekday]),
+                Some('B') => localized.push_str(MONTHS[self.index()][month]),
+                Some('b') | Some('h') => localized.push_str(SHORT_MONTHS[self.index()][month]),
+                Some(specifier) => {
+                    localized.push('%');
+                    localized.push(specifier);
+                }
+                None => localized.push('%'),
+            }
+        }
+
+        // Time specifiers like `%H` fail on a date, which would panic with `to_string`
+        let mut formatted = String::new();
+        match write!(formatted, "{}", date.format(&localized)) {
+            Ok(()) => formatted,
+            Err(_) if format != DEFAULT_DAY_FORMAT => self.format_date(date, DEFAULT_DAY_FORMAT),
+            Err(_) => date.to_string(),
+        }
+    }
+}
+
+/// Texts shown to the users, `{name}` placeholders are filled by [`Text::format`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Text {
+    // Calendar messages
+    Events,
+    NoEvents,
+    AllDay,
+    MoreEvents,
+    Join,
+    NamedCalendar,
+    ThisCalendar,
+    UpdateFailing,
+    UpdateRecovered,
+    FailureStatus,
+    FailureNetwork,
+    FailureInvalidData,
+    FailureUnknown,
+    Reminder,
+    CalendarChanges,
+    ChangelogAdded,
+    ChangelogMoved,
+    ChangelogCancelled,
+
+    // Command responses
+    UnknownTimezone,
+    TimezoneRequired,
+    InvalidCalendar,
+    InvalidCalendarId,
+    CredentialsUnavailable,
+    CalendarAlreadyDisplayed,
+    SuccessfullyAdded,
+    NoCalendar,
+    CalendarNotDisplayed,
+    UnableToDelete,
+    SuccessfullyDeleted,
+    SettingAlreadySet,
+    SettingUpdated,
+    PollIntervalTooShort,
+
+    // Filters
+    Filters,
+    NoFilters,
+    FilterAdded,
+    FilterRemoved,
+    FiltersCleared,
+    FilterNotFound,
+    FilterValueRequired,
+    InvalidRegex,
+
+    // Events
+    EventRoleRequired,
+    ManageServerRequired,
+    NoWritableCalendar,
+    CalendarNotWritable,
+    CalendarRequired,
+    EventNotFound,
+    InvalidEventStart,
+    InvalidDuration,
+    EventWriteFailed,
+    EventCreated,
+    EventUpdated,
+    EventCancelled,
+    QuickEventNotUnderstood,
+    CreateEventPreview,
+    EventStart,
+    EventEnd,
+    EventLocation,
+    Create,
+    Cancel,
+    EventNotCreated,
+
+    // Queries
+    InvalidDate,
+    PastDate,
+    InvalidDateRange,
+    SearchResults,
+    NoSearchResults,
+    Page,
+    Previous,
+    Next,
+    InvalidHours,
+    FreeBusyRangeTooLong,
+    UnknownFreeBusyCalendar,
+    FreeBusyFailed,
+    FreeSlots,
+    NoFreeSlots,
+
+    // Settings
+    Timezone,
+    DisplayedDays,
+    SkipWeekends,
+    ShowIfNoEvents,
+    PollInterval,
+    FailureWarning,
+    Label,
+    DisplayMode,
+    DynamicTimestamps,
+    Template,
+    Language,
+    Reminders,
+    Changelog,
+    ScheduledEvents,
+    EventRole,
+
+    // Template validation
+    TitleLength,
+    InvalidColor,
+    DayFormatLength,
+    InvalidDayFormat,
+    EventFormatLength,
+    UnknownPlaceholder,
+    FooterLength,
+
+    // Status
+    CalendarStatus,
+    Source,
+    LastSync,
+    Never,
+    NextPoll,
+    NotScheduled,
+    DisplayedEvents,
+    Unknown,
+    ConsecutiveFailures,
+    LastError,
+    FailureWarningsDisabled,
+    FailureWarningsAfter,
+}
+
+impl Text {
+    /// English, French and German versions of the text
+    fn catalogue(self) -> [&'static str; 3] {
+        match self {
+            Text::Events => ["Events", "Événements", "Termine"],
+            Text::NoEvents => ["No events", "Aucun événement", "Keine Termine"],
+            Text::AllDay => ["All day", "Toute la journée", "Ganztägig"],
+            Text::MoreEvents => ["+{count} more", "+{count} de plus", "+{count} weitere"],
+            Text::Join => ["Join", "Rejoindre", "Beitreten"],
+            Text::NamedCalendar => [
+                "The calendar {label}",
+                "Le calendrier {label}",
+                "Der Kalender {label}",
+            ],
+            Text::ThisCalendar => ["This calendar", "Ce calendrier", "Dieser Kalender"],
+            Text::UpdateFailing => [
+                ":warning: {calendar} could not be updated for the last {failures} attempts: {error}\nUse `/calendar status` for more details.",
+                ":warning: {calendar} n'a pas pu être mis à jour lors des {failures} dernières tentatives : {error}\nUtilisez `/calendar status` pour plus de détails.",
+                ":warning: {calendar} konnte bei den letzten {failures} Versuchen nicht aktualisiert werden: {error}\nMehr Details mit `/calendar status`.",
+            ],
+            Text::UpdateRecovered => [
+                ":white_check_mark: {calendar} is updated again after {failures} failed attempts.",
+                ":white_check_mark: {calendar} est de nouveau à jour après {failures} tentatives échouées.",
+                ":white_check_mark: {calendar} wird nach {failures} fehlgeschlagenen Versuchen wieder aktualisiert.",
+            ],
+            Text::FailureStatus => [
+                "the server answered with the status {status}",
+                "le serveur a répondu avec le statut {status}",
+                "der Server hat mit dem Status {status} geantwortet",
+            ],
+            Text::FailureNetwork => [
+                "the server could not be reached",
+                "le serveur n'a pas pu être contacté",
+                "der Server war nicht erreichbar",
+            ],
+            Text::FailureInvalidData => [
+                "the calendar data could not be read",
+                "les données du calendrier n'ont pas pu être lues",
+                "die Kalenderdaten konnten nicht gelesen werden",
+            ],
+            Text::FailureUnknown => [
+                "unexpected error",
+                "erreur inattendue",
+                "unerwarteter Fehler",
+            ],
+            Text::Reminder => [
+                ":alarm_clock: **{summary}** starts {time}",
+                ":alarm_clock: **{summary}** commence {time}",
+                ":alarm_clock: **{summary}** beginnt {time}",
+            ],
+            Text::CalendarChanges => [
+                "Calendar changes",
+                "Modifications du calendrier",
+                "Kalenderänderungen",
+            ],
+            Text::ChangelogAdded => [
+                "New event **{summary}** on {time}",
+                "Nouvel événement **{summary}** le {time}",
+                "Neuer Termin **{summary}** am {time}",
+            ],
+            Text::ChangelogMoved => [
+                "**{summary}** moved from {before} to {after}",
+                "**{summary}** déplacé de {before} à {after}",
+                "**{summary}** verschoben von {before} auf {after}",
+            ],
+            Text::ChangelogCancelled => [
+                "**{summary}** on {time} cancelled",
+                "**{summary}** du {time} annulé",
+                "**{summary}** am {time} abgesagt",
+            ],
+
+            Text::UnknownTimezone => [
+                "Unknown timezone {timezone}, please pick one of the suggestions",
+                "Fuseau horaire {timezone} inconnu, veuillez choisir l'une des suggestions",
+                "Unbekannte Zeitzone {timezone}, bitte wähle einen der Vorschläge",
+            ],
+            Text::TimezoneRequired => [
+                "Please pick the timezone of the first calendar of this channel",
+                "Veuillez choisir le fuseau horaire du premier calendrier de ce salon",
+                "Bitte wähle die Zeitzone des ersten Kalenders dieses Kanals",
+            ],
+            Text::InvalidCalendar => [
+                "Invalid calendar: {error}",
+                "Calendrier invalide : {error}",
+                "Ungültiger Kalender: {error}",
+            ],
+            Text::InvalidCalendarId => [
+                "Invalid calendar ID",
+                "Identifiant de calendrier invalide",
+                "Ungültige Kalender-ID",
+            ],
+            Text::CredentialsUnavailable => [
+                "Credentials can't be stored on this bot, ask its administrator to set a secrets key",
+                "Les identifiants ne peuvent pas être enregistrés sur ce bot, demandez à son administrateur de définir une clé de chiffrement",
+                "Zugangsdaten können auf diesem Bot nicht gespeichert werden, bitte den Administrator, einen Schlüssel festzulegen",
+            ],
+            Text::CalendarAlreadyDisplayed => [
+                "This calendar is already displayed in this channel",
+                "Ce calendrier est déjà affiché dans ce salon",
+                "Dieser Kalender wird in diesem Kanal bereits angezeigt",
+            ],
+            Text::SuccessfullyAdded => [
+                "Successfully added",
+                "Ajouté avec succès",
+                "Erfolgreich hinzugefügt",
+            ],
+            Text::NoCalendar => [
+                "This channel doesn't have a calendar",
+                "Ce salon n'a pas de calendrier",
+                "Dieser Kanal hat keinen Kalender",
+            ],
+            Text::CalendarNotDisplayed => [
+                "This calendar is not displayed in this channel",
+                "Ce calendrier n'est pas affiché dans ce salon",
+                "Dieser Kalender wird in diesem Kanal nicht angezeigt",
+            ],
+            Text::UnableToDelete => [
+                "Unable to delete calendar",
+                "Impossible de supprimer le calendrier",
+                "Der Kalender konnte nicht gelöscht werden",
+            ],
+            Text::SuccessfullyDeleted => [
+                "Successfully deleted",
+                "Supprimé avec succès",
+                "Erfolgreich gelöscht",
+            ],
+            Text::SettingAlreadySet => [
+                "{setting} already set to this value",
+                "{setting} : déjà défini sur cette valeur",
+                "{setting}: bereits auf diesen Wert gesetzt",
+            ],
+            Text::SettingUpdated => [
+                "{setting} updated",
+                "{setting} : mis à jour",
+                "{setting}: aktualisiert",
+            ],
+            Text::PollIntervalTooShort => [
+                "Poll interval must be at least {minutes} minutes",
+                "L'intervalle de mise à jour doit être d'au moins {minutes} minutes",
+                "Das Abfrageintervall muss mindestens {minutes} Minuten betragen",
+            ],
+
+            Text::Filters => ["Filters", "Filtres", "Filter"],
+            Text::NoFilters => [
+                "This channel doesn't have filters",
+                "Ce salon n'a pas de filtre",
+                "Dieser Kanal hat keine Filter",
+            ],
+            Text::FilterAdded => ["Filter added", "Filtre ajouté", "Filter hinzugefügt"],
+            Text::FilterRemoved => ["Filter removed", "Filtre supprimé", "Filter entfernt"],
+            Text::FiltersCleared => [
+                "Filters removed",
+                "Filtres supprimés",
+                "Filter entfernt",
+            ],
+            Text::FilterNotFound => [
+                "This filter doesn't exist in this channel",
+                "Ce filtre n'existe pas dans ce salon",
+                "Dieser Filter existiert in diesem Kanal nicht",
+            ],
+            Text::FilterValueRequired => [
+                "This filter needs a value",
+                "Ce filtre a besoin d'une valeur",
+                "Dieser Filter braucht einen Wert",
+            ],
+            Text::InvalidRegex => [
+                "Invalid regex: {error}",
+                "Expression régulière invalide : {error}",
+                "Ungültiger regulärer Ausdruck: {error}",
+            ],
+
+            Text::EventRoleRequired => [
+                "Only members with the {role} role can edit the events of the calendars",
+                "Seuls les membres avec le rôle {role} peuvent modifier les événements des calendriers",
+                "Nur Mitglieder mit der Rolle {role} können die Termine der Kalender bearbeiten",
+            ],
+            Text::ManageServerRequired => [
+                "Only members with the Manage Server permission can edit the events of the calendars, see `/set event_role`",
+                "Seuls les membres avec la permission Gérer le serveur peuvent modifier les événements des calendriers, voir `/set event_role`",
+                "Nur Mitglieder mit der Berechtigung Server verwalten können die Termine der Kalender bearbeiten, siehe `/set event_role`",
+            ],
+            Text::NoWritableCalendar => [
+                "No calendar of this channel can be edited from Discord, only Google calendars can",
+                "Aucun calendrier de ce salon ne peut être modifié depuis Discord, seuls les calendriers Google le peuvent",
+                "Kein Kalender dieses Kanals kann von Discord aus bearbeitet werden, nur Google-Kalender",
+            ],
+            Text::CalendarNotWritable => [
+                "This calendar can't be edited from Discord, only Google calendars can",
+                "Ce calendrier ne peut pas être modifié depuis Discord, seuls les calendriers Google le peuvent",
+                "Dieser Kalender kann nicht von Discord aus bearbeitet werden, nur Google-Kalender",
+            ],
+            Text::CalendarRequired => [
+                "This channel shows several calendars, choose the one to add the event to",
+                "Ce salon affiche plusieurs calendriers, choisissez celui auquel ajouter l'événement",
+                "Dieser Kanal zeigt mehrere Kalender, wähle den Kalender für den Termin",
+            ],
+            Text::EventNotFound => [
+                "This event can't be found in the calendars of this channel",
+                "Cet événement est introuvable dans les calendriers de ce salon",
+                "Dieser Termin wurde in den Kalendern dieses Kanals nicht gefunden",
+            ],
+            Text::InvalidEventStart => [
+                "Invalid start `{start}`, use YYYY-MM-DD HH:MM, or YYYY-MM-DD for an all-day event",
+                "Début `{start}` invalide, utilisez AAAA-MM-JJ HH:MM, ou AAAA-MM-JJ pour un événement sur la journée",
+                "Ungültiger Beginn `{start}`, verwende JJJJ-MM-TT HH:MM, oder JJJJ-MM-TT für einen ganztägigen Termin",
+            ],
+            Text::InvalidDuration => [
+                "Invalid duration `{duration}`, e.g. 45m, 1h30 or 2d",
+                "Durée `{duration}` invalide, par ex. 45m, 1h30 ou 2d",
+                "Ungültige Dauer `{duration}`, z. B. 45m, 1h30 oder 2d",
+            ],
+            Text::EventWriteFailed => [
+                "Unable to write to the calendar: {error}",
+                "Impossible d'écrire dans le calendrier : {error}",
+                "Schreiben in den Kalender nicht möglich: {error}",
+            ],
+            Text::EventCreated => [
+                "Event **{summary}** created, the calendar is being updated",
+                "Événement **{summary}** créé, le calendrier est en cours de mise à jour",
+                "Termin **{summary}** erstellt, der Kalender wird aktualisiert",
+            ],
+            Text::EventUpdated => [
+                "Event **{summary}** updated, the calendar is being updated",
+                "Événement **{summary}** modifié, le calendrier est en cours de mise à jour",
+                "Termin **{summary}** geändert, der Kalender wird aktualisiert",
+            ],
+            Text::EventCancelled => [
+                "Event **{summary}** cancelled, the calendar is being updated",
+                "Événement **{summary}** annulé, le calendrier est en cours de mise à jour",
+                "Termin **{summary}** abgesagt, der Kalender wird aktualisiert",
+            ],
+            Text::QuickEventNotUnderstood => [
+                "A title and a date or time are needed, e.g. `Team retro next friday 14:00-15:30 @ room B`",
+                "Un titre et une date ou une heure sont nécessaires, par ex. `Rétro vendredi prochain de 14h à 15h30 @ salle B`",
+                "Ein Titel und ein Datum oder eine Uhrzeit sind nötig, z. B. `Team retro next friday 14:00-15:30 @ room B`",
+            ],
+            Text::CreateEventPreview => [
+                "Create this event in {calendar}?",
+                "Créer cet événement dans {calendar} ?",
+                "Diesen Termin in {calendar} erstellen?",
+            ],
+            Text::EventStart => ["Start", "Début", "Beginn"],
+            Text::EventEnd => ["End", "Fin", "Ende"],
+            Text::EventLocation => ["Location", "Lieu", "Ort"],
+            Text::Create => ["Create", "Créer", "Erstellen"],
+            Text::Cancel => ["Cancel", "Annuler", "Abbrechen"],
+            Text::EventNotCreated => [
+                "The event wasn't created",
+                "L'événement n'a pas été créé",
+                "Der Termin wurde nicht erstellt",
+            ],
+
+            Text::InvalidDate => [
+                "Unable to read the date `{date}`, try e.g. `friday` or `2026-10-20`",
+                "Impossible de lire la date `{date}`, essayez par ex. `vendredi` ou `20.10.2026`",
+                "Das Datum `{date}` ist ungültig, versuchen Sie z. B. `2026-10-20`",
+            ],
+            Text::PastDate => [
+                "This day is over",
+                "Ce jour est passé",
+                "Dieser Tag ist vorbei",
+            ],
+            Text::InvalidDateRange => [
+                "The start of the range must be before its end",
+                "Le début de la période doit précéder sa fin",
+                "Der Beginn des Zeitraums muss vor seinem Ende liegen",
+            ],
+            Text::SearchResults => [
+                "Events matching `{query}`",
+                "Événements correspondant à `{query}`",
+                "Termine passend zu `{query}`",
+            ],
+            Text::NoSearchResults => [
+                "No event matches `{query}` from {from} to {to}",
+                "Aucun événement ne correspond à `{query}` du {from} au {to}",
+                "Kein Termin passt zu `{query}` vom {from} bis {to}",
+            ],
+            Text::Page => ["Page {page}/{count}", "Page {page}/{count}", "Seite {page}/{count}"],
+            Text::Previous => ["Previous", "Précédent", "Zurück"],
+            Text::Next => ["Next", "Suivant", "Weiter"],
+            Text::InvalidHours => [
+                "Invalid working hours `{hours}`, e.g. 09:00-17:00",
+                "Heures de travail `{hours}` invalides, par ex. 09:00-17:00",
+                "Ungültige Arbeitszeit `{hours}`, z. B. 09:00-17:00",
+            ],
+            Text::FreeBusyRangeTooLong => [
+                "Free slots can be searched over {days} days at most",
+                "Les créneaux libres peuvent être cherchés sur {days} jours au plus",
+                "Freie Zeitfenster können über höchstens {days} Tage gesucht werden",
+            ],
+            Text::UnknownFreeBusyCalendar => [
+                "`{calendar}` is not a calendar of this channel, only members with the Manage Server permission can use other Google calendar ids",
+                "`{calendar}` n'est pas un calendrier de ce salon, seuls les membres avec la permission Gérer le serveur peuvent utiliser d'autres identifiants de calendriers Google",
+                "`{calendar}` ist kein Kalender dieses Kanals, nur Mitglieder mit der Berechtigung Server verwalten können andere Google-Kalender-IDs verwenden",
+            ],
+            Text::FreeBusyFailed => [
+                "Unable to get when `{calendar}` is busy: {error}",
+                "Impossible de savoir quand `{calendar}` est occupé : {error}",
+                "Die belegten Zeiten von `{calendar}` sind nicht verfügbar: {error}",
+            ],
+            Text::FreeSlots => [
+                "Common free slots of {duration} ({timezone})",
+                "Créneaux libres communs de {duration} ({timezone})",
+                "Gemeinsame freie Zeitfenster von {duration} ({timezone})",
+            ],
+            Text::NoFreeSlots => [
+                "No common free slot of {duration} from {from} to {to}",
+                "Aucun créneau libre commun de {duration} du {from} au {to}",
+                "Kein gemeinsames freies Zeitfenster von {duration} vom {from} bis {to}",
+            ],
+
+            Text::Timezone => ["Timezone", "Fuseau horaire", "Zeitzone"],
+            Text::DisplayedDays => [
+                "Number of displayed days",
+                "Nombre de jours affichés",
+                "Anzahl angezeigter Tage",
+            ],
+            Text::SkipWeekends => [
+                "Skip weekends",
+                "Masquer les week-ends",
+                "Wochenenden ausblenden",
+            ],
+            Text::ShowIfNoEvents => [
+                "Show if no events",
+                "Afficher les jours sans événement",
+                "Tage ohne Termine anzeigen",
+            ],
+            Text::PollInterval => [
+                "Poll interval",
+                "Intervalle de mise à jour",
+                "Abfrageintervall",
+            ],
+            Text::FailureWarning => [
+                "Failure warning",
+                "Avertissement d'échec",
+                "Fehlerwarnung",
+            ],
+            Text::Label => ["Label", "Libellé", "Bezeichnung"],
+            Text::DisplayMode => ["Display mode", "Mode d'affichage", "Anzeigemodus"],
+            Text::DynamicTimestamps => [
+                "Dynamic timestamps",
+                "Horodatages dynamiques",
+                "Dynamische Zeitstempel",
+            ],
+            Text::Template => ["Template", "Modèle", "Vorlage"],
+            Text::Language => ["Language", "Langue", "Sprache"],
+            Text::Reminders => ["Reminders", "Rappels", "Erinnerungen"],
+            Text::ScheduledEvents => [
+                "Discord events",
+                "Événements Discord",
+                "Discord-Events",
+            ],
+            Text::Changelog => [
+                "Changelog",
+                "Journal des modifications",
+                "Änderungsprotokoll",
+            ],
+            Text::EventRole => [
+                "Event editor role",
+                "Rôle des éditeurs d'événements",
+                "Rolle der Terminbearbeiter",
+            ],
+
+            Text::TitleLength => [
+                "The title must have between 1 and {max} characters",
+                "Le titre doit avoir entre 1 et {max} caractères",
+                "Der Titel muss zwischen 1 und {max} Zeichen lang sein",
+            ],
+            Text::InvalidColor => [
+                "Invalid colour {color}, use the hexadecimal format #rrggbb",
+                "Couleur {color} invalide, utilisez le format hexadécimal #rrggbb",
+                "Ungültige Farbe {color}, verwende das Hexadezimalformat #rrggbb",
+            ],
+            Text::DayFormatLength => [
+                "The day format must have between 1 and {max} characters",
+                "Le format des jours doit avoir entre 1 et {max} caractères",
+                "Das Tagesformat muss zwischen 1 und {max} Zeichen lang sein",
+            ],
+            Text::InvalidDayFormat => [
+                "Invalid day format {format}, see https://docs.rs/chrono/latest/chrono/format/strftime",
+                "Format des jours {format} invalide, voir https://docs.rs/chrono/latest/chrono/format/strftime",
+                "Ungültiges Tagesformat {format}, siehe https://docs.rs/chrono/latest/chrono/format/strftime",
+            ],
+            Text::EventFormatLength => [
+                "The event format must have between 1 and {max} characters",
+                "Le format des événements doit avoir entre 1 et {max} caractères",
+                "Das Terminformat muss zwischen 1 und {max} Zeichen lang sein",
+            ],
+            Text::UnknownPlaceholder => [
+                "Unknown placeholder {placeholder}, available placeholders: {placeholders}",
+                "Variable {placeholder} inconnue, variables disponibles : {placeholders}",
+                "Unbekannter Platzhalter {placeholder}, verfügbare Platzhalter: {placeholders}",
+            ],
+            Text::FooterLength => [
+                "The footer must have between 1 and {max} characters",
+                "Le pied de page doit avoir entre 1 et {max} caractères",
+                "Die Fußzeile muss zwischen 1 und {max} Zeichen lang sein",
+            ],
+
+            Text::CalendarStatus => ["Calendar status", "État des calendriers", "Kalenderstatus"],
+            Text::Source => ["Source", "Source", "Quelle"],
+            Text::LastSync => [
+                "Last successful sync",
+                "Dernière synchronisation réussie",
+                "Letzte erfolgreiche Synchronisierung",
+            ],
+            Text::Never => ["never", "jamais", "nie"],
+            Text::NextPoll => [
+                "Next poll",
+                "Prochaine mise à jour",
+                "Nächste Abfrage",
+            ],
+            Text::NotScheduled => [
+                "not scheduled yet",
+                "pas encore planifiée",
+                "noch nicht geplant",
+            ],
+            Text::DisplayedEvents => [
+                "Displayed events",
+                "Événements affichés",
+                "Angezeigte Termine",
+            ],
+            Text::Unknown => ["unknown", "inconnu", "unbekannt"],
+            Text::ConsecutiveFailures => [
+                "Consecutive failures",
+                "Échecs consécutifs",
+                "Aufeinanderfolgende Fehler",
+            ],
+            Text::LastError => ["Last error", "Dernière erreur", "Letzter Fehler"],
+            Text::FailureWarningsDisabled => [
+                "Failure warnings are disabled",
+                "Les avertissements d'échec sont désactivés",
+                "Fehlerwarnungen sind deaktiviert",
+            ],
+            Text::FailureWarningsAfter => [
+                "The channel is warned after {failures} failed updates",
+                "Le salon est averti après {failures} mises à jour échouées",
+                "Der Kanal wird nach {failures} fehlgeschlagenen Aktualisierungen gewarnt",
+            ],
+        }
+    }
+
+    pub fn get(self, locale: Locale) -> &'static str {
+        self.catalogue()[locale.index()]
+    }
+
+    /// Returns the text with its `{name}` placeholders replaced
+    pub fn format(self, locale: Locale, args: &[(&str, &(dyn fmt::Display + Sync))]) -> String {
+        replace_placeholders(self.get(locale), |name| {
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+}
+
+static PLACEHOLDER_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{([^{}]*)\}").unwrap());
+
+/// Replaces the `{name}` placeholders of a text in a single pass, so placeholders in the values
+/// are left as they are. Placeholders without value are kept
+pub(crate) fn replace_placeholders(text: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    PLACEHOLDER_REGEX
+        .replace_all(text, |captures: &Captures| {
+            value(&captures[1]).unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_in_values_are_kept() {
+        let text = Text::NamedCalendar.format(Locale::default(), &[("label", &"{label}")]);
+        assert_eq!(text, "The calendar {label}");
+    }
+
+    #[test]
+    fn format_date() {
+        // A Monday
+        let date = NaiveDate::from_ymd_opt(2026, 10, 5).unwrap();
+        let cases = [
+            (
+                Locale::En,
+                "Monday 5 October",
+                "Mon 05 Oct",
+                "**Monday** -  5 October",
+            ),
+            (
+                Locale::Fr,
+                "lundi 5 octobre",
+                "lun. 05 oct.",
+                "**lundi** -  5 octobre",
+            ),
+            (
+                Locale::De,
+                "Montag 5 Oktober",
+                "Mo 05 Okt.",
+                "**Montag** -  5 Oktober",
+            ),
+        ];
+        for (locale, long, short, default) in cases {
+            assert_eq!(locale.format_date(date, "%A %-d %B"), long);
+            assert_eq!(locale.format_date(date, "%a %d %b"), short);
+            // `%%` is a literal percent sign, not the start of a specifier
+            assert_eq!(locale.format_date(date, "100%% %-d"), "100% 5");
+            assert_eq!(locale.format_date(date, "%%A"), "%A");
+            // Time specifiers can't be formatted with a date, the default format is used
+            assert_eq!(locale.format_date(date, "%A %H:%M"), default);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+mod parser;
+
+pub(crate) use parser::parse_events;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use google_calendar3::chrono::{DateTime, Utc};
+use google_calendar3::hyper::{body, Method, Uri};
+
+use crate::sources::http::{HttpClient, StatusError};
+use crate::sources::{events_in_any_timezone, CalendarAuth, CalendarSource};
+use crate::types::{CalendarEvent, CalendarEventSource};
+
+/// Calendar source reading public iCalendar (.ics) feeds
+#[derive(Clone)]
+pub struct IcsCalendar {
+    client: HttpClient,
+}
+
+impl IcsCalendar {
+    pub fn new(client: HttpClient) -> IcsCalendar {
+        IcsCalendar { client }
+    }
+
+    async fn fetch(&self, url: &str, auth: Option<&CalendarAuth>) -> Result<body::Bytes> {
+        // webcal:// is only a hint for calendar applications, the feed is served over https
+        let uri: Uri = match url.strip_prefix("webcal://") {
+            Some(rest) => format!("https://{}", rest).parse()?,
+            None => url.parse()?,
+        };
+
+        let response = self.client.send(Method::GET, uri, auth, &[], "").await?;
+        if !response.status.is_success() {
+            return Err(StatusError(response.status)).context("Unable to fetch ICS feed");
+        }
+
+        Ok(response.body)
+    }
+}
+
+#[async_trait]
+impl CalendarSource for IcsCalendar {
+    fn kind(&self) -> CalendarEventSource {
+        CalendarEventSource::Ics
+    }
+
+    async fn resolve_calendar_id(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+    ) -> Result<String> {
+        let feed = self.fetch(calendar_id, auth).await?;
+        parse_events(&feed, CalendarEventSource::Ics)?;
+
+        Ok(calendar_id.to_string())
+    }
+
+    async fn get_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>> {
+        let feed = self.fetch(calendar_id, auth).await?;
+
+        Ok(events_in_any_timezone(
+            parse_events(&feed, CalendarEventSource::Ics)?,
+            Utc::now(),
+            until,
+        ))
+    }
+
+    async fn search_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        text: &str,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>> {
+        let feed = self.fetch(calendar_id, auth).await?;
+        let events = parse_events(&feed, CalendarEventSource::Ics)?
+            .into_iter()
+            .filter(|event| event.contains_text(text))
+            .collect();
+
+        Ok(events_in_any_timezone(events, from, until))
+    }
+}
@@ -0,0 +1,458 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use anyhow::{anyhow, Result};
+use chrono_tz::Tz;
+use google_calendar3::chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use ical::IcalParser;
+use log::warn;
+
+use crate::types::{
+    instance_id, localize, CalendarEvent, CalendarEventSource, EventTime, Recurrence,
+    RecurrenceRule,
+};
+
+/// Parses every event of an iCalendar document
+pub(crate) fn parse_events(feed: &[u8], source: CalendarEventSource) -> Result<Vec<CalendarEvent>> {
+    let mut events = vec![];
+    let mut found_calendar = false;
+
+    for calendar in IcalParser::new(feed) {
+        let calendar = calendar?;
+        found_calendar = true;
+
+        // Floating times are interpreted in the calendar timezone
+        let default_timezone = calendar
+            .properties
+            .iter()
+            .find(|p| p.name == "X-WR-TIMEZONE")
+            .and_then(|p| p.value.as_ref())
+            .and_then(|tz| tz.parse::<Tz>().ok())
+            .unwrap_or(Tz::UTC);
+
+        // Occurrences moved or cancelled by a RECURRENCE-ID event, per UID
+        let mut overridden: Vec<(String, EventTime)> = vec![];
+        let first_event = events.len();
+
+        for event in calendar.events {
+            let recurrence_id =
+                get_property(&event, "RECURRENCE-ID").and_then(|p| parse_time(p, default_timezone));
+            if let (Some(uid), Some(recurrence_id)) = (get_value(&event, "UID"), recurrence_id) {
+                overridden.push((uid.to_string(), recurrence_id));
+            }
+
+            if get_value(&event, "STATUS").is_some_and(|status| status == "CANCELLED") {
+                continue;
+            }
+
+            match parse_event(&event, default_timezone, source) {
+                Ok(event) => events.push(event),
+                Err(e) => warn!("Unable to parse ICS event: {:?}", e),
+            }
+        }
+
+        for (uid, recurrence_id) in overridden {
+            let series = events[first_event..]
+                .iter_mut()
+                .find(|event| event.id == uid && event.recurrence.is_some());
+            if let Some(recurrence) = series.and_then(|event| event.recurrence.as_mut()) {
+                recurrence.exdates.push(recurrence_id);
+            }
+        }
+    }
+
+    if !found_calendar {
+        return Err(anyhow!("No calendar found in ICS feed"));
+    }
+
+    Ok(events)
+}
+
+fn parse_event(
+    event: &IcalEvent,
+    default_timezone: Tz,
+    source: CalendarEventSource,
+) -> Result<CalendarEvent> {
+    let uid = get_value(event, "UID").ok_or_else(|| anyhow!("Event UID is missing"))?;
+
+    // Modified occurrences of a recurring event share the UID of the series
+    let id =
+        match get_property(event, "RECURRENCE-ID").and_then(|p| parse_time(p, default_timezone)) {
+            Some(recurrence_id) => instance_id(uid, &recurrence_id),
+            None => uid.to_string(),
+        };
+
+    let dtstart = get_property(event, "DTSTART");
+    let start = dtstart.and_then(|p| parse_time(p, default_timezone));
+    let end = match get_property(event, "DTEND") {
+        Some(p) => parse_time(p, default_timezone),
+        None => match (start, get_value(event, "DURATION")) {
+            (Some(start), Some(duration)) => Some(
+                add_duration(start, duration)
+                    .ok_or_else(|| anyhow!("Invalid event duration {}", duration))?,
+            ),
+            // Without end nor duration all-day events last one day
+            (Some(EventTime::Date(start)), None) => start.succ_opt().map(EventTime::Date),
+            (start, _) => start,
+        },
+    };
+
+    Ok(CalendarEvent {
+        id,
+        summary: get_value(event, "SUMMARY")
+            .map(unescape)
+            .unwrap_or_default(),
+        description: get_value(event, "DESCRIPTION")
+            .map(unescape)
+            .unwrap_or_default(),
+        location: get_value(event, "LOCATION")
+            .map(unescape)
+            .filter(|location| !location.is_empty()),
+        html_link: get_value(event, "URL").map(str::to_string),
+        // Google and Microsoft exports carry the meeting link in their own properties
+        conference_link: ["X-GOOGLE-CONFERENCE", "X-MICROSOFT-SKYPETEAMSMEETINGURL"]
+            .iter()
+            .find_map(|name| get_value(event, name))
+            .map(str::to_string),
+        color_id: get_value(event, "COLOR").map(str::to_string),
+        organizer: get_value(event, "ORGANIZER").map(|organizer| {
+            let address = organizer.trim();
+            match address.get(..7) {
+                Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => address[7..].to_string(),
+                _ => address.to_string(),
+            }
+        }),
+        // Feeds don't say which attendee owns the calendar
+        declined: false,
+        start,
+        end,
+        event_source: source,
+        recurrence: dtstart.and_then(|p| parse_recurrence(event, p, default_timezone)),
+        calendar_label: None,
+    })
+}
+
+/// Parses the RRULE, RDATE and EXDATE of a series, `None` if the event doesn't repeat
+fn parse_recurrence(
+    event: &IcalEvent,
+    dtstart: &Property,
+    default_timezone: Tz,
+) -> Option<Recurrence> {
+    // Occurrences are computed in the timezone of DTSTART, UTC times stay in UTC
+    let timezone = match dtstart.value.as_deref() {
+        Some(value) if value.trim().ends_with('Z') => Tz::UTC,
+        _ => property_timezone(dtstart, default_timezone),
+    };
+    let properties = |name: &'static str| event.properties.iter().filter(move |p| p.name == name);
+
+    let rules: Vec<RecurrenceRule> = properties("RRULE")
+        .filter_map(|p| p.value.as_deref())
+        .filter_map(|value| match RecurrenceRule::parse(value, timezone) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                warn!("Ignoring recurrence rule {}: {:?}", value, e);
+                None
+            }
+        })
+        .collect();
+    let rdates: Vec<EventTime> = properties("RDATE")
+        .filter(|p| get_param(p, "VALUE") != Some("PERIOD"))
+        .flat_map(|p| parse_times(p, default_timezone))
+        .collect();
+
+    if rules.is_empty() && rdates.is_empty() {
+        return None;
+    }
+
+    Some(Recurrence {
+        rules,
+        rdates,
+        exdates: properties("EXDATE")
+            .flat_map(|p| parse_times(p, default_timezone))
+            .collect(),
+        timezone,
+    })
+}
+
+fn get_property<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a Property> {
+    event.properties.iter().find(|p| p.name == name)
+}
+
+fn get_value<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a str> {
+    get_property(event, name).and_then(|p| p.value.as_deref())
+}
+
+fn get_param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property
+        .params
+        .as_ref()?
+        .iter()
+        .find(|(param, _)| param == name)
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+/// Parses a DATE or DATE-TIME value (RFC 5545 3.3.4 and 3.3.5)
+fn parse_time(property: &Property, default_timezone: Tz) -> Option<EventTime> {
+    parse_times(property, default_timezone).into_iter().next()
+}
+
+/// Parses a comma separated list of DATE or DATE-TIME values (EXDATE, RDATE)
+fn parse_times(property: &Property, default_timezone: Tz) -> Vec<EventTime> {
+    let Some(values) = property.value.as_deref() else {
+        return vec![];
+    };
+    let is_date = get_param(property, "VALUE") == Some("DATE");
+    let timezone = property_timezone(property, default_timezone);
+
+    values
+        .split(',')
+        .filter_map(|value| parse_time_value(value.trim(), is_date, timezone))
+        .collect()
+}
+
+fn parse_time_value(value: &str, is_date: bool, timezone: Tz) -> Option<EventTime> {
+    if is_date || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(EventTime::Date);
+    }
+
+    if let Some(value) = value.strip_suffix('Z') {
+        let date_time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+        return Some(EventTime::DateTime(date_time.and_utc()));
+    }
+
+    let date_time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    localize(timezone, date_time).map(EventTime::DateTime)
+}
+
+/// Timezone of a local time, from its TZID or the calendar default
+fn property_timezone(property: &Property, default_timezone: Tz) -> Tz {
+    match get_param(property, "TZID") {
+        None => default_timezone,
+        Some(tzid) => tzid
+            .trim_matches('"')
+            .trim_start_matches('/')
+            .parse::<Tz>()
+            .unwrap_or_else(|_| {
+                warn!("Unknown timezone {}, using {}", tzid, default_timezone);
+                default_timezone
+            }),
+    }
+}
+
+/// Adds a DURATION value to the start of an event, `None` if invalid or out of range
+fn add_duration(start: EventTime, duration: &str) -> Option<EventTime> {
+    let duration = parse_duration(duration)?;
+    match start {
+        EventTime::DateTime(start) => start.checked_add_signed(duration).map(EventTime::DateTime),
+        EventTime::Date(start) => start.checked_add_signed(duration).map(EventTime::Date),
+    }
+}
+
+/// Parses a DURATION value (RFC 5545 3.3.6), e.g. `P1D`, `PT1H30M` or `P2W`
+pub(crate) fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let (sign, value) = match value.trim().strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.trim().trim_start_matches('+')),
+    };
+    let value = value.strip_prefix('P')?;
+
+    let mut duration = TimeDelta::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+
+    for c in value.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                let part = match (unit, in_time) {
+                    ('W', false) => TimeDelta::try_weeks(n),
+                    ('D', false) => TimeDelta::try_days(n),
+                    ('H', true) => TimeDelta::try_hours(n),
+                    ('M', true) => TimeDelta::try_minutes(n),
+                    ('S', true) => TimeDelta::try_seconds(n),
+                    _ => return None,
+                };
+                duration = duration.checked_add(&part?)?;
+            }
+        }
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    duration.checked_mul(sign)
+}
+
+/// Unescapes a TEXT value (RFC 5545 3.3.11)
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use google_calendar3::chrono::{TimeZone, Utc};
+
+    fn calendar(events: &str) -> Vec<CalendarEvent> {
+        let feed = format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nX-WR-TIMEZONE:Europe/Zurich\r\n{}END:VCALENDAR\r\n",
+            events.replace('\n', "\r\n")
+        );
+        parse_events(feed.as_bytes(), CalendarEventSource::Ics).unwrap()
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> Option<EventTime> {
+        Some(EventTime::DateTime(
+            Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+                .unwrap(),
+        ))
+    }
+
+    fn date(day: u32) -> Option<EventTime> {
+        NaiveDate::from_ymd_opt(2026, 10, day).map(EventTime::Date)
+    }
+
+    #[test]
+    fn timezones() {
+        let events = calendar(
+            "BEGIN:VEVENT
+UID:tzid
+DTSTART;TZID=America/New_York:20261018T090000
+DTEND;TZID=\"/Unknown/Zone\":20261018T100000
+END:VEVENT
+BEGIN:VEVENT
+UID:floating
+DTSTART:20261018T090000
+DTEND:20261018T100000Z
+END:VEVENT
+",
+        );
+
+        // Unknown timezones fall back to the calendar one
+        assert_eq!(events[0].start, utc(18, 13, 0));
+        assert_eq!(events[0].end, utc(18, 8, 0));
+        assert_eq!(events[1].start, utc(18, 7, 0));
+        assert_eq!(events[1].end, utc(18, 10, 0));
+    }
+
+    #[test]
+    fn all_day_end() {
+        let events = calendar(
+            "BEGIN:VEVENT
+UID:with-end
+DTSTART;VALUE=DATE:20261018
+DTEND;VALUE=DATE:20261020
+END:VEVENT
+BEGIN:VEVENT
+UID:without-end
+DTSTART;VALUE=DATE:20261018
+END:VEVENT
+",
+        );
+
+        assert!(events[0].is_all_day());
+        assert_eq!(events[0].start, date(18));
+        assert_eq!(events[0].end, date(20));
+        assert_eq!(events[1].end, date(19));
+    }
+
+    #[test]
+    fn durations() {
+        let events = calendar(
+            "BEGIN:VEVENT
+UID:timed
+DTSTART:20261018T090000Z
+DURATION:PT1H30M
+END:VEVENT
+BEGIN:VEVENT
+UID:all-day
+DTSTART;VALUE=DATE:20261018
+DURATION:P1W
+END:VEVENT
+BEGIN:VEVENT
+UID:out-of-range
+DTSTART:20261018T090000Z
+DURATION:P99999999999999W
+END:VEVENT
+BEGIN:VEVENT
+UID:invalid
+DTSTART:20261018T090000Z
+DURATION:PT1X
+END:VEVENT
+",
+        );
+
+        // Events with an invalid duration are skipped
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].end, utc(18, 10, 30));
+        assert_eq!(events[1].end, date(25));
+
+        assert_eq!(parse_duration("-P1DT2H"), Some(-TimeDelta::hours(26)));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT5"), None);
+    }
+
+    #[test]
+    fn recurrence_overrides() {
+        let events = calendar(
+            "BEGIN:VEVENT
+UID:series
+DTSTART:20261018T090000Z
+DTEND:20261018T100000Z
+RRULE:FREQ=DAILY;COUNT=5
+END:VEVENT
+BEGIN:VEVENT
+UID:series
+RECURRENCE-ID:20261019T090000Z
+DTSTART:20261019T140000Z
+DTEND:20261019T150000Z
+SUMMARY:Moved
+END:VEVENT
+BEGIN:VEVENT
+UID:series
+RECURRENCE-ID:20261020T090000Z
+STATUS:CANCELLED
+END:VEVENT
+",
+        );
+
+        assert_eq!(events.len(), 2);
+        let exdates = &events[0].recurrence.as_ref().unwrap().exdates;
+        assert_eq!(
+            exdates,
+            &vec![utc(19, 9, 0).unwrap(), utc(20, 9, 0).unwrap()]
+        );
+
+        assert_eq!(events[1].id, "series_20261019T090000Z");
+        assert_eq!(events[1].summary, "Moved");
+        assert_eq!(events[1].start, utc(19, 14, 0));
+    }
+}
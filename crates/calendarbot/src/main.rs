@@ -5,15 +5,25 @@ This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
 
+pub mod caldav;
 pub mod discord;
 pub mod events;
 pub mod gcalendar;
+pub mod i18n;
+pub mod ics;
 pub mod models;
 pub mod schema;
+pub mod sources;
 pub mod types;
 
-use crate::events::UpdateCalendarEvent;
+use crate::caldav::CalDavCalendar;
+use crate::events::{
+    CalendarWarningEvent, ChangelogEvent, ReminderEvent, ScheduledEventsSync, UpdateCalendarEvent,
+};
 use crate::gcalendar::GCalendar;
+use crate::ics::IcsCalendar;
+use crate::sources::http::HttpClient;
+use crate::sources::CalendarSources;
 use anyhow::Error;
 use diesel_async::async_connection_wrapper::AsyncConnectionWrapper;
 use diesel_async::pooled_connection::deadpool::Pool;
@@ -65,23 +75,69 @@ async fn main() {
     }
     log::info!("Migrations applied successfully!");
 
+    // Credentials of private calendars are stored encrypted with this key
+    match env::var("SECRETS_KEY") {
+        Ok(key) => {
+            sources::secrets::init_key(&key).expect("Invalid SECRETS_KEY");
+            let mut db = pool
+                .get()
+                .await
+                .expect("Unable to get a database connection");
+            if let Err(e) = sources::secrets::encrypt_stored_secrets(&mut db).await {
+                log::error!("Unable to encrypt the stored secrets: {:?}", e);
+            }
+        }
+        Err(_) => log::warn!("SECRETS_KEY is not set, calendars with credentials can't be added"),
+    }
+
     let token = env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN not found");
     let intents = serenity::GatewayIntents::non_privileged();
 
     let (update_calendar_tx, update_calendar_rx) =
         tokio::sync::mpsc::channel::<UpdateCalendarEvent>(200);
 
-    let (gcalendar_tx, worker_thread_rx) = tokio::sync::mpsc::channel::<CalendarCommands>(200);
+    let (calendar_warning_tx, calendar_warning_rx) =
+        tokio::sync::mpsc::channel::<CalendarWarningEvent>(200);
 
-    GCalendar::new(pool.clone(), update_calendar_tx)
-        .await
-        .expect("Unable to connect to google calendar")
-        .init_threads(worker_thread_rx);
+    let (reminder_tx, reminder_rx) = tokio::sync::mpsc::channel::<ReminderEvent>(200);
+
+    let (changelog_tx, changelog_rx) = tokio::sync::mpsc::channel::<ChangelogEvent>(200);
 
-    let data = types::GlobalData::new(pool.clone(), gcalendar_tx).expect("Unable to load config!");
+    let (scheduled_events_tx, scheduled_events_rx) =
+        tokio::sync::mpsc::channel::<ScheduledEventsSync>(200);
+
+    let (calendar_tx, worker_thread_rx) = tokio::sync::mpsc::channel::<CalendarCommands>(200);
+
+    let gcalendar = GCalendar::new()
+        .await
+        .expect("Unable to connect to google calendar");
+    let http_client = HttpClient::new().expect("Unable to create http client");
+
+    let data = types::GlobalData::new(pool.clone(), calendar_tx).expect("Unable to load config!");
+
+    CalendarSources::new(
+        pool.clone(),
+        update_calendar_tx,
+        calendar_warning_tx,
+        reminder_tx,
+        changelog_tx,
+        scheduled_events_tx,
+        data.min_poll_interval,
+    )
+    .with_source(gcalendar)
+    .with_source(IcsCalendar::new(http_client.clone()))
+    .with_source(CalDavCalendar::new(http_client))
+    .init_threads(worker_thread_rx);
 
     let mut client = discord::Discord::new(token, intents)
-        .init(update_calendar_rx, data)
+        .init(
+            update_calendar_rx,
+            calendar_warning_rx,
+            reminder_rx,
+            changelog_rx,
+            scheduled_events_rx,
+            data,
+        )
         .await;
 
     if let Err(why) = client.start().await {
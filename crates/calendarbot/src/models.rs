@@ -7,6 +7,7 @@ This is free software, and you are welcome to redistribute it
 #![allow(non_snake_case)]
 
 use diesel::prelude::*;
+use google_calendar3::chrono::{DateTime, Utc};
 
 #[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
 #[diesel(table_name = crate::schema::calendars)]
@@ -15,6 +16,14 @@ pub struct Calendar {
     pub id: i32,
     #[allow(non_snake_case)]
     pub googleId: String,
+    pub source: String,
+    pub authUsername: Option<String>,
+    pub authSecret: Option<String>,
+    pub syncToken: Option<String>,
+    pub lastError: Option<String>,
+    /// Number of consecutive failed updates
+    pub failureCount: i32,
+    pub lastSync: Option<DateTime<Utc>>,
 }
 
 #[derive(Identifiable, Queryable, Selectable, Associations, Debug, Clone)]
@@ -27,13 +36,37 @@ pub struct GuildCalendar {
     pub guild_id: i32,
     pub calendar_id: i32,
     pub channelId: String,
-    pub messageId: Option<String>,
     pub forceUpdate: bool,
     pub timezone: String,
     pub pollInterval: i32,
     pub nbDisplayedDays: i32,
     pub skipWeekend: bool,
     pub skipEmptyDays: bool,
+    /// Consecutive failures after which the channel is warned, 0 to never warn
+    pub failureWarning: i32,
+    /// Shown before the events of this calendar when the channel merges several calendars
+    pub label: Option<String>,
+    pub displayMode: String,
+    /// Event times are shown in the timezone of each member
+    pub dynamicTimestamps: bool,
+    /// Messages showing the events, in order, when they don't fit in one
+    pub messageIds: Vec<String>,
+    /// Template of the embeds, the defaults are used when not set
+    pub embedTitle: Option<String>,
+    pub embedColor: Option<i32>,
+    pub dayFormat: Option<String>,
+    pub eventFormat: Option<String>,
+    pub embedFooter: Option<String>,
+    /// Minutes before the start of events to post a reminder, 0 to never remind
+    pub reminderMinutes: i32,
+    /// Role pinged by the reminders
+    pub reminderRoleId: Option<String>,
+    /// Added, moved and cancelled events are posted in the channel
+    pub changelog: bool,
+    /// Thread the changes are posted in instead of the channel
+    pub changelogThreadId: Option<String>,
+    /// Events are mirrored as Discord scheduled events
+    pub scheduledEvents: bool,
 }
 
 #[derive(Queryable, Selectable, Identifiable, PartialEq, Debug, Clone)]
@@ -42,4 +75,58 @@ pub struct GuildCalendar {
 pub struct Guild {
     pub id: i32,
     pub discordId: String,
+    /// Language of the guild, the locale of each interaction is used when not set
+    pub locale: Option<String>,
+    /// Role allowed to create, edit and cancel events from Discord
+    pub eventRoleId: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = crate::schema::calendar_filters)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CalendarFilter {
+    pub id: i32,
+    pub guild_id: i32,
+    pub calendar_id: i32,
+    pub channelId: String,
+    pub mode: String,
+    pub kind: String,
+    /// Empty for the filters that don't need a value
+    pub value: String,
+}
+
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = crate::schema::reminders)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Reminder {
+    pub id: i32,
+    pub guild_id: i32,
+    pub calendar_id: i32,
+    pub channelId: String,
+    /// Occurrences of recurring events have their own id
+    pub eventId: String,
+    pub summary: String,
+    pub link: Option<String>,
+    pub eventStart: DateTime<Utc>,
+    pub remindAt: DateTime<Utc>,
+    /// Set once the reminder was posted, reset when the event moves
+    pub sentAt: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = crate::schema::scheduled_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ScheduledEvent {
+    pub id: i32,
+    pub guild_id: i32,
+    pub calendar_id: i32,
+    pub channelId: String,
+    pub eventId: String,
+    pub discordEventId: String,
+    /// Values last sent to Discord
+    pub name: String,
+    pub description: String,
+    pub location: String,
+    pub startTime: DateTime<Utc>,
+    pub endTime: DateTime<Utc>,
 }
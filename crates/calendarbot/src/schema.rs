@@ -1,10 +1,37 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    calendar_filters (id) {
+        id -> Int4,
+        guild_id -> Int4,
+        calendar_id -> Int4,
+        #[max_length = 64]
+        channelId -> Varchar,
+        #[max_length = 16]
+        mode -> Varchar,
+        #[max_length = 16]
+        kind -> Varchar,
+        #[max_length = 256]
+        value -> Varchar,
+    }
+}
+
 diesel::table! {
     calendars (id) {
         id -> Int4,
-        #[max_length = 90]
+        #[max_length = 2048]
         googleId -> Varchar,
+        #[max_length = 16]
+        source -> Varchar,
+        #[max_length = 256]
+        authUsername -> Nullable<Varchar>,
+        #[max_length = 2048]
+        authSecret -> Nullable<Varchar>,
+        #[max_length = 256]
+        syncToken -> Nullable<Varchar>,
+        lastError -> Nullable<Text>,
+        failureCount -> Int4,
+        lastSync -> Nullable<Timestamptz>,
     }
 }
 
@@ -13,6 +40,10 @@ diesel::table! {
         id -> Int4,
         #[max_length = 64]
         discordId -> Varchar,
+        #[max_length = 8]
+        locale -> Nullable<Varchar>,
+        #[max_length = 64]
+        eventRoleId -> Nullable<Varchar>,
     }
 }
 
@@ -22,8 +53,6 @@ diesel::table! {
         calendar_id -> Int4,
         #[max_length = 64]
         channelId -> Varchar,
-        #[max_length = 64]
-        messageId -> Nullable<Varchar>,
         forceUpdate -> Bool,
         #[max_length = 60]
         timezone -> Varchar,
@@ -31,10 +60,79 @@ diesel::table! {
         nbDisplayedDays -> Int4,
         skipWeekend -> Bool,
         skipEmptyDays -> Bool,
+        failureWarning -> Int4,
+        #[max_length = 64]
+        label -> Nullable<Varchar>,
+        #[max_length = 16]
+        displayMode -> Varchar,
+        dynamicTimestamps -> Bool,
+        messageIds -> Array<Varchar>,
+        #[max_length = 256]
+        embedTitle -> Nullable<Varchar>,
+        embedColor -> Nullable<Int4>,
+        #[max_length = 64]
+        dayFormat -> Nullable<Varchar>,
+        #[max_length = 256]
+        eventFormat -> Nullable<Varchar>,
+        #[max_length = 2048]
+        embedFooter -> Nullable<Varchar>,
+        reminderMinutes -> Int4,
+        #[max_length = 64]
+        reminderRoleId -> Nullable<Varchar>,
+        changelog -> Bool,
+        #[max_length = 64]
+        changelogThreadId -> Nullable<Varchar>,
+        scheduledEvents -> Bool,
+    }
+}
+
+diesel::table! {
+    reminders (id) {
+        id -> Int4,
+        guild_id -> Int4,
+        calendar_id -> Int4,
+        #[max_length = 64]
+        channelId -> Varchar,
+        #[max_length = 1024]
+        eventId -> Varchar,
+        summary -> Text,
+        link -> Nullable<Text>,
+        eventStart -> Timestamptz,
+        remindAt -> Timestamptz,
+        sentAt -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    scheduled_events (id) {
+        id -> Int4,
+        guild_id -> Int4,
+        calendar_id -> Int4,
+        #[max_length = 64]
+        channelId -> Varchar,
+        #[max_length = 1024]
+        eventId -> Varchar,
+        #[max_length = 64]
+        discordEventId -> Varchar,
+        #[max_length = 100]
+        name -> Varchar,
+        #[max_length = 1000]
+        description -> Varchar,
+        #[max_length = 100]
+        location -> Varchar,
+        startTime -> Timestamptz,
+        endTime -> Timestamptz,
     }
 }
 
 diesel::joinable!(guilds_calendars -> calendars (calendar_id));
 diesel::joinable!(guilds_calendars -> guilds (guild_id));
 
-diesel::allow_tables_to_appear_in_same_query!(calendars, guilds, guilds_calendars,);
+diesel::allow_tables_to_appear_in_same_query!(
+    calendar_filters,
+    calendars,
+    guilds,
+    guilds_calendars,
+    reminders,
+    scheduled_events,
+);
@@ -0,0 +1,468 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use anyhow::{anyhow, Result};
+use google_calendar3::hyper::client::connect::dns::{GaiResolver, Name};
+use google_calendar3::hyper::client::HttpConnector;
+use google_calendar3::hyper::header::{HeaderName, AUTHORIZATION, LOCATION, USER_AGENT};
+use google_calendar3::hyper::service::Service;
+use google_calendar3::hyper::{body, Body, Client, Method, Request, StatusCode, Uri};
+use google_calendar3::hyper_rustls;
+use log::debug;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::sources::CalendarAuth;
+
+const MAX_REDIRECTS: usize = 5;
+
+/// Small HTTP client shared by the sources that don't have their own API client.
+/// Urls are given by the guilds, so only public addresses are requested
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client<hyper_rustls::HttpsConnector<HttpConnector<PublicResolver>>>,
+    allow_private: bool,
+}
+
+/// Resolves host names to their public addresses only, the bot would otherwise request
+/// its own host or network for the guilds
+#[derive(Clone)]
+struct PublicResolver {
+    resolver: GaiResolver,
+    allow_private: bool,
+}
+
+impl Service<Name> for PublicResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.resolver.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolving = self.resolver.call(name);
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = resolving
+                .await?
+                .filter(|address| allow_private || is_public(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "Only public addresses can be requested",
+                ));
+            }
+            Ok(addresses.into_iter())
+        })
+    }
+}
+
+/// Whether an address is reachable from the internet, loopback, private, link-local
+/// (cloud metadata), shared and reserved ranges are not
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, ..] = address.octets();
+            !(address.is_unspecified()
+                || address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_broadcast()
+                || address.is_documentation()
+                || address.is_multicast()
+                || a == 0
+                // Shared address space (RFC 6598) and reserved (RFC 1112)
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(address) => is_public(IpAddr::V4(address)),
+            None => {
+                let first = address.segments()[0];
+                !(address.is_unspecified()
+                    || address.is_loopback()
+                    || address.is_multicast()
+                    // Unique local (fc00::/7) and link-local (fe80::/10)
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Refuses urls that are not http(s) or whose host is a literal address that is not public,
+/// literal addresses are not resolved so `PublicResolver` doesn't see them
+fn check_uri(uri: &Uri, allow_private: bool) -> Result<()> {
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(anyhow!("Only http and https urls are supported"));
+    }
+    let host = uri
+        .host()
+        .ok_or_else(|| anyhow!("Url without host"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(address) if !allow_private && !is_public(address) => {
+            Err(anyhow!("Only public addresses can be requested"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Non-success status answered by a server
+#[derive(Debug)]
+pub struct StatusError(pub StatusCode);
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+pub struct HttpResponse {
+    pub status: StatusCode,
+    /// Uri of the response after following redirects
+    pub uri: Uri,
+    pub body: body::Bytes,
+}
+
+impl HttpClient {
+    pub fn new() -> Result<HttpClient> {
+        HttpClient::with_private_addresses(false)
+    }
+
+    fn with_private_addresses(allow_private: bool) -> Result<HttpClient> {
+        let mut http = HttpConnector::new_with_resolver(PublicResolver {
+            resolver: GaiResolver::new(),
+            allow_private,
+        });
+        http.enforce_http(false);
+        let client = Client::builder().build(
+            hyper_rustls::HttpsConnectorBuilder::new()
+                .with_native_roots()?
+                .https_or_http()
+                .enable_http1()
+                .wrap_connector(http),
+        );
+        Ok(HttpClient {
+            client,
+            allow_private,
+        })
+    }
+
+    /// Sends a request, following redirects with the same method and body
+    pub async fn send(
+        &self,
+        method: Method,
+        mut uri: Uri,
+        auth: Option<&CalendarAuth>,
+        headers: &[(HeaderName, &str)],
+        request_body: &str,
+    ) -> Result<HttpResponse> {
+        let mut auth = auth;
+        for _ in 0..MAX_REDIRECTS {
+            debug!("{} {}", method, uri);
+            // Redirects could lead anywhere
+            check_uri(&uri, self.allow_private)?;
+            let mut request = Request::builder()
+                .method(method.clone())
+                .uri(uri.clone())
+                .header(USER_AGENT, "calendarbot");
+            if let Some(auth) = auth {
+                request = request.header(AUTHORIZATION, auth.header_value());
+            }
+            for (name, value) in headers {
+                request = request.header(name, *value);
+            }
+
+            let response = self
+                .client
+                .request(request.body(Body::from(request_body.to_string()))?)
+                .await?;
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .ok_or_else(|| anyhow!("Redirect without location"))?
+                    .to_str()?;
+                let next = resolve_uri(&uri, location)?;
+                // Credentials are only sent to the origin they were given for
+                if next.scheme() != uri.scheme() || next.authority() != uri.authority() {
+                    auth = None;
+                }
+                uri = next;
+                continue;
+            }
+
+            return Ok(HttpResponse {
+                status: response.status(),
+                uri,
+                body: body::to_bytes(response.into_body()).await?,
+            });
+        }
+
+        Err(anyhow!("Too many redirects"))
+    }
+}
+
+/// Resolves a possibly relative reference (href, location header) against a base uri (RFC 3986 5.2)
+pub fn resolve_uri(base: &Uri, reference: &str) -> Result<Uri> {
+    let reference = reference.trim();
+    let reference = reference
+        .split_once('#')
+        .map_or(reference, |(reference, _)| reference);
+    if reference.is_empty() {
+        return Ok(base.clone());
+    }
+    if is_absolute(reference) {
+        return Ok(reference.parse()?);
+    }
+    if let Some(network_path) = reference.strip_prefix("//") {
+        let scheme = base.scheme_str().unwrap_or("https");
+        return Ok(format!("{}://{}", scheme, network_path).parse()?);
+    }
+
+    let base_path = base.path();
+    let path_and_query = if reference.starts_with('?') {
+        format!("{}{}", base_path, reference)
+    } else {
+        let (path, query) = match reference.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (reference, None),
+        };
+        let path = if path.starts_with('/') {
+            remove_dot_segments(path)
+        } else {
+            let directory = &base_path[..base_path.rfind('/').map_or(0, |i| i + 1)];
+            remove_dot_segments(&format!("/{}{}", directory.trim_start_matches('/'), path))
+        };
+        match query {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        }
+    };
+
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// Whether the reference starts with a scheme, like `https:`
+fn is_absolute(reference: &str) -> bool {
+    reference.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Removes the `.` and `..` segments of an absolute path
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    let mut trailing_slash = false;
+    for segment in path.split('/').skip(1) {
+        trailing_slash = matches!(segment, "." | "..");
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if trailing_slash {
+        segments.push("");
+    }
+    format!("/{}", segments.join("/"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// Serves the canned responses in order on a local port, one per connection,
+    /// and returns the requests received
+    pub(crate) async fn serve(responses: Vec<String>) -> (Uri, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let handle = tokio::spawn(async move {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 4096];
+                while !is_complete(&request) {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                requests.push(String::from_utf8(request).unwrap());
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+            requests
+        });
+        (uri, handle)
+    }
+
+    /// Client allowed to request the local test servers
+    pub(crate) fn local_client() -> HttpClient {
+        HttpClient::with_private_addresses(true).unwrap()
+    }
+
+    /// Whether the headers and the body announced by Content-Length were read
+    fn is_complete(request: &[u8]) -> bool {
+        let request = String::from_utf8_lossy(request);
+        let Some((head, body)) = request.split_once("\r\n\r\n") else {
+            return false;
+        };
+        let length = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        body.len() >= length
+    }
+
+    pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    fn has_authorization(request: &str) -> bool {
+        request
+            .lines()
+            .any(|line| line.to_ascii_lowercase().starts_with("authorization:"))
+    }
+
+    #[test]
+    fn relative_references() {
+        let base: Uri = "https://dav.example.com/dav/calendars/user/"
+            .parse()
+            .unwrap();
+        let resolve = |reference| resolve_uri(&base, reference).unwrap().to_string();
+
+        assert_eq!(
+            resolve("work/"),
+            "https://dav.example.com/dav/calendars/user/work/"
+        );
+        assert_eq!(
+            resolve("../other/home/"),
+            "https://dav.example.com/dav/calendars/other/home/"
+        );
+        assert_eq!(resolve("/principal/"), "https://dav.example.com/principal/");
+        assert_eq!(
+            resolve("?export"),
+            "https://dav.example.com/dav/calendars/user/?export"
+        );
+        assert_eq!(resolve("//cdn.example.com/a"), "https://cdn.example.com/a");
+        assert_eq!(
+            resolve("http://other.example.com/cal"),
+            "http://other.example.com/cal"
+        );
+
+        let file: Uri = "https://example.com/calendars/team.ics".parse().unwrap();
+        assert_eq!(
+            resolve_uri(&file, "holidays.ics").unwrap().to_string(),
+            "https://example.com/calendars/holidays.ics"
+        );
+    }
+
+    #[tokio::test]
+    async fn authorization_is_dropped_on_cross_origin_redirects() {
+        let (target, target_requests) = serve(vec![response("200 OK", &[], "done")]).await;
+        let (origin, origin_requests) = serve(vec![
+            response("302 Found", &[("Location", "/moved")], ""),
+            response("302 Found", &[("Location", &target.to_string())], ""),
+        ])
+        .await;
+
+        let auth = CalendarAuth::Bearer("secret".to_string());
+        let response = local_client()
+            .send(Method::GET, origin, Some(&auth), &[], "")
+            .await
+            .unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.uri, target);
+
+        let origin_requests = origin_requests.await.unwrap();
+        assert!(origin_requests.iter().all(|r| has_authorization(r)));
+        assert!(origin_requests[1].starts_with("GET /moved "));
+        let target_requests = target_requests.await.unwrap();
+        assert!(!has_authorization(&target_requests[0]));
+    }
+
+    #[test]
+    fn public_addresses() {
+        let public = |address: &str| is_public(address.parse().unwrap());
+        assert!(public("93.184.215.14"));
+        assert!(public("2606:4700::6810:84e5"));
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(address), "{}", address);
+        }
+    }
+
+    #[tokio::test]
+    async fn private_addresses_are_refused() {
+        let client = HttpClient::new().unwrap();
+        let send = |uri: &str| client.send(Method::GET, uri.parse().unwrap(), None, &[], "");
+
+        assert!(send("http://169.254.169.254/latest/meta-data/")
+            .await
+            .is_err());
+        assert!(send("http://[::1]/").await.is_err());
+        assert!(send("ftp://example.com/calendar.ics").await.is_err());
+        // Resolved names are checked too
+        assert!(send("http://localhost/").await.is_err());
+
+        // Every redirect is checked like the first url
+        assert!(check_uri(&"http://10.0.0.1/".parse().unwrap(), false).is_err());
+        assert!(check_uri(&"https://example.com/a.ics".parse().unwrap(), false).is_ok());
+    }
+}
@@ -0,0 +1,619 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+pub mod http;
+pub mod reminders;
+pub mod scheduled_events;
+pub mod secrets;
+pub mod update_calendar_event;
+pub mod worker_thread;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono_tz::Tz;
+use diesel_async::pooled_connection::deadpool::Pool;
+use diesel_async::AsyncPgConnection;
+use google_calendar3::chrono::{DateTime, TimeDelta, Utc};
+use google_calendar3::hyper;
+use log::error;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::Instant;
+
+use crate::events::{
+    CalendarCommands, CalendarWarningEvent, ChangelogEvent, ReminderEvent, ScheduledEventsSync,
+    UpdateCalendarEvent,
+};
+use crate::models::Calendar;
+use crate::sources::http::StatusError;
+use crate::types::{busy_periods, CalendarEvent, CalendarEventSource, EventTime, Period};
+
+/// Credentials used to access a calendar
+#[derive(Clone, PartialEq, Eq)]
+pub enum CalendarAuth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl CalendarAuth {
+    /// Builds the credentials as stored in the database,
+    /// a secret without username is a bearer token
+    pub fn new(username: Option<String>, secret: Option<String>) -> Option<CalendarAuth> {
+        match (username, secret) {
+            (_, None) => None,
+            (Some(username), Some(password)) if !username.is_empty() => {
+                Some(CalendarAuth::Basic { username, password })
+            }
+            (_, Some(token)) => Some(CalendarAuth::Bearer(token)),
+        }
+    }
+
+    /// Builds the credentials of a calendar, its secret is stored encrypted
+    pub fn from_calendar(calendar: &Calendar) -> Option<CalendarAuth> {
+        let secret = match calendar.authSecret.as_deref().map(secrets::decrypt_secret) {
+            Some(Ok(secret)) => Some(secret),
+            Some(Err(e)) => {
+                error!(
+                    "Unable to decrypt the secret of calendar {}: {:?}",
+                    calendar.id, e
+                );
+                return None;
+            }
+            None => None,
+        };
+        CalendarAuth::new(calendar.authUsername.clone(), secret)
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        match self {
+            CalendarAuth::Basic { username, .. } => Some(username),
+            CalendarAuth::Bearer(_) => None,
+        }
+    }
+
+    pub fn secret(&self) -> &str {
+        match self {
+            CalendarAuth::Basic { password, .. } => password,
+            CalendarAuth::Bearer(token) => token,
+        }
+    }
+
+    /// Value of the Authorization header
+    pub fn header_value(&self) -> String {
+        match self {
+            CalendarAuth::Basic { username, password } => format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password))
+            ),
+            CalendarAuth::Bearer(token) => format!("Bearer {}", token),
+        }
+    }
+}
+
+// Never print secrets in logs
+impl fmt::Debug for CalendarAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalendarAuth::Basic { username, .. } => write!(f, "Basic({}, ***)", username),
+            CalendarAuth::Bearer(_) => write!(f, "Bearer(***)"),
+        }
+    }
+}
+
+/// Event written to a calendar from Discord
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventDraft {
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub start: EventTime,
+    /// Exclusive, the day after the last day for all-day events
+    pub end: EventTime,
+    /// Timezone the event was entered in, used by recurring events and calendar applications
+    pub timezone: String,
+}
+
+/// A backend providing calendar events (Google Calendar, ICS feeds, CalDAV, ...)
+#[async_trait]
+pub trait CalendarSource: Send + Sync {
+    /// Kind of calendars handled by this source
+    fn kind(&self) -> CalendarEventSource;
+
+    /// Checks that the calendar identifier is valid and that the calendar is readable.
+    /// Returns the identifier to store, which can differ for sources able to discover calendars
+    async fn resolve_calendar_id(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+    ) -> Result<String>;
+
+    /// Fetches the upcoming events of a calendar starting before `until`, ordered by start
+    async fn get_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>>;
+
+    /// Fetches the events between `from` and `until` whose summary, description or location
+    /// contain `text`, ordered by start
+    async fn search_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        text: &str,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<CalendarEvent>>;
+
+    /// Fetches when a calendar is busy between `from` and `until`, from its events by default
+    async fn busy_periods(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Period>> {
+        // Every event contains the empty text
+        let events = self
+            .search_events(calendar_id, auth, "", from, until)
+            .await?;
+        Ok(busy_periods(&events))
+    }
+
+    /// Fetches the events changed since the last sync.
+    /// Sources without incremental sync return every upcoming event starting before `until`
+    async fn sync_events(
+        &self,
+        calendar_id: &str,
+        auth: Option<&CalendarAuth>,
+        _sync_token: Option<&str>,
+        until: DateTime<Utc>,
+    ) -> Result<SyncResult> {
+        Ok(SyncResult {
+            changes: EventChanges::Full(self.get_events(calendar_id, auth, until).await?),
+            sync_token: None,
+        })
+    }
+
+    /// Creates an event and returns its identifier. Sources are read-only by default
+    async fn create_event(
+        &self,
+        _calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        _event: &EventDraft,
+    ) -> Result<String> {
+        Err(anyhow!("{} calendars are read-only", self.kind()))
+    }
+
+    /// Replaces the summary, description, location and times of an event
+    async fn update_event(
+        &self,
+        _calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        _event_id: &str,
+        _event: &EventDraft,
+    ) -> Result<()> {
+        Err(anyhow!("{} calendars are read-only", self.kind()))
+    }
+
+    /// Deletes an event, or a single occurrence of a recurring event
+    async fn delete_event(
+        &self,
+        _calendar_id: &str,
+        _auth: Option<&CalendarAuth>,
+        _event_id: &str,
+    ) -> Result<()> {
+        Err(anyhow!("{} calendars are read-only", self.kind()))
+    }
+}
+
+/// Events returned by a sync
+#[derive(Debug)]
+pub enum EventChanges {
+    /// Every event of the calendar, replaces the cached events
+    Full(Vec<CalendarEvent>),
+    /// Events created, updated or cancelled since the last sync
+    Incremental {
+        updated: Vec<CalendarEvent>,
+        cancelled: Vec<String>,
+    },
+}
+
+impl EventChanges {
+    /// Applies the changes to the cached events (by event id), returns whether anything changed
+    pub fn apply(self, cache: &mut BTreeMap<String, CalendarEvent>) -> bool {
+        match self {
+            EventChanges::Full(events) => {
+                let events: BTreeMap<String, CalendarEvent> = events
+                    .into_iter()
+                    .map(|event| (event.id.clone(), event))
+                    .collect();
+                let changed = *cache != events;
+                *cache = events;
+                changed
+            }
+            EventChanges::Incremental { updated, cancelled } => {
+                let mut changed = false;
+                for event in updated {
+                    if cache.get(&event.id) != Some(&event) {
+                        cache.insert(event.id.clone(), event);
+                        changed = true;
+                    }
+                }
+                for id in cancelled {
+                    changed |= cache.remove(&id).is_some();
+                }
+                changed
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncResult {
+    pub changes: EventChanges,
+    /// Token to pass to the next sync, if the source supports incremental sync
+    pub sync_token: Option<String>,
+}
+
+/// Timezones where days start first and end last. Calendars are shared by channels in
+/// different timezones, their all-day events are kept while they are upcoming in one of them
+const EARLIEST_TIMEZONE: Tz = Tz::Etc__GMTMinus14;
+pub(crate) const LATEST_TIMEZONE: Tz = Tz::Etc__GMTPlus12;
+
+/// Returns whether the event is not over yet, all-day events end at midnight in `timezone`
+pub(crate) fn is_upcoming(event: &CalendarEvent, now: DateTime<Utc>, timezone: &Tz) -> bool {
+    event
+        .end
+        .or(event.start)
+        .is_some_and(|end| end.to_utc(timezone) > now)
+}
+
+/// Expands recurring events and keeps the occurrences that are not over yet and start before
+/// `until`, ordered by start
+pub(crate) fn upcoming_events(
+    events: Vec<CalendarEvent>,
+    until: DateTime<Utc>,
+    timezone: &Tz,
+) -> Vec<CalendarEvent> {
+    events_between(events, Utc::now(), until, timezone)
+}
+
+/// Expands recurring events and keeps the occurrences that are not over at `from` and start
+/// before `until`, ordered by start. All-day events start and end at midnight in `timezone`
+pub(crate) fn events_between(
+    events: Vec<CalendarEvent>,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    timezone: &Tz,
+) -> Vec<CalendarEvent> {
+    filter_events(events, from, until, timezone, timezone)
+}
+
+/// Same as `events_between` for the events of a calendar, which can be shown in channels of
+/// any timezone
+pub(crate) fn events_in_any_timezone(
+    events: Vec<CalendarEvent>,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Vec<CalendarEvent> {
+    filter_events(events, from, until, &EARLIEST_TIMEZONE, &LATEST_TIMEZONE)
+}
+
+fn filter_events(
+    events: Vec<CalendarEvent>,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    start_timezone: &Tz,
+    end_timezone: &Tz,
+) -> Vec<CalendarEvent> {
+    // Occurrences are generated in the timezone of their series, which can start a day later
+    let mut events: Vec<CalendarEvent> = events
+        .into_iter()
+        .flat_map(|event| event.expand(until + TimeDelta::days(1)))
+        .filter(|event| is_upcoming(event, from, end_timezone))
+        .filter(|event| {
+            event
+                .start
+                .is_some_and(|start| start.to_utc(start_timezone) < until)
+        })
+        .collect();
+    events.sort_by_key(|event| {
+        (
+            event.start.map(|start| start.to_utc(start_timezone)),
+            event.id.clone(),
+        )
+    });
+
+    events
+}
+
+/// Why a calendar could not be updated, without the urls and server messages
+/// of the error, which are only shown by `/calendar status`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The server answered with this status code
+    Status(u16),
+    /// The server could not be reached
+    Network,
+    /// The server answered with data that could not be read
+    InvalidData,
+    Unknown,
+}
+
+impl FailureKind {
+    pub fn of(error: &anyhow::Error) -> FailureKind {
+        for cause in error.chain() {
+            if let Some(StatusError(status)) = cause.downcast_ref() {
+                return FailureKind::Status(status.as_u16());
+            }
+            if let Some(error) = cause.downcast_ref::<google_calendar3::Error>() {
+                return match error {
+                    google_calendar3::Error::Failure(response) => {
+                        FailureKind::Status(response.status().as_u16())
+                    }
+                    // Google errors carry their status code in the body
+                    google_calendar3::Error::BadRequest(body) => body["error"]["code"]
+                        .as_u64()
+                        .and_then(|code| u16::try_from(code).ok())
+                        .map_or(FailureKind::Unknown, FailureKind::Status),
+                    google_calendar3::Error::HttpError(_) | google_calendar3::Error::Io(_) => {
+                        FailureKind::Network
+                    }
+                    google_calendar3::Error::JsonDecodeError(..) => FailureKind::InvalidData,
+                    _ => FailureKind::Unknown,
+                };
+            }
+            if cause.is::<hyper::Error>() || cause.is::<std::io::Error>() {
+                return FailureKind::Network;
+            }
+            if cause.is::<roxmltree::Error>()
+                || cause.is::<ical::parser::ParserError>()
+                || cause.is::<std::str::Utf8Error>()
+            {
+                return FailureKind::InvalidData;
+            }
+        }
+        FailureKind::Unknown
+    }
+}
+
+/// Polling state of a calendar, shared with the worker thread
+#[derive(Debug, Copy, Clone)]
+pub struct PollStatus {
+    pub next_poll: DateTime<Utc>,
+    /// Displayed events, unknown until the first successful update
+    pub event_count: Option<usize>,
+}
+
+/// Events of a calendar as of its last successful sync, shared with the worker thread
+#[derive(Debug, Clone)]
+pub struct SyncedEvents {
+    pub events: Vec<CalendarEvent>,
+    /// Date until which the events are complete
+    pub until: DateTime<Utc>,
+    /// When the calendar is polled again, the events are considered outdated after that
+    pub fresh_until: DateTime<Utc>,
+}
+
+/// Polls every registered calendar source and forwards new events to discord
+#[derive(Clone)]
+pub struct CalendarSources {
+    pub db: Pool<AsyncPgConnection>,
+    sources: BTreeMap<CalendarEventSource, Arc<dyn CalendarSource>>,
+    /// Upcoming events of each calendar (by database id), by event id
+    events_cache: BTreeMap<i32, BTreeMap<String, CalendarEvent>>,
+    /// Date until which the cached events of each calendar (by database id) are complete
+    sync_horizons: BTreeMap<i32, DateTime<Utc>>,
+    /// When each calendar (by database id) has to be polled next
+    next_polls: BTreeMap<i32, Instant>,
+    /// Same as `next_polls` with the number of events, readable from the worker thread
+    poll_statuses: Arc<Mutex<BTreeMap<i32, PollStatus>>>,
+    /// Same as `events_cache` by database id, readable from the worker thread
+    synced_events: Arc<Mutex<BTreeMap<i32, SyncedEvents>>>,
+    /// Server-wide minimum poll interval in minutes
+    min_poll_interval: i32,
+    calendar_update_tx: Sender<UpdateCalendarEvent>,
+    calendar_warning_tx: Sender<CalendarWarningEvent>,
+    reminder_tx: Sender<ReminderEvent>,
+    changelog_tx: Sender<ChangelogEvent>,
+    scheduled_events_tx: Sender<ScheduledEventsSync>,
+}
+
+impl CalendarSources {
+    pub fn new(
+        db: Pool<AsyncPgConnection>,
+        calendar_update_tx: Sender<UpdateCalendarEvent>,
+        calendar_warning_tx: Sender<CalendarWarningEvent>,
+        reminder_tx: Sender<ReminderEvent>,
+        changelog_tx: Sender<ChangelogEvent>,
+        scheduled_events_tx: Sender<ScheduledEventsSync>,
+        min_poll_interval: i32,
+    ) -> CalendarSources {
+        CalendarSources {
+            db,
+            sources: BTreeMap::new(),
+            events_cache: BTreeMap::new(),
+            sync_horizons: BTreeMap::new(),
+            next_polls: BTreeMap::new(),
+            poll_statuses: Arc::new(Mutex::new(BTreeMap::new())),
+            synced_events: Arc::new(Mutex::new(BTreeMap::new())),
+            min_poll_interval,
+            calendar_update_tx,
+            calendar_warning_tx,
+            reminder_tx,
+            changelog_tx,
+            scheduled_events_tx,
+        }
+    }
+
+    /// Registers a source, replacing any previous source of the same kind
+    pub fn with_source(mut self, source: impl CalendarSource + 'static) -> Self {
+        self.sources.insert(source.kind(), Arc::new(source));
+        self
+    }
+
+    pub(crate) fn get_source(&self, kind: &CalendarEventSource) -> Option<Arc<dyn CalendarSource>> {
+        self.sources.get(kind).cloned()
+    }
+
+    pub fn init_threads(self, worker_thread_rx: Receiver<CalendarCommands>) -> Self {
+        self.new_update_calendars_thread()
+            .new_reminders_thread()
+            .new_worker_thread(worker_thread_rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use google_calendar3::chrono::NaiveDate;
+    use google_calendar3::hyper::StatusCode;
+
+    #[test]
+    fn failure_kinds() {
+        let status: Result<()> = Err(StatusError(StatusCode::NOT_FOUND))
+            .context("Unable to fetch ICS feed from https://example.com/secret.ics");
+        assert_eq!(
+            FailureKind::of(&status.unwrap_err()),
+            FailureKind::Status(404)
+        );
+
+        let google = anyhow::Error::from(google_calendar3::Error::BadRequest(
+            serde_json::json!({"error": {"code": 403, "message": "Forbidden"}}),
+        ));
+        assert_eq!(FailureKind::of(&google), FailureKind::Status(403));
+
+        let xml = anyhow::Error::from(roxmltree::Document::parse("<a>").unwrap_err());
+        assert_eq!(FailureKind::of(&xml), FailureKind::InvalidData);
+
+        assert_eq!(
+            FailureKind::of(&anyhow!("No calendar found at this url")),
+            FailureKind::Unknown
+        );
+    }
+
+    fn all_day_event(day: NaiveDate) -> CalendarEvent {
+        CalendarEvent {
+            id: String::from("holiday"),
+            summary: String::from("Holiday"),
+            description: String::new(),
+            location: None,
+            html_link: None,
+            conference_link: None,
+            color_id: None,
+            organizer: None,
+            declined: false,
+            start: Some(EventTime::Date(day)),
+            end: Some(EventTime::Date(day + TimeDelta::days(1))),
+            event_source: CalendarEventSource::Ics,
+            recurrence: None,
+            calendar_label: None,
+        }
+    }
+
+    #[test]
+    fn all_day_events_end_at_midnight_in_the_channel_timezone() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 20).unwrap();
+        let event = all_day_event(day);
+        // Noon in UTC, the day is already over in Auckland
+        let now = day.and_hms_opt(12, 0, 0).unwrap().and_utc();
+
+        assert!(is_upcoming(&event, now, &Tz::UTC));
+        assert!(!is_upcoming(&event, now, &chrono_tz::Pacific::Auckland));
+        assert!(is_upcoming(&event, now, &LATEST_TIMEZONE));
+    }
+
+    #[test]
+    fn calendar_events_are_kept_for_every_timezone() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 20).unwrap();
+        let events = vec![all_day_event(day)];
+        // The day has started in Auckland but not in UTC
+        let from = day.and_hms_opt(0, 0, 0).unwrap().and_utc() - TimeDelta::hours(12);
+        let until = from + TimeDelta::hours(1);
+
+        assert!(events_between(events.clone(), from, until, &Tz::UTC).is_empty());
+        assert_eq!(
+            events_between(events.clone(), from, until, &chrono_tz::Pacific::Auckland).len(),
+            1
+        );
+        assert_eq!(events_in_any_timezone(events, from, until).len(), 1);
+    }
+
+    fn cache(events: Vec<CalendarEvent>) -> BTreeMap<String, CalendarEvent> {
+        events
+            .into_iter()
+            .map(|event| (event.id.clone(), event))
+            .collect()
+    }
+
+    #[test]
+    fn full_changes_replace_the_cache() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 20).unwrap();
+        let holiday = all_day_event(day);
+        let trip = CalendarEvent {
+            id: String::from("trip"),
+            ..all_day_event(day + TimeDelta::days(2))
+        };
+
+        let mut events = cache(vec![holiday.clone()]);
+        assert!(!EventChanges::Full(vec![holiday.clone()]).apply(&mut events));
+        assert!(EventChanges::Full(vec![trip.clone()]).apply(&mut events));
+        assert_eq!(events, cache(vec![trip]));
+    }
+
+    #[test]
+    fn incremental_changes_update_the_cache() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 20).unwrap();
+        let holiday = all_day_event(day);
+        let trip = CalendarEvent {
+            id: String::from("trip"),
+            ..all_day_event(day + TimeDelta::days(2))
+        };
+        let mut events = cache(vec![holiday.clone()]);
+
+        // Unchanged events and unknown cancelled events change nothing
+        let unchanged = EventChanges::Incremental {
+            updated: vec![holiday.clone()],
+            cancelled: vec![String::from("unknown")],
+        };
+        assert!(!unchanged.apply(&mut events));
+        assert_eq!(events, cache(vec![holiday.clone()]));
+
+        let added = EventChanges::Incremental {
+            updated: vec![trip.clone()],
+            cancelled: vec![],
+        };
+        assert!(added.apply(&mut events));
+        assert_eq!(events, cache(vec![holiday.clone(), trip.clone()]));
+
+        let moved = CalendarEvent {
+            start: Some(EventTime::Date(day + TimeDelta::days(3))),
+            end: Some(EventTime::Date(day + TimeDelta::days(4))),
+            ..trip.clone()
+        };
+        let updated = EventChanges::Incremental {
+            updated: vec![moved.clone()],
+            cancelled: vec![],
+        };
+        assert!(updated.apply(&mut events));
+        assert_eq!(events, cache(vec![holiday.clone(), moved.clone()]));
+
+        let cancelled = EventChanges::Incremental {
+            updated: vec![],
+            cancelled: vec![holiday.id.clone()],
+        };
+        assert!(cancelled.apply(&mut events));
+        assert_eq!(events, cache(vec![moved]));
+    }
+}
@@ -0,0 +1,254 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use crate::events::ReminderEvent;
+use crate::models::{Calendar, GuildCalendar, Reminder};
+use crate::schema::{guilds_calendars, reminders};
+use crate::sources::update_calendar_event::{channel_filters, guild_locales};
+use crate::sources::{events_in_any_timezone, CalendarSources};
+use crate::types::{CalendarEvent, EventFilter, EventTime};
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use google_calendar3::chrono::{DateTime, TimeDelta, Utc};
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// How often the reminders that are due are looked for
+const REMINDER_TICK: Duration = Duration::from_secs(15);
+
+/// Reminder an event should have in a channel
+struct PlannedReminder {
+    guild_id: i32,
+    summary: String,
+    link: Option<String>,
+    event_start: DateTime<Utc>,
+    remind_at: DateTime<Utc>,
+}
+
+/// Schedules the reminders of the upcoming events of a calendar in the channels that want them.
+/// The reminders of moved events are rescheduled and the ones of cancelled events are dropped
+pub(crate) async fn sync_reminders(
+    db: &mut AsyncPgConnection,
+    calendar: &Calendar,
+    guild_calendars: &[GuildCalendar],
+    events: &BTreeMap<String, CalendarEvent>,
+    until: DateTime<Utc>,
+) -> Result<()> {
+    let reminding: Vec<&GuildCalendar> = guild_calendars
+        .iter()
+        .filter(|guild_calendar| guild_calendar.reminderMinutes > 0)
+        .collect();
+
+    let mut planned: BTreeMap<(String, String), PlannedReminder> = BTreeMap::new();
+    if !reminding.is_empty() {
+        let channel_ids: Vec<String> = reminding
+            .iter()
+            .map(|guild_calendar| guild_calendar.channelId.clone())
+            .collect();
+        let filters = channel_filters(db, &channel_ids).await?;
+
+        let now = Utc::now();
+        let occurrences = events_in_any_timezone(events.values().cloned().collect(), now, until);
+        for guild_calendar in reminding {
+            let filters = filters
+                .get(&(calendar.id, guild_calendar.channelId.clone()))
+                .map_or(&[][..], Vec::as_slice);
+
+            // All-day events have no start time to remind before
+            for event in occurrences
+                .iter()
+                .filter(|event| !event.is_all_day())
+                .filter(|event| EventFilter::keep(filters, event))
+            {
+                let Some(EventTime::DateTime(start)) = event.start else {
+                    continue;
+                };
+                if start <= now {
+                    continue;
+                }
+
+                let event = CalendarEvent {
+                    calendar_label: guild_calendar.label.clone(),
+                    ..event.clone()
+                };
+                planned.insert(
+                    (guild_calendar.channelId.clone(), event.id.clone()),
+                    PlannedReminder {
+                        guild_id: guild_calendar.guild_id,
+                        summary: event.display_summary(),
+                        link: event.join_link().or(event.html_link.clone()),
+                        event_start: start,
+                        remind_at: start
+                            - TimeDelta::minutes(i64::from(guild_calendar.reminderMinutes)),
+                    },
+                );
+            }
+        }
+    }
+
+    let scheduled = reminders::table
+        .filter(reminders::calendar_id.eq(calendar.id))
+        .select(Reminder::as_select())
+        .load(db)
+        .await?;
+
+    for reminder in scheduled {
+        let key = (reminder.channelId.clone(), reminder.eventId.clone());
+        let Some(wanted) = planned.remove(&key) else {
+            // Cancelled, started, filtered out or reminders were disabled
+            trace!("Dropping reminder of event {}", reminder.eventId);
+            diesel::delete(&reminder).execute(db).await?;
+            continue;
+        };
+
+        if reminder.summary == wanted.summary
+            && reminder.link == wanted.link
+            && reminder.eventStart == wanted.event_start
+            && reminder.remindAt == wanted.remind_at
+        {
+            continue;
+        }
+
+        // Moved events are reminded again at their new time
+        let sent_at = match reminder.eventStart == wanted.event_start {
+            true => reminder.sentAt,
+            false => {
+                debug!(
+                    "Event {} moved from {} to {}",
+                    reminder.eventId, reminder.eventStart, wanted.event_start
+                );
+                None
+            }
+        };
+        diesel::update(&reminder)
+            .set((
+                reminders::summary.eq(&wanted.summary),
+                reminders::link.eq(&wanted.link),
+                reminders::eventStart.eq(wanted.event_start),
+                reminders::remindAt.eq(wanted.remind_at),
+                reminders::sentAt.eq(sent_at),
+            ))
+            .execute(db)
+            .await?;
+    }
+
+    for ((channel_id, event_id), wanted) in planned {
+        trace!(
+            "Scheduling reminder of event {} in channel {} at {}",
+            event_id,
+            channel_id,
+            wanted.remind_at
+        );
+        diesel::insert_into(reminders::table)
+            .values((
+                reminders::guild_id.eq(wanted.guild_id),
+                reminders::calendar_id.eq(calendar.id),
+                reminders::channelId.eq(channel_id),
+                reminders::eventId.eq(event_id),
+                reminders::summary.eq(wanted.summary),
+                reminders::link.eq(wanted.link),
+                reminders::eventStart.eq(wanted.event_start),
+                reminders::remindAt.eq(wanted.remind_at),
+            ))
+            .on_conflict_do_nothing()
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+impl CalendarSources {
+    pub(crate) fn new_reminders_thread(self) -> Self {
+        let self_clone = self.clone();
+        info!("Starting reminders thread");
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self_clone.send_due_reminders().await {
+                    error!("Unable to send reminders: {:?}", e);
+                }
+                tokio::time::sleep(REMINDER_TICK).await;
+            }
+        });
+        self
+    }
+
+    /// Sends the reminders that are due, reminders missed while the bot was offline
+    /// are still sent if their event didn't start yet
+    async fn send_due_reminders(&self) -> Result<()> {
+        let mut db = self.db.get().await?;
+        let now = Utc::now();
+
+        let due = reminders::table
+            .filter(reminders::sentAt.is_null())
+            .filter(reminders::remindAt.le(now))
+            .select(Reminder::as_select())
+            .load(&mut db)
+            .await?;
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        // Marked first so a reminder is never sent twice
+        let ids: Vec<i32> = due.iter().map(|reminder| reminder.id).collect();
+        diesel::update(reminders::table.filter(reminders::id.eq_any(&ids)))
+            .set(reminders::sentAt.eq(now))
+            .execute(&mut db)
+            .await?;
+
+        let channel_ids: Vec<String> = due
+            .iter()
+            .map(|reminder| reminder.channelId.clone())
+            .collect();
+        let roles: BTreeMap<String, Option<String>> = guilds_calendars::table
+            .filter(guilds_calendars::channelId.eq_any(&channel_ids))
+            .select((
+                guilds_calendars::channelId,
+                guilds_calendars::reminderRoleId,
+            ))
+            .load::<(String, Option<String>)>(&mut db)
+            .await?
+            .into_iter()
+            .collect();
+        let guild_ids: Vec<i32> = due.iter().map(|reminder| reminder.guild_id).collect();
+        let locales = guild_locales(&mut db, &guild_ids).await;
+
+        for reminder in due {
+            if reminder.eventStart <= now {
+                debug!("Event {} started before its reminder", reminder.eventId);
+                continue;
+            }
+            let channel_id = match reminder.channelId.parse::<u64>() {
+                Ok(channel_id) => channel_id,
+                Err(e) => {
+                    warn!("Unable to parse channel id: {:?}", e);
+                    continue;
+                }
+            };
+            let role_id = roles
+                .get(&reminder.channelId)
+                .and_then(Option::as_ref)
+                .and_then(|role_id| role_id.parse::<u64>().ok());
+
+            let event = ReminderEvent {
+                channel_id,
+                role_id,
+                summary: reminder.summary,
+                start: reminder.eventStart,
+                link: reminder.link,
+                locale: locales.get(&reminder.guild_id).copied().unwrap_or_default(),
+            };
+            if let Err(e) = self.reminder_tx.send(event).await {
+                error!("Unable to send reminder: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+}
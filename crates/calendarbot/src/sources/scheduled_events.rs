@@ -0,0 +1,149 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use crate::events::{MirroredEvent, ScheduledEventsSync};
+use crate::models::{Calendar, GuildCalendar};
+use crate::schema::guilds;
+use crate::sources::upcoming_events;
+use crate::sources::update_calendar_event::{channel_filters, display_window};
+use crate::types::{truncate, CalendarEvent, EventFilter, EventTime};
+use anyhow::Result;
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use google_calendar3::chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use log::error;
+use std::collections::BTreeMap;
+
+/// Discord allows 100 scheduled events per guild, a calendar only takes part of them.
+/// The guild-wide limit is checked when the events are created
+const MAX_MIRRORED_EVENTS: usize = 25;
+
+/// Discord limits of scheduled events
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_LOCATION_LENGTH: usize = 100;
+
+/// Returns an instant of an event in a timezone, all-day events start at midnight
+fn instant(time: EventTime, timezone: &Tz) -> Option<DateTime<Utc>> {
+    match time {
+        EventTime::Date(date) => date
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(*timezone)
+            .earliest()
+            .map(|date_time| date_time.to_utc()),
+        EventTime::DateTime(date_time) => Some(date_time),
+    }
+}
+
+/// Builds the scheduled event mirroring a calendar event
+fn mirror(event: &CalendarEvent, timezone: &Tz) -> Option<MirroredEvent> {
+    let start = instant(event.start?, timezone)?;
+    // External events need an end, events without one last an hour
+    let end = match event.end {
+        Some(end) => instant(end, timezone)?,
+        None => start + TimeDelta::hours(1),
+    };
+
+    // External events need a location
+    let location = event
+        .location
+        .clone()
+        .filter(|location| !location.trim().is_empty())
+        .or_else(|| event.join_link())
+        .or_else(|| event.html_link.clone())
+        .unwrap_or_else(|| poise::ChoiceParameter::name(&event.event_source).to_string());
+
+    Some(MirroredEvent {
+        event_id: event.id.clone(),
+        name: truncate(&event.display_summary(), MAX_NAME_LENGTH),
+        description: truncate(
+            &event.short_description().unwrap_or_default(),
+            MAX_DESCRIPTION_LENGTH,
+        ),
+        location: truncate(&location, MAX_LOCATION_LENGTH),
+        start,
+        end: end.max(start + TimeDelta::minutes(1)),
+    })
+}
+
+/// Returns the scheduled events of each channel mirroring the events of a calendar
+pub(crate) async fn mirrored_events(
+    db: &mut AsyncPgConnection,
+    calendar: &Calendar,
+    guild_calendars: &[GuildCalendar],
+    events: &BTreeMap<String, CalendarEvent>,
+) -> Result<Vec<ScheduledEventsSync>> {
+    let mirroring: Vec<&GuildCalendar> = guild_calendars
+        .iter()
+        .filter(|guild_calendar| guild_calendar.scheduledEvents)
+        .collect();
+    if mirroring.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let channel_ids: Vec<String> = mirroring
+        .iter()
+        .map(|guild_calendar| guild_calendar.channelId.clone())
+        .collect();
+    let guild_ids: Vec<i32> = mirroring
+        .iter()
+        .map(|guild_calendar| guild_calendar.guild_id)
+        .collect();
+    let filters = channel_filters(db, &channel_ids).await?;
+    let discord_ids: BTreeMap<i32, String> = guilds::table
+        .filter(guilds::id.eq_any(&guild_ids))
+        .select((guilds::id, guilds::discordId))
+        .load::<(i32, String)>(db)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut syncs = vec![];
+    for guild_calendar in mirroring {
+        let Some(discord_guild_id) = discord_ids
+            .get(&guild_calendar.guild_id)
+            .and_then(|discord_id| discord_id.parse::<u64>().ok())
+        else {
+            error!("Unable to find the guild {}", guild_calendar.guild_id);
+            continue;
+        };
+        let timezone: Tz = match guild_calendar.timezone.parse() {
+            Ok(timezone) => timezone,
+            Err(e) => {
+                error!("Failed to parse timezone: {}", e);
+                continue;
+            }
+        };
+        let filters = filters
+            .get(&(calendar.id, guild_calendar.channelId.clone()))
+            .map_or(&[][..], Vec::as_slice);
+
+        // Only the displayed events are mirrored
+        let until = Utc::now() + display_window(std::slice::from_ref(guild_calendar));
+        let events = upcoming_events(events.values().cloned().collect(), until, &timezone)
+            .into_iter()
+            .filter(|event| EventFilter::keep(filters, event))
+            .map(|event| CalendarEvent {
+                calendar_label: guild_calendar.label.clone(),
+                ..event
+            })
+            .filter_map(|event| mirror(&event, &timezone))
+            .take(MAX_MIRRORED_EVENTS)
+            .collect();
+
+        syncs.push(ScheduledEventsSync {
+            guild_id: guild_calendar.guild_id,
+            discord_guild_id,
+            calendar_id: calendar.id,
+            channel_id: guild_calendar.channelId.clone(),
+            events,
+        });
+    }
+
+    Ok(syncs)
+}
@@ -0,0 +1,143 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use log::info;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::OnceLock;
+
+use crate::schema::calendars;
+
+/// Prefix of the encrypted secrets, older secrets were stored as they are
+const ENCRYPTED_PREFIX: &str = "aes256gcm:";
+
+/// Key of the calendar secrets, from the `SECRETS_KEY` environment variable
+static KEY: OnceLock<LessSafeKey> = OnceLock::new();
+
+/// Parses a base64 encoded 32 bytes key
+fn parse_key(key: &str) -> Result<LessSafeKey> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(key.trim())?;
+    let key = UnboundKey::new(&AES_256_GCM, &bytes)
+        .map_err(|_| anyhow!("The secrets key must be 32 bytes encoded in base64"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Sets the key used to encrypt and decrypt the calendar secrets
+pub fn init_key(key: &str) -> Result<()> {
+    KEY.set(parse_key(key)?)
+        .map_err(|_| anyhow!("The secrets key is already set"))
+}
+
+fn key() -> Result<&'static LessSafeKey> {
+    KEY.get()
+        .ok_or_else(|| anyhow!("SECRETS_KEY is not set, calendar credentials can't be stored"))
+}
+
+fn seal(key: &LessSafeKey, secret: &str) -> Result<String> {
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("Unable to generate a nonce"))?;
+
+    let mut data = secret.as_bytes().to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Unable to encrypt secret"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(data);
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_PREFIX,
+        base64::engine::general_purpose::STANDARD.encode(sealed)
+    ))
+}
+
+fn open(key: &LessSafeKey, value: &str) -> Result<String> {
+    let Some(sealed) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let mut sealed = base64::engine::general_purpose::STANDARD.decode(sealed)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow!("Encrypted secret is too short"));
+    }
+    let mut data = sealed.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&sealed)
+        .map_err(|_| anyhow!("Invalid nonce of encrypted secret"))?;
+
+    let secret = key
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Unable to decrypt secret, was the secrets key changed?"))?;
+    Ok(String::from_utf8(secret.to_vec())?)
+}
+
+/// Encrypts a secret before storing it in the database
+pub fn encrypt_secret(secret: &str) -> Result<String> {
+    seal(key()?, secret)
+}
+
+/// Decrypts a secret stored in the database, secrets stored before encryption are returned as they are
+pub fn decrypt_secret(value: &str) -> Result<String> {
+    if !value.starts_with(ENCRYPTED_PREFIX) {
+        return Ok(value.to_string());
+    }
+    open(key()?, value)
+}
+
+/// Encrypts the secrets that were stored before encryption
+pub async fn encrypt_stored_secrets(db: &mut AsyncPgConnection) -> Result<()> {
+    let plain_secrets = calendars::table
+        .filter(calendars::authSecret.is_not_null())
+        .filter(calendars::authSecret.not_like(format!("{}%", ENCRYPTED_PREFIX)))
+        .select((calendars::id, calendars::authSecret))
+        .load::<(i32, Option<String>)>(db)
+        .await?;
+
+    for (id, secret) in plain_secrets {
+        let Some(secret) = secret else {
+            continue;
+        };
+        diesel::update(calendars::table.filter(calendars::id.eq(id)))
+            .set(calendars::authSecret.eq(encrypt_secret(&secret)?))
+            .execute(db)
+            .await?;
+        info!("Encrypted the secret of calendar {}", id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    #[test]
+    fn round_trip() {
+        let key = parse_key(KEY).unwrap();
+        let sealed = seal(&key, "p4ssw0rd").unwrap();
+
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert!(!sealed.contains("p4ssw0rd"));
+        // Nonces are random, the same secret is never stored twice the same way
+        assert_ne!(sealed, seal(&key, "p4ssw0rd").unwrap());
+        assert_eq!(open(&key, &sealed).unwrap(), "p4ssw0rd");
+        assert_eq!(open(&key, "legacy").unwrap(), "legacy");
+    }
+
+    #[test]
+    fn wrong_key() {
+        let sealed = seal(&parse_key(KEY).unwrap(), "p4ssw0rd").unwrap();
+        let other = parse_key("ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=").unwrap();
+
+        assert!(open(&other, &sealed).is_err());
+        assert!(parse_key("c2hvcnQ=").is_err());
+    }
+}
@@ -0,0 +1,694 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use crate::sources::{
+    events_in_any_timezone, is_upcoming, upcoming_events, CalendarAuth, CalendarSources,
+    EventChanges, FailureKind, PollStatus, SyncedEvents, LATEST_TIMEZONE,
+};
+use crate::types::{CalendarEvent, CalendarEventSource, CalendarOptions, EventChange, EventFilter};
+
+use crate::events::{CalendarWarningEvent, ChangelogEvent, UpdateCalendarEvent};
+use crate::i18n::Locale;
+use crate::models::{Calendar, CalendarFilter, GuildCalendar};
+use crate::schema::{calendar_filters, calendars, guilds, guilds_calendars};
+use crate::sources::reminders::sync_reminders;
+use crate::sources::scheduled_events::mirrored_events;
+use anyhow::{anyhow, Result};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use google_calendar3::chrono::{TimeDelta, Utc};
+use log::{debug, error, info, trace, warn};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How often the scheduler checks for calendars that are due
+const SCHEDULER_TICK: Duration = Duration::from_secs(10);
+
+/// Events are fetched this many days further than displayed,
+/// so incremental syncs can be used until the displayed window reaches that date
+const SYNC_HORIZON_MARGIN_DAYS: i64 = 7;
+
+/// Upper bound of the delay before retrying a failing calendar
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Channel to warn, with the label of the calendar there and the guild of the channel
+type WarnedChannel = (u64, Option<String>, i32);
+
+/// Language of each guild, guilds without one aren't in the map
+pub(crate) async fn guild_locales(
+    db: &mut AsyncPgConnection,
+    guild_ids: &[i32],
+) -> BTreeMap<i32, Locale> {
+    let res = guilds::table
+        .filter(guilds::id.eq_any(guild_ids))
+        .select((guilds::id, guilds::locale))
+        .load::<(i32, Option<String>)>(db)
+        .await;
+
+    match res {
+        Ok(locales) => locales
+            .into_iter()
+            .filter_map(|(guild_id, locale)| Some((guild_id, locale?.parse().ok()?)))
+            .collect(),
+        Err(e) => {
+            error!("Unable to load guild locales: {}", e);
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Filters of each calendar (by database id) in each of the channels
+pub(crate) async fn channel_filters(
+    db: &mut AsyncPgConnection,
+    channel_ids: &[String],
+) -> Result<BTreeMap<(i32, String), Vec<EventFilter>>> {
+    let mut filters: BTreeMap<(i32, String), Vec<EventFilter>> = BTreeMap::new();
+    let calendar_filters = calendar_filters::table
+        .filter(calendar_filters::channelId.eq_any(channel_ids))
+        .load::<CalendarFilter>(db)
+        .await?;
+    for filter in calendar_filters {
+        match EventFilter::try_from(&filter) {
+            Ok(event_filter) => filters
+                .entry((filter.calendar_id, filter.channelId))
+                .or_default()
+                .push(event_filter),
+            Err(e) => error!("Unable to parse filter {}: {:?}", filter.id, e),
+        }
+    }
+    Ok(filters)
+}
+
+/// Returns when to retry a calendar after `failures` consecutive failures,
+/// the poll interval is doubled on each failure
+fn retry_delay(poll_interval: Duration, failures: i32) -> Duration {
+    let exponent = (failures - 1).clamp(0, 16) as u32;
+
+    poll_interval
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_RETRY_DELAY.max(poll_interval))
+}
+
+/// Returns how far in the future the subscribers of a calendar display events
+pub(crate) fn display_window(guild_calendars: &[GuildCalendar]) -> TimeDelta {
+    let days = guild_calendars
+        .iter()
+        .map(|guild_calendar| {
+            let days = i64::from(guild_calendar.nbDisplayedDays);
+            // Skipped weekends are not counted in the displayed days
+            match guild_calendar.skipWeekend {
+                true => days + (days / 5 + 1) * 2,
+                false => days,
+            }
+        })
+        .max()
+        .unwrap_or_default();
+
+    // The last displayed day is included and channels are not in UTC
+    TimeDelta::days(days + 2)
+}
+
+impl CalendarSources {
+    pub(crate) fn new_update_calendars_thread(self) -> Self {
+        let mut self_clone = self.clone();
+        tokio::spawn(async move {
+            loop {
+                self_clone.update_calendars().await;
+                trace!("Updated calendars");
+                tokio::time::sleep(SCHEDULER_TICK).await;
+            }
+        });
+        self
+    }
+
+    /// Returns the poll interval of a calendar, the smallest interval among its subscribers
+    /// bounded by the server-wide minimum
+    fn poll_interval(&self, guild_calendars: &[GuildCalendar]) -> Duration {
+        let minutes = guild_calendars
+            .iter()
+            .map(|guild_calendar| guild_calendar.pollInterval)
+            .min()
+            .unwrap_or(self.min_poll_interval)
+            .max(self.min_poll_interval);
+
+        Duration::from_secs(minutes as u64 * 60)
+    }
+
+    async fn update_calendars(&mut self) {
+        let mut db = match self.db.get().await {
+            Ok(db) => db,
+            Err(e) => {
+                warn!("Unable to clone db: {:?}", e);
+                return;
+            }
+        };
+
+        let db_calendars = match calendars::table
+            .select(Calendar::as_select())
+            .load(&mut db)
+            .await
+        {
+            Ok(db_calendars) => db_calendars,
+            Err(e) => {
+                error!("Unable to get calendars: {:?}", e);
+                return;
+            }
+        };
+
+        // Forget calendars that were deleted
+        self.next_polls
+            .retain(|id, _| db_calendars.iter().any(|calendar| calendar.id == *id));
+        if let Ok(mut statuses) = self.poll_statuses.lock() {
+            statuses.retain(|id, _| db_calendars.iter().any(|calendar| calendar.id == *id));
+        }
+        if let Ok(mut synced_events) = self.synced_events.lock() {
+            synced_events.retain(|id, _| db_calendars.iter().any(|calendar| calendar.id == *id));
+        }
+        self.events_cache
+            .retain(|id, _| db_calendars.iter().any(|calendar| calendar.id == *id));
+        self.sync_horizons
+            .retain(|id, _| db_calendars.iter().any(|calendar| calendar.id == *id));
+
+        for calendar in db_calendars {
+            let guild_calendars = match GuildCalendar::belonging_to(&calendar)
+                .select(GuildCalendar::as_select())
+                .load(&mut db)
+                .await
+            {
+                Ok(guild_calendars) => guild_calendars,
+                Err(e) => {
+                    error!(
+                        "Unable to get channel and message ids of calendar {}: {:?}",
+                        calendar.id, e
+                    );
+                    continue;
+                }
+            };
+
+            if guild_calendars.is_empty() {
+                continue;
+            }
+
+            let forced_update = guild_calendars
+                .iter()
+                .any(|guild_calendar| guild_calendar.forceUpdate);
+
+            // Failing calendars wait for their retry even if an update is forced
+            let now = Instant::now();
+            if (!forced_update || calendar.failureCount > 0)
+                && self
+                    .next_polls
+                    .get(&calendar.id)
+                    .is_some_and(|next_poll| *next_poll > now)
+            {
+                continue;
+            }
+            let poll_interval = self.poll_interval(&guild_calendars);
+
+            trace!(
+                "Updating calendar: {} (next poll in {:?})",
+                calendar.googleId,
+                poll_interval
+            );
+
+            // Channels to warn and the number of failures they are warned after
+            let failure_warnings: Vec<(WarnedChannel, i32)> = guild_calendars
+                .iter()
+                .filter(|guild_calendar| guild_calendar.failureWarning > 0)
+                .filter_map(|guild_calendar| {
+                    let channel_id = guild_calendar.channelId.parse::<u64>().ok()?;
+                    Some((
+                        (
+                            channel_id,
+                            guild_calendar.label.clone(),
+                            guild_calendar.guild_id,
+                        ),
+                        guild_calendar.failureWarning,
+                    ))
+                })
+                .collect();
+
+            let result = self
+                .update_calendar(&mut db, &calendar, guild_calendars, forced_update)
+                .await;
+
+            let (next_poll, event_count, warned, failures, error) = match result {
+                Ok(event_count) => {
+                    if calendar.failureCount > 0 {
+                        info!(
+                            "Calendar {} updated after {} failures",
+                            calendar.id, calendar.failureCount
+                        );
+                    }
+
+                    let res = diesel::update(&calendar)
+                        .set((
+                            calendars::lastSync.eq(Utc::now()),
+                            calendars::lastError.eq(None::<String>),
+                            calendars::failureCount.eq(0),
+                        ))
+                        .execute(&mut db)
+                        .await;
+                    if let Err(e) = res {
+                        error!("Unable to save calendar status: {}", e);
+                    }
+
+                    // Channels that were warned are told that the calendar works again
+                    let warned: Vec<_> = failure_warnings
+                        .iter()
+                        .filter(|(_, threshold)| calendar.failureCount >= *threshold)
+                        .map(|(channel, _)| channel.clone())
+                        .collect();
+
+                    (
+                        poll_interval,
+                        Some(event_count),
+                        warned,
+                        calendar.failureCount,
+                        None,
+                    )
+                }
+                Err(e) => {
+                    let failures = calendar.failureCount + 1;
+                    let delay = retry_delay(poll_interval, failures);
+                    error!(
+                        "Unable to update calendar {} ({} consecutive failures, retrying in {:?}): {:?}",
+                        calendar.id, failures, delay, e
+                    );
+
+                    let res = diesel::update(&calendar)
+                        .set((
+                            calendars::lastError.eq(format!("{:#}", e)),
+                            calendars::failureCount.eq(failures),
+                        ))
+                        .execute(&mut db)
+                        .await;
+                    if let Err(e) = res {
+                        error!("Unable to save calendar status: {}", e);
+                    }
+
+                    let warned = failure_warnings
+                        .iter()
+                        .filter(|(_, threshold)| failures == *threshold)
+                        .map(|(channel, _)| channel.clone())
+                        .collect();
+
+                    let event_count = self
+                        .poll_statuses
+                        .lock()
+                        .ok()
+                        .and_then(|statuses| statuses.get(&calendar.id)?.event_count);
+                    (
+                        delay,
+                        event_count,
+                        warned,
+                        failures,
+                        Some(FailureKind::of(&e)),
+                    )
+                }
+            };
+
+            self.next_polls.insert(calendar.id, now + next_poll);
+            if let Ok(mut statuses) = self.poll_statuses.lock() {
+                statuses.insert(
+                    calendar.id,
+                    PollStatus {
+                        next_poll: Utc::now() + next_poll,
+                        event_count,
+                    },
+                );
+            }
+
+            if !warned.is_empty() {
+                let guild_ids: Vec<i32> = warned.iter().map(|(_, _, guild_id)| *guild_id).collect();
+                let locales = guild_locales(&mut db, &guild_ids).await;
+                let warning = CalendarWarningEvent {
+                    channels: warned
+                        .into_iter()
+                        .map(|(channel_id, label, guild_id)| {
+                            let locale = locales.get(&guild_id).copied().unwrap_or_default();
+                            (channel_id, label, locale)
+                        })
+                        .collect(),
+                    failures,
+                    error,
+                };
+                if let Err(e) = self.calendar_warning_tx.send(warning).await {
+                    error!("Unable to send calendar warning: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Syncs the events of a calendar and sends them to the channels that need an update
+    async fn update_calendar(
+        &mut self,
+        db: &mut AsyncPgConnection,
+        calendar: &Calendar,
+        mut guild_calendars: Vec<GuildCalendar>,
+        forced_update: bool,
+    ) -> Result<usize> {
+        let cal_id = calendar.googleId.clone();
+        let sender = self.calendar_update_tx.clone();
+
+        let kind = CalendarEventSource::from_str(&calendar.source)?;
+        let source = self
+            .get_source(&kind)
+            .ok_or_else(|| anyhow!("No source registered for calendar source {}", kind))?;
+
+        let auth = CalendarAuth::from_calendar(calendar);
+
+        let until = Utc::now() + display_window(&guild_calendars);
+        let poll_interval = self.poll_interval(&guild_calendars);
+
+        // The cache is only in memory, a full sync is needed after a restart
+        // or once the displayed window goes past the events fetched by the last full sync
+        let horizon = self.sync_horizons.get(&calendar.id).copied();
+        let (sync_token, fetch_until) = match horizon {
+            Some(horizon) if horizon >= until => (calendar.syncToken.as_deref(), horizon),
+            _ => (None, until + TimeDelta::days(SYNC_HORIZON_MARGIN_DAYS)),
+        };
+        let sync = source
+            .sync_events(&cal_id, auth.as_ref(), sync_token, fetch_until)
+            .await?;
+
+        if let EventChanges::Full(_) = sync.changes {
+            self.sync_horizons.insert(calendar.id, fetch_until);
+        }
+
+        if sync.sync_token != calendar.syncToken {
+            let res = diesel::update(calendar)
+                .set(calendars::syncToken.eq(&sync.sync_token))
+                .execute(db)
+                .await;
+
+            if let Err(e) = res {
+                error!("Unable to save sync token: {}", e);
+            }
+        }
+
+        // Events before the sync, the first sync after a restart has nothing to compare with
+        let changelog_until = horizon
+            .filter(|_| {
+                guild_calendars
+                    .iter()
+                    .any(|guild_calendar| guild_calendar.changelog)
+            })
+            .map(|horizon| horizon.min(until));
+        let cached_events = self.events_cache.entry(calendar.id).or_default();
+        let previous_events: Vec<CalendarEvent> = match changelog_until {
+            Some(_) => cached_events.values().cloned().collect(),
+            None => vec![],
+        };
+        // Channels waiting for this calendar's first sync are updated even if it has no events
+        let mut changed = sync.changes.apply(cached_events) || horizon.is_none();
+
+        // Events that are over are not displayed anymore
+        let now = Utc::now();
+        let count = cached_events.len();
+        cached_events.retain(|_, event| is_upcoming(event, now, &LATEST_TIMEZONE));
+        changed |= cached_events.len() != count;
+
+        trace!("changed: {}", changed);
+
+        if let Ok(mut synced_events) = self.synced_events.lock() {
+            synced_events.insert(
+                calendar.id,
+                SyncedEvents {
+                    events: cached_events.values().cloned().collect(),
+                    until: fetch_until,
+                    fresh_until: now + poll_interval,
+                },
+            );
+        }
+
+        // Reminders follow the events even when the messages don't need an update
+        if let Err(e) =
+            sync_reminders(db, calendar, &guild_calendars, cached_events, fetch_until).await
+        {
+            error!(
+                "Unable to schedule reminders of calendar {}: {:?}",
+                calendar.id, e
+            );
+        }
+
+        let mirrors = match mirrored_events(db, calendar, &guild_calendars, cached_events).await {
+            Ok(mirrors) => mirrors,
+            Err(e) => {
+                error!(
+                    "Unable to mirror events of calendar {}: {:?}",
+                    calendar.id, e
+                );
+                vec![]
+            }
+        };
+
+        // Only the events that were complete in the cache before the sync are compared
+        let changes = match changelog_until {
+            Some(changelog_until) if changed => EventChange::diff(
+                events_in_any_timezone(previous_events, now, changelog_until),
+                events_in_any_timezone(
+                    cached_events.values().cloned().collect(),
+                    now,
+                    changelog_until,
+                ),
+                cached_events,
+            ),
+            _ => vec![],
+        };
+
+        let event_count =
+            events_in_any_timezone(cached_events.values().cloned().collect(), now, until).len();
+        for mirror in mirrors {
+            self.scheduled_events_tx
+                .send(mirror)
+                .await
+                .map_err(|e| anyhow!("Unable to send scheduled events: {}", e))?;
+        }
+        if !changes.is_empty() {
+            if let Err(e) = self
+                .send_changelogs(db, calendar, &guild_calendars, changes)
+                .await
+            {
+                error!(
+                    "Unable to send changelog of calendar {}: {:?}",
+                    calendar.id, e
+                );
+            }
+        }
+        if !changed && !forced_update {
+            debug!("No new events");
+            return Ok(event_count);
+        }
+
+        // if it is a forced update only update these channels
+        if forced_update {
+            guild_calendars = guild_calendars
+                .into_iter()
+                .filter(|guild_calendar| guild_calendar.forceUpdate)
+                .collect::<Vec<GuildCalendar>>();
+        }
+
+        // Channels merge the events of every calendar they display
+        let channel_ids: Vec<String> = guild_calendars
+            .iter()
+            .map(|guild_calendar| guild_calendar.channelId.clone())
+            .collect();
+        let channel_calendars = guilds_calendars::table
+            .inner_join(calendars::table)
+            .inner_join(guilds::table)
+            .filter(guilds_calendars::channelId.eq_any(&channel_ids))
+            .select((GuildCalendar::as_select(), guilds::locale))
+            .load::<(GuildCalendar, Option<String>)>(db)
+            .await?;
+
+        let filters = channel_filters(db, &channel_ids).await?;
+
+        let mut channels: BTreeMap<String, Vec<GuildCalendar>> = BTreeMap::new();
+        let mut locales: BTreeMap<String, Locale> = BTreeMap::new();
+        for (guild_calendar, locale) in channel_calendars {
+            let locale = locale.and_then(|locale| locale.parse().ok());
+            locales.insert(guild_calendar.channelId.clone(), locale.unwrap_or_default());
+            channels
+                .entry(guild_calendar.channelId.clone())
+                .or_default()
+                .push(guild_calendar);
+        }
+
+        for (channel_id, channel_calendars) in channels {
+            // Calendars that weren't synced yet since the start would be missing from the channel
+            if let Some(guild_calendar) = channel_calendars.iter().find(|guild_calendar| {
+                !self.sync_horizons.contains_key(&guild_calendar.calendar_id)
+            }) {
+                debug!(
+                    "Waiting for calendar {} to be synced before updating channel {}",
+                    guild_calendar.calendar_id, channel_id
+                );
+                continue;
+            }
+
+            let locale = locales.get(&channel_id).copied().unwrap_or_default();
+            let channel_id = match channel_id.parse::<u64>() {
+                Ok(channel_id) => channel_id,
+                Err(e) => {
+                    error!("Unable to parse channel id: {:?}", e);
+                    continue;
+                }
+            };
+            let msg_ids = channel_calendars
+                .iter()
+                .map(|guild_calendar| &guild_calendar.messageIds)
+                .find(|message_ids| !message_ids.is_empty())
+                .into_iter()
+                .flatten()
+                .filter_map(|val| match val.parse::<u64>() {
+                    Err(e) => {
+                        warn!("Unable to parse message id: {:?}", e);
+                        None
+                    }
+                    Ok(parsed_val) => Some(parsed_val),
+                })
+                .collect();
+
+            // Calendars of a channel share its settings
+            let options = match CalendarOptions::try_from(channel_calendars[0].clone()) {
+                Ok(options) => CalendarOptions { locale, ..options },
+                Err(e) => {
+                    error!("Unable to convert CalendarOptions: {:?}", e);
+                    continue;
+                }
+            };
+
+            let until = Utc::now() + display_window(&channel_calendars);
+            let timezone = options.timezone;
+
+            let events = channel_calendars
+                .iter()
+                .flat_map(|guild_calendar| {
+                    let filters = filters
+                        .get(&(guild_calendar.calendar_id, guild_calendar.channelId.clone()))
+                        .map_or(&[][..], Vec::as_slice);
+                    self.events_cache
+                        .get(&guild_calendar.calendar_id)
+                        .into_iter()
+                        .flat_map(|events| events.values())
+                        .filter(|event| EventFilter::keep(filters, event))
+                        .cloned()
+                        .map(|event| CalendarEvent {
+                            calendar_label: guild_calendar.label.clone(),
+                            ..event
+                        })
+                })
+                .collect();
+
+            sender
+                .send(UpdateCalendarEvent {
+                    discord_channel_and_message_ids: vec![(channel_id, msg_ids)],
+                    calendar_id: cal_id.clone(),
+                    calendar_options: options,
+                    new_events: upcoming_events(events, until, &timezone),
+                })
+                .await
+                .map_err(|e| anyhow!("Unable to send events: {}", e))?;
+        }
+
+        // If was forced update change to false in db
+        if forced_update {
+            let res = diesel::update(GuildCalendar::belonging_to(calendar))
+                .set(guilds_calendars::forceUpdate.eq(false))
+                .execute(db)
+                .await;
+
+            if let Err(e) = res {
+                error!("Unable to change forceUpdate to false: {}", e);
+            }
+        }
+
+        Ok(event_count)
+    }
+
+    /// Sends the changes of a calendar to the channels with a changelog
+    async fn send_changelogs(
+        &self,
+        db: &mut AsyncPgConnection,
+        calendar: &Calendar,
+        guild_calendars: &[GuildCalendar],
+        changes: Vec<EventChange>,
+    ) -> Result<()> {
+        let guild_calendars: Vec<&GuildCalendar> = guild_calendars
+            .iter()
+            .filter(|guild_calendar| guild_calendar.changelog)
+            .collect();
+        let channel_ids: Vec<String> = guild_calendars
+            .iter()
+            .map(|guild_calendar| guild_calendar.channelId.clone())
+            .collect();
+        let guild_ids: Vec<i32> = guild_calendars
+            .iter()
+            .map(|guild_calendar| guild_calendar.guild_id)
+            .collect();
+        let filters = channel_filters(db, &channel_ids).await?;
+        let locales = guild_locales(db, &guild_ids).await;
+
+        for guild_calendar in guild_calendars {
+            let filters = filters
+                .get(&(calendar.id, guild_calendar.channelId.clone()))
+                .map_or(&[][..], Vec::as_slice);
+            let changes: Vec<EventChange> = changes
+                .iter()
+                .filter(|change| change.keep(filters))
+                .map(|change| change.clone().with_label(guild_calendar.label.clone()))
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+
+            let channel_id = match guild_calendar
+                .changelogThreadId
+                .as_ref()
+                .unwrap_or(&guild_calendar.channelId)
+                .parse::<u64>()
+            {
+                Ok(channel_id) => channel_id,
+                Err(e) => {
+                    error!("Unable to parse changelog channel id: {:?}", e);
+                    continue;
+                }
+            };
+            let locale = locales
+                .get(&guild_calendar.guild_id)
+                .copied()
+                .unwrap_or_default();
+            let options = match CalendarOptions::try_from(guild_calendar.clone()) {
+                Ok(options) => CalendarOptions { locale, ..options },
+                Err(e) => {
+                    error!("Unable to convert CalendarOptions: {:?}", e);
+                    continue;
+                }
+            };
+
+            debug!(
+                "Sending {} changes of calendar {} to {}",
+                changes.len(),
+                calendar.id,
+                channel_id
+            );
+            self.changelog_tx
+                .send(ChangelogEvent {
+                    channel_id,
+                    changes,
+                    calendar_options: options,
+                })
+                .await
+                .map_err(|e| anyhow!("Unable to send changelog: {}", e))?;
+        }
+
+        Ok(())
+    }
+}
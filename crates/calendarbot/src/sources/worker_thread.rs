@@ -0,0 +1,175 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+
+use crate::events::CalendarCommands;
+use crate::sources::{upcoming_events, CalendarSources};
+use anyhow::anyhow;
+use google_calendar3::chrono::Utc;
+use log::{info, trace};
+use std::sync::Arc;
+use tokio::sync::mpsc::Receiver;
+
+impl CalendarSources {
+    pub(crate) fn new_worker_thread(self, mut rcv: Receiver<CalendarCommands>) -> Self {
+        let sources = Arc::new(self.clone());
+        info!("Starting worker thread");
+        tokio::spawn(async move {
+            while let Some(cmd) = rcv.recv().await {
+                trace!("Received command: {:?}", cmd);
+                // Slow sources would hold up the commands of every other calendar
+                let sources = sources.clone();
+                tokio::spawn(async move { sources.handle_command(cmd).await });
+            }
+        });
+        self
+    }
+
+    async fn handle_command(&self, cmd: CalendarCommands) {
+        match cmd {
+            CalendarCommands::ResolveCalendarId {
+                source,
+                calendar_id,
+                auth,
+                resp,
+            } => {
+                let result = match self.get_source(&source) {
+                    Some(source) => {
+                        source
+                            .resolve_calendar_id(&calendar_id, auth.as_ref())
+                            .await
+                    }
+                    None => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result);
+            }
+            CalendarCommands::GetPollStatus { calendar_id, resp } => {
+                let status = self
+                    .poll_statuses
+                    .lock()
+                    .ok()
+                    .and_then(|statuses| statuses.get(&calendar_id).copied());
+                let _ = resp.send(Ok(status));
+            }
+            CalendarCommands::GetUpcomingEvents {
+                id,
+                source,
+                calendar_id,
+                auth,
+                until,
+                timezone,
+                resp,
+            } => {
+                let cached = self.synced_events.lock().ok().and_then(|synced| {
+                    synced
+                        .get(&id)
+                        .filter(|synced| synced.fresh_until > Utc::now() && synced.until >= until)
+                        .map(|synced| synced.events.clone())
+                });
+                let result = match (cached, self.get_source(&source)) {
+                    (Some(events), _) => {
+                        trace!("Using the cached events of calendar {}", id);
+                        Ok(events)
+                    }
+                    (None, Some(source)) => {
+                        source.get_events(&calendar_id, auth.as_ref(), until).await
+                    }
+                    (None, None) => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result.map(|events| upcoming_events(events, until, &timezone)));
+            }
+            CalendarCommands::SearchEvents {
+                source,
+                calendar_id,
+                auth,
+                text,
+                from,
+                until,
+                resp,
+            } => {
+                let result = match self.get_source(&source) {
+                    Some(source) => {
+                        source
+                            .search_events(&calendar_id, auth.as_ref(), &text, from, until)
+                            .await
+                    }
+                    None => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result);
+            }
+            CalendarCommands::GetBusyPeriods {
+                source,
+                calendar_id,
+                auth,
+                from,
+                until,
+                resp,
+            } => {
+                let result = match self.get_source(&source) {
+                    Some(source) => {
+                        source
+                            .busy_periods(&calendar_id, auth.as_ref(), from, until)
+                            .await
+                    }
+                    None => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result);
+            }
+            CalendarCommands::CreateEvent {
+                source,
+                calendar_id,
+                auth,
+                event,
+                resp,
+            } => {
+                let result = match self.get_source(&source) {
+                    Some(source) => {
+                        source
+                            .create_event(&calendar_id, auth.as_ref(), &event)
+                            .await
+                    }
+                    None => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result);
+            }
+            CalendarCommands::UpdateEvent {
+                source,
+                calendar_id,
+                auth,
+                event_id,
+                event,
+                resp,
+            } => {
+                let result = match self.get_source(&source) {
+                    Some(source) => {
+                        source
+                            .update_event(&calendar_id, auth.as_ref(), &event_id, &event)
+                            .await
+                    }
+                    None => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result);
+            }
+            CalendarCommands::DeleteEvent {
+                source,
+                calendar_id,
+                auth,
+                event_id,
+                resp,
+            } => {
+                let result = match self.get_source(&source) {
+                    Some(source) => {
+                        source
+                            .delete_event(&calendar_id, auth.as_ref(), &event_id)
+                            .await
+                    }
+                    None => Err(anyhow!("No source registered for {}", source)),
+                };
+                let _ = resp.send(result);
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::types::{CalendarEvent, EventTime};
+use chrono_tz::Tz;
+use google_calendar3::chrono::{
+    DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc,
+};
+
+/// Span of time, the end is exclusive
+pub type Period = (DateTime<Utc>, DateTime<Utc>);
+
+/// Returns when the events keep their calendar busy. All-day events don't,
+/// like in Google Calendar, nor do declined events
+pub fn busy_periods(events: &[CalendarEvent]) -> Vec<Period> {
+    events
+        .iter()
+        .filter(|event| !event.declined)
+        .filter_map(|event| match (event.start, event.end) {
+            (Some(EventTime::DateTime(start)), Some(EventTime::DateTime(end))) => {
+                Some((start, end))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Days and hours to look for free slots in
+pub struct WorkingHours {
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    /// Start and end of the working day, in `timezone`
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub weekends: bool,
+    pub timezone: Tz,
+}
+
+impl WorkingHours {
+    /// Working hours of every day, weekends are skipped unless included
+    fn periods(&self) -> Vec<Period> {
+        let instant = |day: NaiveDate, time: NaiveTime| {
+            self.timezone
+                .from_local_datetime(&day.and_time(time))
+                .earliest()
+                .map(|date_time| date_time.to_utc())
+        };
+
+        self.first_day
+            .iter_days()
+            .take_while(|day| *day <= self.last_day)
+            .filter(|day| self.weekends || day.weekday().number_from_monday() <= 5)
+            .filter_map(|day| Some((instant(day, self.start)?, instant(day, self.end)?)))
+            .collect()
+    }
+
+    /// Returns the periods of at least `duration` in the working hours that are not busy
+    /// and not over at `now`
+    pub fn free_slots(
+        &self,
+        mut busy: Vec<Period>,
+        duration: TimeDelta,
+        now: DateTime<Utc>,
+    ) -> Vec<Period> {
+        busy.sort();
+
+        let mut slots = vec![];
+        for (start, end) in self.periods() {
+            let mut free_from = start.max(now);
+            for &(busy_start, busy_end) in busy
+                .iter()
+                .filter(|(busy_start, busy_end)| *busy_end > start && *busy_start < end)
+            {
+                if busy_start - free_from >= duration {
+                    slots.push((free_from, busy_start));
+                }
+                free_from = free_from.max(busy_end);
+            }
+            if end - free_from >= duration {
+                slots.push((free_from, end));
+            }
+        }
+
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEZONE: Tz = chrono_tz::Europe::Zurich;
+
+    /// Time of a day of October 2026 in Zurich
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        TIMEZONE
+            .with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .to_utc()
+    }
+
+    fn hours(first_day: u32, last_day: u32) -> WorkingHours {
+        WorkingHours {
+            first_day: NaiveDate::from_ymd_opt(2026, 10, first_day).unwrap(),
+            last_day: NaiveDate::from_ymd_opt(2026, 10, last_day).unwrap(),
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            weekends: false,
+            timezone: TIMEZONE,
+        }
+    }
+
+    #[test]
+    fn slots_between_busy_periods() {
+        // Overlapping periods of several calendars
+        let busy = vec![
+            (at(14, 10, 0), at(14, 11, 0)),
+            (at(14, 8, 0), at(14, 9, 30)),
+            (at(14, 10, 30), at(14, 12, 0)),
+            (at(14, 16, 30), at(14, 18, 0)),
+        ];
+        assert_eq!(
+            hours(14, 14).free_slots(busy, TimeDelta::minutes(30), at(14, 0, 0)),
+            vec![
+                (at(14, 9, 30), at(14, 10, 0)),
+                (at(14, 12, 0), at(14, 16, 30))
+            ]
+        );
+    }
+
+    #[test]
+    fn short_slots_are_skipped() {
+        let busy = vec![(at(14, 10, 0), at(14, 16, 30))];
+        assert_eq!(
+            hours(14, 14).free_slots(busy, TimeDelta::hours(1), at(14, 0, 0)),
+            vec![(at(14, 9, 0), at(14, 10, 0))]
+        );
+    }
+
+    #[test]
+    fn weekends_and_past_hours_are_skipped() {
+        // From Friday 16 to Monday 19, it is Friday 15:00
+        assert_eq!(
+            hours(16, 19).free_slots(vec![], TimeDelta::hours(1), at(16, 15, 0)),
+            vec![
+                (at(16, 15, 0), at(16, 17, 0)),
+                (at(19, 9, 0), at(19, 17, 0))
+            ]
+        );
+    }
+}
@@ -4,19 +4,105 @@ Calendarbot  Copyright (C) 2023 Zbinden Yohan
 This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
+use crate::i18n::{Locale, Text};
 use crate::models::GuildCalendar;
+use crate::types::{instance_id, EmbedTemplate, EventPlaceholders, Recurrence, DEFAULT_DAY_FORMAT};
 use anyhow::anyhow;
 use chrono_tz::Tz;
-use google_calendar3::api::Event;
-use google_calendar3::chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Utc};
+use google_calendar3::api::{Event, EventDateTime};
+use google_calendar3::chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, Utc};
 use log::warn;
 use poise::serenity_prelude as serenity;
+use regex::Regex;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 use workdays::WorkCalendar;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, poise::ChoiceParameter)]
 pub enum CalendarEventSource {
+    #[name = "Google Calendar"]
     GoogleCalendar,
+    #[name = "ICS feed"]
+    Ics,
+    #[name = "CalDAV"]
+    CalDav,
+}
+
+impl CalendarEventSource {
+    /// Guesses the source of a calendar from the identifier given by the user,
+    /// CalDAV calendars can't be told apart from ICS feeds and have to be chosen explicitly
+    pub fn from_calendar_id(calendar_id: &str) -> Self {
+        let calendar_id = calendar_id.to_lowercase();
+        if ["http://", "https://", "webcal://"]
+            .iter()
+            .any(|scheme| calendar_id.starts_with(scheme))
+        {
+            CalendarEventSource::Ics
+        } else {
+            CalendarEventSource::GoogleCalendar
+        }
+    }
+
+    /// Whether events can be created, edited and cancelled from Discord
+    pub fn is_writable(self) -> bool {
+        self == CalendarEventSource::GoogleCalendar
+    }
+}
+
+impl fmt::Display for CalendarEventSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalendarEventSource::GoogleCalendar => write!(f, "google"),
+            CalendarEventSource::Ics => write!(f, "ics"),
+            CalendarEventSource::CalDav => write!(f, "caldav"),
+        }
+    }
+}
+
+impl FromStr for CalendarEventSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "google" => Ok(CalendarEventSource::GoogleCalendar),
+            "ics" => Ok(CalendarEventSource::Ics),
+            "caldav" => Ok(CalendarEventSource::CalDav),
+            _ => Err(anyhow!("Unknown calendar source: {}", s)),
+        }
+    }
+}
+
+/// How the events of a channel are displayed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, poise::ChoiceParameter)]
+pub enum DisplayMode {
+    /// Times and summaries in a code block
+    #[name = "Compact"]
+    Compact,
+    /// Markdown with locations, descriptions and links
+    #[name = "Rich"]
+    Rich,
+}
+
+impl fmt::Display for DisplayMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayMode::Compact => write!(f, "compact"),
+            DisplayMode::Rich => write!(f, "rich"),
+        }
+    }
+}
+
+impl FromStr for DisplayMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(DisplayMode::Compact),
+            "rich" => Ok(DisplayMode::Rich),
+            _ => Err(anyhow!("Unknown display mode: {}", s)),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq)]
@@ -25,6 +111,19 @@ pub struct CalendarOptions {
     pub num_of_days: i32,
     pub skip_weekend: bool,
     pub show_if_no_events: bool,
+    pub display_mode: DisplayMode,
+    /// Event times are Discord timestamps, shown in the timezone of each member
+    pub dynamic_timestamps: bool,
+    pub template: EmbedTemplate,
+    /// Language of the guild, set apart since it isn't stored with the calendar settings
+    pub locale: Locale,
+}
+
+impl CalendarOptions {
+    /// Discord doesn't format timestamps inside code blocks
+    fn code_block(&self) -> bool {
+        self.display_mode == DisplayMode::Compact && !self.dynamic_timestamps
+    }
 }
 
 impl PartialEq for CalendarOptions {
@@ -33,6 +132,10 @@ impl PartialEq for CalendarOptions {
             && self.num_of_days == other.num_of_days
             && self.skip_weekend == other.skip_weekend
             && self.show_if_no_events == other.show_if_no_events
+            && self.display_mode == other.display_mode
+            && self.dynamic_timestamps == other.dynamic_timestamps
+            && self.template == other.template
+            && self.locale == other.locale
     }
 }
 
@@ -48,6 +151,10 @@ impl Ord for CalendarOptions {
             .cmp(&other.num_of_days)
             .then_with(|| self.skip_weekend.cmp(&other.skip_weekend))
             .then_with(|| self.show_if_no_events.cmp(&other.show_if_no_events))
+            .then_with(|| self.display_mode.cmp(&other.display_mode))
+            .then_with(|| self.dynamic_timestamps.cmp(&other.dynamic_timestamps))
+            .then_with(|| self.template.cmp(&other.template))
+            .then_with(|| self.locale.cmp(&other.locale))
     }
 }
 
@@ -65,23 +172,397 @@ impl TryFrom<GuildCalendar> for CalendarOptions {
             num_of_days: guild_calendar.nbDisplayedDays,
             show_if_no_events: !guild_calendar.skipEmptyDays,
             skip_weekend: guild_calendar.skipWeekend,
+            display_mode: guild_calendar.displayMode.parse()?,
+            dynamic_timestamps: guild_calendar.dynamicTimestamps,
+            template: EmbedTemplate::from(&guild_calendar),
+            locale: Locale::default(),
         })
     }
 }
 
+/// Start or end of an event
+///
+/// All-day events only carry a date which is the same in every timezone,
+/// while timed events are stored as an instant in UTC.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventTime {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+impl EventTime {
+    /// Returns the day this time falls on in the given timezone
+    pub fn date_naive(&self, timezone: &Tz) -> NaiveDate {
+        match self {
+            EventTime::Date(date) => *date,
+            EventTime::DateTime(date_time) => date_time.with_timezone(timezone).date_naive(),
+        }
+    }
+
+    /// Returns this time as an instant, all-day dates start at midnight in the given timezone
+    pub fn to_utc(&self, timezone: &Tz) -> DateTime<Utc> {
+        match self {
+            EventTime::Date(date) => {
+                let midnight = date.and_time(NaiveTime::MIN);
+                midnight
+                    .and_local_timezone(*timezone)
+                    .earliest()
+                    .map_or_else(|| midnight.and_utc(), |date_time| date_time.to_utc())
+            }
+            EventTime::DateTime(date_time) => *date_time,
+        }
+    }
+
+    fn from_event_date_time(value: EventDateTime) -> Option<Self> {
+        match (value.date_time, value.date) {
+            (Some(date_time), _) => Some(EventTime::DateTime(date_time)),
+            (None, Some(date)) => Some(EventTime::Date(date)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Maximum length of the description shown in the rich view
+const MAX_DESCRIPTION_LENGTH: usize = 100;
+
+/// Discord limits, see https://discord.com/developers/docs/resources/message#embed-object-embed-limits
+const MAX_FIELD_LENGTH: usize = 1024;
+const MAX_FIELDS: usize = 25;
+const MAX_EMBEDS: usize = 10;
+/// Applies to the sum of the titles, field names and values of all embeds of a message
+const MAX_MESSAGE_LENGTH: usize = 6000;
+
+/// Room left in a field for the "+N more" marker
+const MORE_MARKER_LENGTH: usize = 16;
+
+/// Joins the lines of a field, leaving out the last ones with a "+N more" marker
+/// when they exceed the length of a field value
+fn field_value(lines: &[String], code_block: bool, locale: Locale) -> String {
+    let wrapper_length = if code_block { 6 } else { 0 };
+    let wrap = |value: String| {
+        if code_block {
+            format!("```{}```", value)
+        } else {
+            value
+        }
+    };
+
+    let total: usize = lines.iter().map(|line| line.chars().count()).sum();
+    if total + wrapper_length <= MAX_FIELD_LENGTH {
+        return wrap(lines.concat());
+    }
+
+    let max_length = MAX_FIELD_LENGTH - wrapper_length - MORE_MARKER_LENGTH;
+    let mut value = String::new();
+    let mut length = 0;
+    let mut shown = 0;
+    for line in lines {
+        let line_length = line.chars().count();
+        if length + line_length > max_length {
+            // A single line longer than a field is cut rather than hidden
+            if shown == 0 {
+                value = truncate(line, max_length - 1);
+                value.push('\n');
+                shown = 1;
+            }
+            break;
+        }
+        value.push_str(line);
+        length += line_length;
+        shown += 1;
+    }
+    if shown < lines.len() {
+        let count = lines.len() - shown;
+        value.push_str(&format!(
+            "{}\n",
+            Text::MoreEvents.format(locale, &[("count", &count)])
+        ));
+    }
+
+    wrap(value)
+}
+
+/// Splits the fields in messages of up to 10 embeds with up to 25 fields each,
+/// starting a new message when the characters of the current one would exceed the limit
+fn paginate(
+    fields: Vec<(String, String, bool)>,
+    template: &EmbedTemplate,
+    locale: Locale,
+) -> Vec<Vec<serenity::CreateEmbed>> {
+    let title = template
+        .title
+        .as_deref()
+        .unwrap_or(Text::Events.get(locale));
+    // The footer is only on the last message, but it's not known yet which one it is
+    let footer_length = template.footer.as_ref().map_or(0, |f| f.chars().count());
+    let mut messages: Vec<Vec<Vec<(String, String, bool)>>> = vec![vec![vec![]]];
+    let mut length = title.chars().count() + footer_length;
+
+    for field in fields {
+        let field_length = field.0.chars().count() + field.1.chars().count();
+        let current = messages.len() - 1;
+        let field_count = messages[current].last().map_or(0, Vec::len);
+
+        if length + field_length > MAX_MESSAGE_LENGTH
+            || (field_count == MAX_FIELDS && messages[current].len() == MAX_EMBEDS)
+        {
+            messages.push(vec![vec![]]);
+            length = footer_length;
+        } else if field_count == MAX_FIELDS {
+            messages[current].push(vec![]);
+        }
+
+        length += field_length;
+        if let Some(embed) = messages.last_mut().and_then(|message| message.last_mut()) {
+            embed.push(field);
+        }
+    }
+
+    let message_count = messages.len();
+    messages
+        .into_iter()
+        .enumerate()
+        .map(|(message_index, message)| {
+            let embed_count = message.len();
+            message
+                .into_iter()
+                .enumerate()
+                .map(|(embed_index, fields)| {
+                    let mut embed = serenity::CreateEmbed::new()
+                        .color(template.color)
+                        .fields(fields);
+                    if message_index == 0 && embed_index == 0 {
+                        embed = embed.title(title);
+                    }
+                    if let (true, Some(footer)) = (
+                        message_index == message_count - 1 && embed_index == embed_count - 1,
+                        &template.footer,
+                    ) {
+                        embed = embed.footer(serenity::CreateEmbedFooter::new(footer));
+                    }
+                    embed
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Escapes the characters Discord would read as markdown
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '[' | ']' | '<' | '>'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Cuts a text to `max` characters, ending with an ellipsis when shortened
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
 #[derive(Clone, Debug)]
 pub struct CalendarEvent {
     pub id: String,
     pub summary: String,
     pub description: String,
-    pub start: Option<DateTime<Utc>>,
-    pub end: Option<DateTime<Utc>>,
+    pub location: Option<String>,
+    /// Link to the event in the calendar application
+    pub html_link: Option<String>,
+    /// Video call link given by the source (Google Meet, Teams, ...)
+    pub conference_link: Option<String>,
+    /// Google colour id, or the COLOR of ICS events
+    pub color_id: Option<String>,
+    /// Email address of the organiser
+    pub organizer: Option<String>,
+    /// The owner of the calendar declined the event
+    pub declined: bool,
+    pub start: Option<EventTime>,
+    pub end: Option<EventTime>,
     pub event_source: CalendarEventSource,
+    /// Set on the series of recurring events that sources don't expand themselves
+    pub recurrence: Option<Recurrence>,
+    /// Label of the calendar the event comes from, when a channel shows several calendars
+    pub calendar_label: Option<String>,
 }
 
 impl CalendarEvent {
-    pub fn to_embed(events: Vec<Self>, options: CalendarOptions) -> serenity::CreateEmbed {
+    pub fn is_all_day(&self) -> bool {
+        matches!(self.start, Some(EventTime::Date(_)))
+    }
+
+    /// Returns whether the summary, description or location contain the text, ignoring case
+    pub fn contains_text(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        [
+            Some(self.summary.as_str()),
+            Some(self.description.as_str()),
+            self.location.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&text))
+    }
+
+    /// Returns the summary prefixed by the label of its calendar
+    pub fn display_summary(&self) -> String {
+        match &self.calendar_label {
+            Some(label) => format!("{} {}", label, self.summary),
+            None => self.summary.clone(),
+        }
+    }
+
+    /// Returns the video call link of the event, looking for Meet, Zoom or Teams
+    /// links in the location and the description when the source doesn't give one
+    pub fn join_link(&self) -> Option<String> {
+        static JOIN_LINK_REGEX: &str = r"https://([\w-]+\.)*(meet\.google\.com|zoom\.us|teams\.microsoft\.com|teams\.live\.com)/[^\s<>\x22')\]]*";
+
+        if let Some(link) = &self.conference_link {
+            return Some(link.clone());
+        }
+
+        let re = Regex::new(JOIN_LINK_REGEX).unwrap();
+        [self.location.as_deref(), Some(self.description.as_str())]
+            .into_iter()
+            .flatten()
+            .find_map(|text| re.find(text))
+            .map(|link| link.as_str().to_string())
+    }
+
+    /// Returns the first line of the description without html tags
+    pub fn short_description(&self) -> Option<String> {
+        static HTML_TAG_REGEX: &str = r"<[^>]*>";
+
+        let re = Regex::new(HTML_TAG_REGEX).unwrap();
+        // Google descriptions use <br> for new lines
+        let description = self.description.replace("<br>", "\n");
+        let line = re
+            .replace_all(&description, "")
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())?
+            .replace("&nbsp;", " ")
+            .replace("&amp;", "&");
+
+        Some(truncate(&line, MAX_DESCRIPTION_LENGTH))
+    }
+
+    /// Formats a line of the compact view, `time` is either a time range or "All day"
+    fn compact_line(&self, time: &str, code_block: bool) -> String {
+        if code_block {
+            format!("{:<13} | {}\n", time, self.display_summary())
+        } else {
+            format!("{} | {}\n", time, escape_markdown(&self.display_summary()))
+        }
+    }
+
+    /// Formats the lines of the rich view: the summary linking to the event,
+    /// then the location, the join link and the description
+    fn rich_lines(&self, time: &str, locale: Locale) -> String {
+        let summary = escape_markdown(&self.display_summary());
+        let mut lines = match &self.html_link {
+            Some(link) => format!("**{}** [{}]({})\n", time, summary, link),
+            None => format!("**{}** {}\n", time, summary),
+        };
+
+        let join_link = self.join_link();
+        let mut details = vec![];
+        // Locations that are only the meeting link are shown as the join link
+        if let Some(location) = self
+            .location
+            .as_ref()
+            .filter(|location| Some(location.as_str()) != join_link.as_deref())
+        {
+            details.push(format!("📍 {}", escape_markdown(location)));
+        }
+        if let Some(link) = join_link {
+            details.push(format!("[{}]({})", Text::Join.get(locale), link));
+        }
+        if !details.is_empty() {
+            lines.push_str(&format!("{}\n", details.join(" · ")));
+        }
+
+        if let Some(description) = self.short_description() {
+            lines.push_str(&format!("*{}*\n", escape_markdown(&description)));
+        }
+
+        lines
+    }
+
+    /// Formats the line of the event with the template of the channel, or with its display mode,
+    /// `start` and `end` are empty for all-day events
+    fn format_line(&self, time: &str, start: &str, end: &str, options: &CalendarOptions) -> String {
+        let escape = |text: &str| {
+            if options.code_block() {
+                text.to_string()
+            } else {
+                escape_markdown(text)
+            }
+        };
+        let placeholders = EventPlaceholders {
+            time,
+            start,
+            end,
+            summary: &escape(&self.display_summary()),
+            location: &escape(self.location.as_deref().unwrap_or_default()),
+            description: &escape(&self.short_description().unwrap_or_default()),
+            link: self.html_link.as_deref().unwrap_or_default(),
+        };
+        if let Some(line) = options.template.format_event(&placeholders) {
+            return line;
+        }
+
+        match options.display_mode {
+            DisplayMode::Compact => self.compact_line(time, options.code_block()),
+            DisplayMode::Rich => self.rich_lines(time, options.locale),
+        }
+    }
+
+    /// Expands a recurring event into its occurrences starting before `until`,
+    /// other events are returned as is
+    pub fn expand(self, until: DateTime<Utc>) -> Vec<CalendarEvent> {
+        let (Some(recurrence), Some(start), Some(end)) = (&self.recurrence, self.start, self.end)
+        else {
+            return vec![self];
+        };
+        // All-day events last whole days, whatever the timezone
+        let duration = end.to_utc(&Tz::UTC) - start.to_utc(&Tz::UTC);
+
+        recurrence
+            .occurrences(start, until)
+            .into_iter()
+            .map(|start| CalendarEvent {
+                id: instance_id(&self.id, &start),
+                start: Some(start),
+                end: Some(match start {
+                    EventTime::Date(date) => EventTime::Date(date + duration),
+                    EventTime::DateTime(date_time) => EventTime::DateTime(date_time + duration),
+                }),
+                recurrence: None,
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Builds the embeds showing the events, split in several messages when they
+    /// don't fit in the limits of a single one
+    pub fn to_embed(
+        events: Vec<Self>,
+        options: CalendarOptions,
+    ) -> Vec<Vec<serenity::CreateEmbed>> {
         let mut sorted: BTreeMap<(NaiveDate, NaiveDate), Vec<CalendarEvent>> = BTreeMap::new();
+        let mut all_day: BTreeMap<NaiveDate, Vec<CalendarEvent>> = BTreeMap::new();
         let mut fields: Vec<(String, String, bool)> = vec![];
         let today_date = Utc::now().with_timezone(&options.timezone).date_naive();
         let mut calendar = WorkCalendar::new();
@@ -96,38 +577,49 @@ impl CalendarEvent {
         }
 
         for ele in events {
-            let ele_clone = ele.clone();
-
-            let start_date = ele_clone.start;
-            let end_date = ele_clone.end;
+            match (ele.start, ele.end) {
+                (Some(EventTime::Date(start_date)), Some(EventTime::Date(end_date))) => {
+                    // The end date of all-day events is exclusive
+                    let end_date = (end_date - TimeDelta::days(1)).max(start_date);
 
-            if let (None, None) = (start_date.as_ref(), end_date.as_ref()) {
-                warn!(
-                    "Event start date or event end date is None {:?}",
-                    ele.clone()
-                );
-                continue;
-            }
+                    // Multi-day events are shown on every day they cover
+                    let mut date = start_date.max(today_date);
+                    while date <= end_date.min(last_date) {
+                        if !(options.skip_weekend && date.weekday().number_from_monday() > 5) {
+                            all_day.entry(date).or_default().push(ele.clone());
+                            sorted.entry((date, date)).or_default();
+                        }
+                        date += TimeDelta::days(1);
+                    }
+                }
+                (Some(EventTime::DateTime(start_date)), Some(EventTime::DateTime(end_date))) => {
+                    let start_date = start_date.with_timezone(&options.timezone);
+                    let end_date = end_date.with_timezone(&options.timezone);
 
-            let start_date = start_date.unwrap().with_timezone(&options.timezone);
-            let end_date = end_date.unwrap().with_timezone(&options.timezone);
+                    // Skip weekend
+                    if options.skip_weekend
+                        && (start_date.weekday().number_from_monday() > 5
+                            || end_date.weekday().number_from_monday() > 5)
+                    {
+                        continue;
+                    }
 
-            // Skip weekend
-            if options.skip_weekend
-                && (start_date.weekday().number_from_monday() > 5
-                    || end_date.weekday().number_from_monday() > 5)
-            {
-                continue;
-            }
+                    if start_date.date_naive() > last_date {
+                        continue;
+                    }
 
-            if start_date.date_naive() > last_date {
-                continue;
+                    sorted
+                        .entry((start_date.date_naive(), end_date.date_naive()))
+                        .or_default()
+                        .push(ele);
+                }
+                _ => {
+                    warn!(
+                        "Event start date or event end date is None or inconsistent {:?}",
+                        ele
+                    );
+                }
             }
-
-            sorted
-                .entry((start_date.date_naive(), end_date.date_naive()))
-                .or_default()
-                .push(ele);
         }
 
         // Process show if no events
@@ -165,12 +657,21 @@ impl CalendarEvent {
         let mut passed_dates = vec![];
 
         for ((start_date, end_date), events) in sorted.iter() {
-            let mut field = String::new();
+            // One entry per event, so that whole events are left out when the field is too long
+            let mut lines = vec![];
+
+            // All-day events are only listed in single day fields
+            let all_day_events = if start_date == end_date {
+                all_day.get(start_date)
+            } else {
+                None
+            };
+            let is_empty = events.is_empty() && all_day_events.is_none_or(Vec::is_empty);
 
             // Skip if we already passed this day and there are no events (it means that this was added for show_if_no_events)
             if (passed_dates.contains(start_date) || passed_dates.contains(end_date))
                 && options.show_if_no_events
-                && events.is_empty()
+                && is_empty
             {
                 continue;
             }
@@ -180,49 +681,71 @@ impl CalendarEvent {
                 passed_dates.push(*end_date);
             }
 
+            for event in all_day_events.into_iter().flatten() {
+                lines.push(event.format_line(Text::AllDay.get(options.locale), "", "", &options));
+            }
+
             for event in events {
-                if event.start.is_none() {
-                    warn!("Event start is None");
+                let (Some(EventTime::DateTime(start)), Some(EventTime::DateTime(end))) =
+                    (event.start, event.end)
+                else {
+                    warn!("Event start or end is not a date time");
                     continue;
                 };
 
-                if event.end.is_none() {
-                    warn!("Event end is None");
-                    continue;
+                // The fields are still grouped by day in the timezone of the channel
+                let (start_time, end_time, time) = if options.dynamic_timestamps {
+                    let start_time = format!("<t:{}:t>", start.timestamp());
+                    let end_time = format!("<t:{}:t>", end.timestamp());
+                    let time = format!(
+                        "{} - {} (<t:{}:R>)",
+                        start_time,
+                        end_time,
+                        start.timestamp()
+                    );
+                    (start_time, end_time, time)
+                } else {
+                    let start_time = start
+                        .with_timezone(&options.timezone)
+                        .format("%H:%M")
+                        .to_string();
+                    let end_time = end
+                        .with_timezone(&options.timezone)
+                        .format("%H:%M")
+                        .to_string();
+                    let time = format!("{} - {}", start_time, end_time);
+                    (start_time, end_time, time)
                 };
-
-                let start = event.start.unwrap().with_timezone(&options.timezone);
-                let end = event.end.unwrap().with_timezone(&options.timezone);
-
-                field.push_str(&format!(
-                    "{} - {} | {}\n",
-                    start.format("%H:%M"),
-                    end.format("%H:%M"),
-                    event.summary.clone()
-                ));
-            }
-            if events.is_empty() {
-                field = String::from("No events");
+                lines.push(event.format_line(&time, &start_time, &end_time, &options));
             }
-            let mut format = String::from("**%A** - %e %B");
-            if start_date.year() != end_date.year() || start_date.year() != Utc::now().year() {
-                format = String::from("%F");
+            if is_empty {
+                lines = vec![Text::NoEvents.get(options.locale).to_string()];
             }
+            let format = match &options.template.day_format {
+                Some(format) => format.clone(),
+                None if start_date.year() != end_date.year()
+                    || start_date.year() != Utc::now().year() =>
+                {
+                    String::from("%F")
+                }
+                None => String::from(DEFAULT_DAY_FORMAT),
+            };
 
-            let mut key = start_date.format(&format.clone()).to_string();
+            let mut key = options.locale.format_date(*start_date, &format);
 
-            if start_date.format("%F").to_string() != end_date.format("%F").to_string() {
-                key.push_str(
-                    &end_date
-                        .format(format!(" // {}", format.clone()).as_str())
-                        .to_string(),
-                );
+            if start_date != end_date {
+                key.push_str(" // ");
+                key.push_str(&options.locale.format_date(*end_date, &format));
             }
 
-            fields.push((key, format!("```{}```", field), false));
+            fields.push((
+                key,
+                field_value(&lines, options.code_block(), options.locale),
+                false,
+            ));
         }
 
-        serenity::CreateEmbed::new().title("Events").fields(fields)
+        paginate(fields, &options.template, options.locale)
     }
 }
 
@@ -231,8 +754,15 @@ impl PartialEq for CalendarEvent {
         self.id == other.id
             && self.summary == other.summary
             && self.description == other.description
+            && self.location == other.location
+            && self.html_link == other.html_link
+            && self.conference_link == other.conference_link
+            && self.color_id == other.color_id
+            && self.organizer == other.organizer
+            && self.declined == other.declined
             && self.start == other.start
             && self.end == other.end
+            && self.recurrence == other.recurrence
     }
 }
 
@@ -248,25 +778,276 @@ impl TryFrom<Event> for CalendarEvent {
         let summary = value.summary.unwrap_or_default();
         let description = value.description.unwrap_or_default();
 
-        let start = match value.start {
-            Some(start) => start.date_time,
-            None => None,
-        };
+        // Meet links are in hangoutLink, other conference solutions only have entry points
+        let conference_link = value.hangout_link.or_else(|| {
+            value
+                .conference_data?
+                .entry_points?
+                .into_iter()
+                .find(|entry_point| entry_point.entry_point_type.as_deref() == Some("video"))?
+                .uri
+        });
 
-        let end = match value.end {
-            Some(end) => end.date_time,
-            None => None,
-        };
+        // The attendee marked as self is the owner of the calendar
+        let declined = value.attendees.iter().flatten().any(|attendee| {
+            attendee.self_ == Some(true) && attendee.response_status.as_deref() == Some("declined")
+        });
+
+        let start = value.start.and_then(EventTime::from_event_date_time);
+        let end = value.end.and_then(EventTime::from_event_date_time);
 
         Ok(Self {
             id,
             summary,
             description,
+            location: value.location.filter(|location| !location.is_empty()),
+            html_link: value.html_link,
+            conference_link,
+            color_id: value.color_id,
+            organizer: value.organizer.and_then(|organizer| organizer.email),
+            declined,
             start,
             end,
             event_source: CalendarEventSource::GoogleCalendar,
+            recurrence: None,
+            calendar_label: None,
         })
     }
 
     type Error = anyhow::Error;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn event(id: &str, start: EventTime, end: EventTime) -> CalendarEvent {
+        CalendarEvent {
+            id: id.to_string(),
+            summary: id.to_string(),
+            description: String::new(),
+            location: None,
+            html_link: None,
+            conference_link: None,
+            color_id: None,
+            organizer: None,
+            declined: false,
+            start: Some(start),
+            end: Some(end),
+            event_source: CalendarEventSource::Ics,
+            recurrence: None,
+            calendar_label: None,
+        }
+    }
+
+    fn options() -> CalendarOptions {
+        CalendarOptions {
+            timezone: chrono_tz::Europe::Zurich,
+            num_of_days: 7,
+            skip_weekend: false,
+            show_if_no_events: false,
+            display_mode: DisplayMode::Compact,
+            dynamic_timestamps: false,
+            template: EmbedTemplate::default(),
+            locale: Locale::default(),
+        }
+    }
+
+    /// Name and value of the fields of every embed
+    fn fields(messages: Vec<Vec<serenity::CreateEmbed>>) -> Vec<(String, String)> {
+        messages
+            .into_iter()
+            .flatten()
+            .flat_map(|embed| {
+                let embed = serde_json::to_value(embed).unwrap();
+                let fields = match &embed["fields"] {
+                    Value::Array(fields) => fields.clone(),
+                    _ => vec![],
+                };
+                fields.into_iter().map(|field| {
+                    (
+                        field["name"].as_str().unwrap().to_string(),
+                        field["value"].as_str().unwrap().to_string(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn today() -> NaiveDate {
+        Utc::now()
+            .with_timezone(&chrono_tz::Europe::Zurich)
+            .date_naive()
+    }
+
+    #[test]
+    fn dates_start_at_midnight_in_the_timezone() {
+        let date = EventTime::Date(NaiveDate::from_ymd_opt(2026, 10, 20).unwrap());
+        assert_eq!(
+            date.to_utc(&chrono_tz::Pacific::Auckland),
+            DateTime::parse_from_rfc3339("2026-10-19T11:00:00Z").unwrap()
+        );
+        assert_eq!(
+            date.to_utc(&Tz::UTC),
+            DateTime::parse_from_rfc3339("2026-10-20T00:00:00Z").unwrap()
+        );
+    }
+
+    #[test]
+    fn all_day_events() {
+        let today = today();
+        let events = vec![event(
+            "Holiday",
+            EventTime::Date(today),
+            EventTime::Date(today + TimeDelta::days(1)),
+        )];
+
+        let fields = fields(CalendarEvent::to_embed(events, options()));
+        assert_eq!(fields.len(), 1);
+        assert!(fields[0].1.contains("All day"));
+        assert!(fields[0].1.contains("Holiday"));
+    }
+
+    #[test]
+    fn multi_day_events() {
+        let today = today();
+        // The end date is exclusive, the event covers three days
+        let events = vec![
+            event(
+                "Conference",
+                EventTime::Date(today),
+                EventTime::Date(today + TimeDelta::days(3)),
+            ),
+            event(
+                "Trip",
+                EventTime::Date(today + TimeDelta::days(5)),
+                EventTime::Date(today + TimeDelta::days(10)),
+            ),
+        ];
+
+        let fields = fields(CalendarEvent::to_embed(events, options()));
+        let days_with = |summary: &str| {
+            fields
+                .iter()
+                .filter(|(_, value)| value.contains(summary))
+                .count()
+        };
+        assert_eq!(days_with("Conference"), 3);
+        // Only the days until the last displayed one are shown
+        assert_eq!(days_with("Trip"), 3);
+        assert!(fields.iter().all(|(name, _)| !name.contains(" // ")));
+    }
+
+    fn lines(count: usize, length: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| format!("{}\n", "a".repeat(length - 1)))
+            .collect()
+    }
+
+    #[test]
+    fn field_values_fit_in_a_field() {
+        let locale = Locale::default();
+        assert_eq!(
+            field_value(&lines(2, 10), false, locale).chars().count(),
+            20
+        );
+        assert_eq!(
+            field_value(&lines(2, 10), true, locale),
+            format!("```{}```", lines(2, 10).concat())
+        );
+
+        // 20 lines of 100 characters don't fit, the last ones are counted
+        let value = field_value(&lines(20, 100), false, locale);
+        assert!(value.chars().count() <= MAX_FIELD_LENGTH);
+        assert!(value.ends_with("+10 more\n"));
+        let value = field_value(&lines(20, 100), true, locale);
+        assert!(value.chars().count() <= MAX_FIELD_LENGTH);
+        assert!(value.starts_with("```") && value.ends_with("more\n```"));
+
+        // A single line longer than a field is cut
+        let value = field_value(&lines(1, 2000), false, locale);
+        assert!(value.chars().count() <= MAX_FIELD_LENGTH);
+        assert!(value.starts_with("aaa"));
+    }
+
+    fn paginated(count: usize, length: usize, template: &EmbedTemplate) -> Vec<Vec<Value>> {
+        let fields = (0..count)
+            .map(|index| (format!("Day {}", index), "a".repeat(length), false))
+            .collect();
+        paginate(fields, template, Locale::default())
+            .into_iter()
+            .map(|message| {
+                message
+                    .into_iter()
+                    .map(|embed| serde_json::to_value(embed).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn field_count(embed: &Value) -> usize {
+        embed["fields"].as_array().map_or(0, Vec::len)
+    }
+
+    /// Characters of a message counted by Discord
+    fn message_length(message: &[Value]) -> usize {
+        message
+            .iter()
+            .map(|embed| {
+                let text = |value: &Value| value.as_str().map_or(0, |text| text.chars().count());
+                text(&embed["title"])
+                    + text(&embed["footer"]["text"])
+                    + embed["fields"].as_array().map_or(0, |fields| {
+                        fields
+                            .iter()
+                            .map(|field| text(&field["name"]) + text(&field["value"]))
+                            .sum()
+                    })
+            })
+            .sum()
+    }
+
+    #[test]
+    fn embeds_have_up_to_25_fields() {
+        let messages = paginated(30, 10, &EmbedTemplate::default());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].len(), 2);
+        assert_eq!(field_count(&messages[0][0]), MAX_FIELDS);
+        assert_eq!(field_count(&messages[0][1]), 5);
+    }
+
+    #[test]
+    fn messages_have_up_to_10_embeds() {
+        let messages = paginated(MAX_FIELDS * MAX_EMBEDS + 1, 1, &EmbedTemplate::default());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].len(), MAX_EMBEDS);
+        assert!(messages[0]
+            .iter()
+            .all(|embed| field_count(embed) == MAX_FIELDS));
+        assert_eq!(field_count(&messages[1][0]), 1);
+    }
+
+    #[test]
+    fn messages_have_up_to_6000_characters() {
+        let template = EmbedTemplate {
+            title: Some(String::from("Team calendar")),
+            footer: Some(String::from("Updated every 5 minutes")),
+            ..EmbedTemplate::default()
+        };
+        let messages = paginated(20, 1000, &template);
+        assert!(messages.len() > 1);
+        for message in &messages {
+            assert!(message_length(message) <= MAX_MESSAGE_LENGTH);
+        }
+        let fields: usize = messages.iter().flatten().map(field_count).sum();
+        assert_eq!(fields, 20);
+
+        // The title is on the first embed and the footer on the last one
+        assert_eq!(messages[0][0]["title"], "Team calendar");
+        assert!(messages[1][0]["title"].is_null());
+        let last = messages.last().and_then(|message| message.last()).unwrap();
+        assert_eq!(last["footer"]["text"], "Updated every 5 minutes");
+        assert!(messages[0][0]["footer"].is_null());
+    }
+}
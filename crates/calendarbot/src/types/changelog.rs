@@ -0,0 +1,302 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::i18n::Text;
+use crate::types::{escape_markdown, CalendarEvent, CalendarOptions, EventFilter, EventTime};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+/// Format of the days in the changelog, e.g. "Tuesday 14 May"
+const CHANGELOG_DAY_FORMAT: &str = "%A %-d %B";
+
+/// Discord limit of the content of a message
+const MAX_CONTENT_LENGTH: usize = 2000;
+
+/// Change of an upcoming event between two syncs
+#[derive(Debug, Clone)]
+pub enum EventChange {
+    Added(CalendarEvent),
+    Moved {
+        before: Box<CalendarEvent>,
+        after: Box<CalendarEvent>,
+    },
+    Cancelled(CalendarEvent),
+}
+
+impl EventChange {
+    /// Compares the events of two syncs by event id, ordered by start.
+    /// Events whose start or end didn't change are left out. `synced` holds every event after
+    /// the sync, events missing from `after` are only cancelled when they aren't there either
+    pub fn diff(
+        before: Vec<CalendarEvent>,
+        after: Vec<CalendarEvent>,
+        synced: &BTreeMap<String, CalendarEvent>,
+    ) -> Vec<EventChange> {
+        let mut before: BTreeMap<String, CalendarEvent> = before
+            .into_iter()
+            .map(|event| (event.id.clone(), event))
+            .collect();
+
+        let mut changes = vec![];
+        for event in after {
+            match before.remove(&event.id) {
+                None => changes.push(EventChange::Added(event)),
+                Some(previous) if previous.start != event.start || previous.end != event.end => {
+                    changes.push(EventChange::Moved {
+                        before: Box::new(previous),
+                        after: Box::new(event),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        // Events moved after the compared window are still in the synced events
+        for previous in before.into_values() {
+            match synced.get(&previous.id) {
+                None => changes.push(EventChange::Cancelled(previous)),
+                Some(event) if previous.start != event.start || previous.end != event.end => {
+                    changes.push(EventChange::Moved {
+                        before: Box::new(previous),
+                        after: Box::new(event.clone()),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        // Only orders the changes, all-day events are sorted by their day in UTC
+        changes.sort_by_key(|change| change.event().start.map(|start| start.to_utc(&Tz::UTC)));
+        changes
+    }
+
+    /// The event as it is now, or as it was before being cancelled
+    pub fn event(&self) -> &CalendarEvent {
+        match self {
+            EventChange::Added(event) | EventChange::Cancelled(event) => event,
+            EventChange::Moved { after, .. } => after.as_ref(),
+        }
+    }
+
+    /// Sets the label of the calendar on the events of the change
+    pub fn with_label(self, label: Option<String>) -> Self {
+        let label = |event: CalendarEvent| CalendarEvent {
+            calendar_label: label.clone(),
+            ..event
+        };
+        match self {
+            EventChange::Added(event) => EventChange::Added(label(event)),
+            EventChange::Moved { before, after } => EventChange::Moved {
+                before: Box::new(label(*before)),
+                after: Box::new(label(*after)),
+            },
+            EventChange::Cancelled(event) => EventChange::Cancelled(label(event)),
+        }
+    }
+
+    /// Whether the filters of a channel show the event, moved events are shown
+    /// when they were or are now shown
+    pub fn keep(&self, filters: &[EventFilter]) -> bool {
+        match self {
+            EventChange::Moved { before, after } => {
+                EventFilter::keep(filters, before) || EventFilter::keep(filters, after)
+            }
+            _ => EventFilter::keep(filters, self.event()),
+        }
+    }
+
+    /// Describes the change in one line, e.g. "Standup moved from 10:00 to 14:00"
+    pub fn describe(&self, options: &CalendarOptions) -> String {
+        let locale = options.locale;
+        let summary = escape_markdown(&self.event().display_summary());
+
+        match self {
+            EventChange::Added(event) => Text::ChangelogAdded.format(
+                locale,
+                &[
+                    ("summary", &summary),
+                    ("time", &format_time(event.start, options, true)),
+                ],
+            ),
+            EventChange::Moved { before, after } => {
+                // The day is only repeated when the event changes day
+                let same_day = match (before.start, after.start) {
+                    (Some(before), Some(after)) => {
+                        before.date_naive(&options.timezone) == after.date_naive(&options.timezone)
+                    }
+                    _ => false,
+                };
+                Text::ChangelogMoved.format(
+                    locale,
+                    &[
+                        ("summary", &summary),
+                        ("before", &format_time(before.start, options, !same_day)),
+                        ("after", &format_time(after.start, options, !same_day)),
+                    ],
+                )
+            }
+            EventChange::Cancelled(event) => Text::ChangelogCancelled.format(
+                locale,
+                &[
+                    ("summary", &summary),
+                    ("time", &format_time(event.start, options, true)),
+                ],
+            ),
+        }
+    }
+
+    /// Builds the message listing the changes, the last ones are counted
+    /// when they don't fit in a single message
+    pub fn to_message(changes: &[EventChange], options: &CalendarOptions) -> String {
+        let locale = options.locale;
+        let mut content = format!("**{}**", Text::CalendarChanges.get(locale));
+
+        for (index, change) in changes.iter().enumerate() {
+            let line = format!("\n- {}", change.describe(options));
+            let more = Text::MoreEvents.format(locale, &[("count", &(changes.len() - index))]);
+            let more_length = more.chars().count() + 1;
+
+            let remaining = match index + 1 == changes.len() {
+                true => 0,
+                false => more_length,
+            };
+            if content.chars().count() + line.chars().count() + remaining > MAX_CONTENT_LENGTH {
+                content = format!("{}\n{}", content, more);
+                break;
+            }
+            content.push_str(&line);
+        }
+
+        content
+    }
+}
+
+/// Formats the start of an event in the timezone of the channel
+fn format_time(time: Option<EventTime>, options: &CalendarOptions, with_day: bool) -> String {
+    let locale = options.locale;
+    match time {
+        None => String::new(),
+        Some(EventTime::Date(date)) => locale.format_date(date, CHANGELOG_DAY_FORMAT),
+        Some(EventTime::DateTime(date_time)) if options.dynamic_timestamps => match with_day {
+            true => format!("<t:{}:f>", date_time.timestamp()),
+            false => format!("<t:{}:t>", date_time.timestamp()),
+        },
+        Some(EventTime::DateTime(date_time)) => {
+            let local = date_time.with_timezone(&options.timezone);
+            let time = local.format("%H:%M").to_string();
+            match with_day {
+                true => format!(
+                    "{} {}",
+                    locale.format_date(local.date_naive(), CHANGELOG_DAY_FORMAT),
+                    time
+                ),
+                false => time,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Locale;
+    use crate::types::{CalendarEventSource, DisplayMode, EmbedTemplate};
+    use google_calendar3::chrono::{DateTime, TimeDelta};
+
+    fn at(hour: i64) -> EventTime {
+        let start = DateTime::parse_from_rfc3339("2026-10-20T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        EventTime::DateTime(start + TimeDelta::hours(hour))
+    }
+
+    fn event(id: &str, hour: i64) -> CalendarEvent {
+        CalendarEvent {
+            id: id.to_string(),
+            summary: id.to_string(),
+            description: String::new(),
+            location: None,
+            html_link: None,
+            conference_link: None,
+            color_id: None,
+            organizer: None,
+            declined: false,
+            start: Some(at(hour)),
+            end: Some(at(hour + 1)),
+            event_source: CalendarEventSource::Ics,
+            recurrence: None,
+            calendar_label: None,
+        }
+    }
+
+    fn synced(events: &[CalendarEvent]) -> BTreeMap<String, CalendarEvent> {
+        events
+            .iter()
+            .map(|event| (event.id.clone(), event.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn diff() {
+        let before = vec![event("standup", 9), event("retro", 14), event("lunch", 12)];
+        let after = vec![event("standup", 9), event("retro", 16), event("demo", 10)];
+
+        let changes = EventChange::diff(before, after.clone(), &synced(&after));
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(&changes[0], EventChange::Added(event) if event.id == "demo"));
+        assert!(matches!(&changes[1], EventChange::Cancelled(event) if event.id == "lunch"));
+        assert!(matches!(
+            &changes[2],
+            EventChange::Moved { before, after } if before.start == Some(at(14)) && after.start == Some(at(16))
+        ));
+    }
+
+    #[test]
+    fn events_moved_after_the_window_are_not_cancelled() {
+        let before = vec![event("retro", 14)];
+        // The retro moved to next week, after the compared events
+        let moved = event("retro", 24 * 7);
+
+        let changes = EventChange::diff(before.clone(), vec![], &synced(&[moved]));
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(
+            &changes[0],
+            EventChange::Moved { after, .. } if after.start == Some(at(24 * 7))
+        ));
+
+        let changes = EventChange::diff(before.clone(), vec![], &synced(&before));
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn long_changelogs_are_truncated() {
+        let options = CalendarOptions {
+            timezone: Tz::UTC,
+            num_of_days: 7,
+            skip_weekend: false,
+            show_if_no_events: false,
+            display_mode: DisplayMode::Compact,
+            dynamic_timestamps: true,
+            template: EmbedTemplate::default(),
+            locale: Locale::default(),
+        };
+        let changes: Vec<EventChange> = (0..100)
+            .map(|index| EventChange::Added(event(&format!("Event number {}", index), index)))
+            .collect();
+
+        let message = EventChange::to_message(&changes, &options);
+        assert!(message.chars().count() <= MAX_CONTENT_LENGTH);
+        let shown = message
+            .lines()
+            .filter(|line| line.starts_with("- "))
+            .count();
+        assert!(shown < changes.len());
+        assert!(message.ends_with(&format!("+{} more", changes.len() - shown)));
+
+        let message = EventChange::to_message(&changes[..2], &options);
+        assert_eq!(message.lines().count(), 3);
+        assert!(!message.contains("more"));
+    }
+}
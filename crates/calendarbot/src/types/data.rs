@@ -18,13 +18,15 @@ pub struct GlobalData {
     pub client_id: serenity::UserId,
     pub bot_start_time: std::time::Instant,
     pub db: Pool<AsyncPgConnection>,
-    pub gcalendar_tx: Sender<CalendarCommands>,
+    pub calendar_tx: Sender<CalendarCommands>,
+    /// Minimum poll interval in minutes, protects the calendar APIs quotas
+    pub min_poll_interval: i32,
 }
 
 impl GlobalData {
     pub fn new(
         db_connection: Pool<AsyncPgConnection>,
-        gcalendar_tx: Sender<CalendarCommands>,
+        calendar_tx: Sender<CalendarCommands>,
     ) -> Result<GlobalData> {
         Ok(Self {
             application_id: env::var("APPLICATION_ID")
@@ -37,11 +39,17 @@ impl GlobalData {
                 .into(),
             bot_start_time: std::time::Instant::now(),
             db: db_connection,
-            gcalendar_tx,
+            calendar_tx,
+            min_poll_interval: match env::var("MIN_POLL_INTERVAL") {
+                Ok(value) => value.parse::<i32>()?.max(1),
+                Err(_) => DEFAULT_MIN_POLL_INTERVAL,
+            },
         })
     }
 }
 
 pub type Context<'a> = poise::Context<'a, GlobalData, Error>;
 
+pub const DEFAULT_MIN_POLL_INTERVAL: i32 = 1;
+
 pub const EMBED_COLOR: (u8, u8, u8) = (0xb7, 0x47, 0x00);
@@ -0,0 +1,265 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::models::CalendarFilter;
+use crate::types::CalendarEvent;
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, poise::ChoiceParameter)]
+pub enum FilterMode {
+    /// Only the events matching one of the include filters are shown
+    #[name = "Include"]
+    Include,
+    #[name = "Exclude"]
+    Exclude,
+}
+
+impl fmt::Display for FilterMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterMode::Include => write!(f, "include"),
+            FilterMode::Exclude => write!(f, "exclude"),
+        }
+    }
+}
+
+impl FromStr for FilterMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(FilterMode::Include),
+            "exclude" => Ok(FilterMode::Exclude),
+            _ => Err(anyhow!("Unknown filter mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, poise::ChoiceParameter)]
+pub enum FilterKind {
+    #[name = "Summary contains"]
+    Summary,
+    #[name = "Summary matches regex"]
+    Regex,
+    #[name = "Colour ID"]
+    Color,
+    #[name = "Organiser"]
+    Organizer,
+    #[name = "Declined"]
+    Declined,
+}
+
+impl FilterKind {
+    pub fn needs_value(self) -> bool {
+        self != FilterKind::Declined
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterKind::Summary => write!(f, "summary"),
+            FilterKind::Regex => write!(f, "regex"),
+            FilterKind::Color => write!(f, "color"),
+            FilterKind::Organizer => write!(f, "organizer"),
+            FilterKind::Declined => write!(f, "declined"),
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "summary" => Ok(FilterKind::Summary),
+            "regex" => Ok(FilterKind::Regex),
+            "color" => Ok(FilterKind::Color),
+            "organizer" => Ok(FilterKind::Organizer),
+            "declined" => Ok(FilterKind::Declined),
+            _ => Err(anyhow!("Unknown filter kind: {}", s)),
+        }
+    }
+}
+
+/// Compiles a regex filter, ignoring case like the other filters
+pub fn parse_filter_regex(value: &str) -> Result<Regex> {
+    Ok(RegexBuilder::new(value).case_insensitive(true).build()?)
+}
+
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    pub mode: FilterMode,
+    kind: FilterKind,
+    value: String,
+    regex: Option<Regex>,
+}
+
+impl TryFrom<&CalendarFilter> for EventFilter {
+    type Error = anyhow::Error;
+
+    fn try_from(filter: &CalendarFilter) -> std::result::Result<Self, Self::Error> {
+        let kind: FilterKind = filter.kind.parse()?;
+        let regex = match kind {
+            FilterKind::Regex => Some(parse_filter_regex(&filter.value)?),
+            _ => None,
+        };
+
+        Ok(Self {
+            mode: filter.mode.parse()?,
+            kind,
+            value: filter.value.to_lowercase(),
+            regex,
+        })
+    }
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &CalendarEvent) -> bool {
+        match self.kind {
+            FilterKind::Summary => event.summary.to_lowercase().contains(&self.value),
+            FilterKind::Regex => self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(&event.summary)),
+            FilterKind::Color => event
+                .color_id
+                .as_ref()
+                .is_some_and(|color_id| color_id.eq_ignore_ascii_case(&self.value)),
+            FilterKind::Organizer => event
+                .organizer
+                .as_ref()
+                .is_some_and(|organizer| organizer.to_lowercase() == self.value),
+            FilterKind::Declined => event.declined,
+        }
+    }
+
+    /// Whether an event is shown: it matches no exclude filter and,
+    /// when there are include filters, at least one of them
+    pub fn keep(filters: &[EventFilter], event: &CalendarEvent) -> bool {
+        let mut includes = filters
+            .iter()
+            .filter(|filter| filter.mode == FilterMode::Include)
+            .peekable();
+        let included = includes.peek().is_none() || includes.any(|filter| filter.matches(event));
+
+        included
+            && !filters
+                .iter()
+                .filter(|filter| filter.mode == FilterMode::Exclude)
+                .any(|filter| filter.matches(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CalendarEventSource;
+
+    fn filter(mode: FilterMode, kind: FilterKind, value: &str) -> EventFilter {
+        EventFilter::try_from(&CalendarFilter {
+            id: 0,
+            guild_id: 0,
+            calendar_id: 0,
+            channelId: String::new(),
+            mode: mode.to_string(),
+            kind: kind.to_string(),
+            value: value.to_string(),
+        })
+        .unwrap()
+    }
+
+    fn event(summary: &str) -> CalendarEvent {
+        CalendarEvent {
+            id: summary.to_string(),
+            summary: summary.to_string(),
+            description: String::new(),
+            location: None,
+            html_link: None,
+            conference_link: None,
+            color_id: None,
+            organizer: None,
+            declined: false,
+            start: None,
+            end: None,
+            event_source: CalendarEventSource::Ics,
+            recurrence: None,
+            calendar_label: None,
+        }
+    }
+
+    #[test]
+    fn matches() {
+        let standup = event("Daily Standup");
+        assert!(filter(FilterMode::Include, FilterKind::Summary, "STANDUP").matches(&standup));
+        assert!(!filter(FilterMode::Include, FilterKind::Summary, "retro").matches(&standup));
+
+        let colored = CalendarEvent {
+            color_id: Some(String::from("Tomato")),
+            organizer: Some(String::from("Alice@Example.com")),
+            ..standup.clone()
+        };
+        assert!(filter(FilterMode::Include, FilterKind::Color, "tomato").matches(&colored));
+        assert!(!filter(FilterMode::Include, FilterKind::Color, "tomato").matches(&standup));
+        assert!(filter(
+            FilterMode::Include,
+            FilterKind::Organizer,
+            "alice@example.com"
+        )
+        .matches(&colored));
+
+        let declined = CalendarEvent {
+            declined: true,
+            ..standup.clone()
+        };
+        assert!(filter(FilterMode::Exclude, FilterKind::Declined, "").matches(&declined));
+        assert!(!filter(FilterMode::Exclude, FilterKind::Declined, "").matches(&standup));
+    }
+
+    #[test]
+    fn regexes_ignore_case() {
+        let regex = filter(FilterMode::Include, FilterKind::Regex, r"^daily\b");
+        assert!(regex.matches(&event("DAILY standup")));
+        assert!(!regex.matches(&event("Not daily")));
+    }
+
+    #[test]
+    fn keep() {
+        let standup = event("Daily standup");
+        let retro = event("Sprint retro");
+        let planning = event("Sprint planning");
+
+        assert!(EventFilter::keep(&[], &standup));
+
+        let include = [filter(FilterMode::Include, FilterKind::Summary, "sprint")];
+        assert!(!EventFilter::keep(&include, &standup));
+        assert!(EventFilter::keep(&include, &retro));
+
+        let exclude = [filter(FilterMode::Exclude, FilterKind::Summary, "retro")];
+        assert!(EventFilter::keep(&exclude, &standup));
+        assert!(!EventFilter::keep(&exclude, &retro));
+
+        // Exclude filters win over include filters
+        let both = [
+            filter(FilterMode::Include, FilterKind::Summary, "sprint"),
+            filter(FilterMode::Exclude, FilterKind::Summary, "retro"),
+        ];
+        assert!(!EventFilter::keep(&both, &standup));
+        assert!(!EventFilter::keep(&both, &retro));
+        assert!(EventFilter::keep(&both, &planning));
+
+        let declined = [filter(FilterMode::Exclude, FilterKind::Declined, "")];
+        let declined_retro = CalendarEvent {
+            declined: true,
+            ..retro.clone()
+        };
+        assert!(EventFilter::keep(&declined, &retro));
+        assert!(!EventFilter::keep(&declined, &declined_retro));
+    }
+}
@@ -4,10 +4,22 @@ Calendarbot  Copyright (C) 2023 Zbinden Yohan
 This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
+mod availability;
 mod calendar;
+mod changelog;
 mod data;
+mod filter;
+mod quick_event;
+mod recurrence;
+mod template;
 mod timezones;
 
+pub use availability::*;
 pub use calendar::*;
+pub use changelog::*;
 pub use data::*;
+pub use filter::*;
+pub use quick_event::*;
+pub use recurrence::*;
+pub use template::*;
 pub use timezones::*;
@@ -0,0 +1,813 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::types::EventTime;
+use chrono_tz::Tz;
+use google_calendar3::chrono::{
+    DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, Timelike, Weekday,
+};
+use regex::Regex;
+
+/// Length of timed events given without end nor duration
+const DEFAULT_DURATION_MINUTES: i64 = 60;
+
+/// Words introducing a date, a time or a duration, only removed from the summary
+/// when followed by what they introduce
+const DATE_PREFIXES: [&str; 7] = ["on", "the", "this", "le", "la", "ce", "cette"];
+const TIME_PREFIXES: [&str; 8] = ["at", "a", "from", "between", "de", "des", "entre", "vers"];
+const DURATION_PREFIXES: [&str; 4] = ["for", "during", "pendant", "durant"];
+
+/// Separators of the start and end of a time range
+const RANGE_SEPARATORS: [&str; 9] = [
+    "-", "to", "until", "till", "and", "a", "jusqu'a", "et", "au",
+];
+
+const WEEKDAYS: [(&str, Weekday); 14] = [
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+    ("lundi", Weekday::Mon),
+    ("mardi", Weekday::Tue),
+    ("mercredi", Weekday::Wed),
+    ("jeudi", Weekday::Thu),
+    ("vendredi", Weekday::Fri),
+    ("samedi", Weekday::Sat),
+    ("dimanche", Weekday::Sun),
+];
+
+/// English and French month names, accents removed, with the English abbreviations
+const MONTHS: [(&str, u32); 35] = [
+    ("january", 1),
+    ("february", 2),
+    ("march", 3),
+    ("april", 4),
+    ("may", 5),
+    ("june", 6),
+    ("july", 7),
+    ("august", 8),
+    ("september", 9),
+    ("october", 10),
+    ("november", 11),
+    ("december", 12),
+    ("janvier", 1),
+    ("fevrier", 2),
+    ("mars", 3),
+    ("avril", 4),
+    ("mai", 5),
+    ("juin", 6),
+    ("juillet", 7),
+    ("aout", 8),
+    ("septembre", 9),
+    ("octobre", 10),
+    ("novembre", 11),
+    ("decembre", 12),
+    ("jan", 1),
+    ("feb", 2),
+    ("mar", 3),
+    ("apr", 4),
+    ("jun", 6),
+    ("jul", 7),
+    ("aug", 8),
+    ("sep", 9),
+    ("sept", 9),
+    ("oct", 10),
+    ("nov", 11),
+];
+
+/// Event read from a sentence like "Team retro next friday 14:00-15:30 @ room B"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickEvent {
+    pub summary: String,
+    pub location: Option<String>,
+    pub start: EventTime,
+    /// Exclusive, the day after the last day for all-day events
+    pub end: EventTime,
+}
+
+/// Word of the sentence, compared without case and accents
+struct Token<'a> {
+    text: &'a str,
+    word: String,
+}
+
+impl<'a> Token<'a> {
+    fn new(text: &'a str) -> Self {
+        let word = text
+            .to_lowercase()
+            .replace('’', "'")
+            .chars()
+            .map(|c| match c {
+                'à' | 'â' | 'ä' => 'a',
+                'é' | 'è' | 'ê' | 'ë' => 'e',
+                'î' | 'ï' => 'i',
+                'ô' | 'ö' => 'o',
+                'ù' | 'û' | 'ü' => 'u',
+                'ç' => 'c',
+                '–' => '-',
+                c => c,
+            })
+            .collect::<String>()
+            .trim_end_matches([',', ';', '!', '?'])
+            .to_string();
+        Token { text, word }
+    }
+}
+
+/// What was read so far, in the timezone of the channel
+#[derive(Default)]
+struct Parsed {
+    date: Option<NaiveDate>,
+    start: Option<NaiveTime>,
+    end: Option<NaiveTime>,
+    duration: Option<TimeDelta>,
+}
+
+/// Time of the day, `meridiem` is set for times like `2pm`
+#[derive(Debug, Copy, Clone)]
+struct Time {
+    time: NaiveTime,
+    meridiem: bool,
+}
+
+impl Parsed {
+    /// Reads a date, a time or a duration at the start of the tokens,
+    /// returns the number of tokens used
+    fn read(&mut self, tokens: &[Token], today: NaiveDate) -> Option<usize> {
+        if self.duration.is_none() && self.end.is_none() {
+            if let Some((length, duration)) =
+                with_prefixes(tokens, &DURATION_PREFIXES, true, parse_duration_words)
+            {
+                self.duration = Some(duration);
+                return Some(length);
+            }
+        }
+
+        if self.start.is_none() {
+            if let Some((length, (start, end))) =
+                with_prefixes(tokens, &TIME_PREFIXES, false, parse_time_range)
+            {
+                self.start = Some(start);
+                self.end = Some(end);
+                return Some(length);
+            }
+            if let Some((length, time)) = with_prefixes(tokens, &TIME_PREFIXES, false, parse_time) {
+                self.start = Some(time.time);
+                return Some(length);
+            }
+            // Numbers are only hours when introduced, e.g. "at 5" or "à 14"
+            if let Some((length, time)) = with_prefixes(tokens, &TIME_PREFIXES, true, parse_hour) {
+                self.start = Some(time);
+                return Some(length);
+            }
+        }
+
+        if self.date.is_none() {
+            if let Some((length, date)) = with_prefixes(tokens, &DATE_PREFIXES, false, |tokens| {
+                parse_date(tokens, today)
+            }) {
+                self.date = Some(date);
+                return Some(length);
+            }
+        }
+
+        // Durations after the start don't need to be introduced, e.g. "14:00 45min"
+        if self.duration.is_none() && self.end.is_none() {
+            if let Some((length, duration)) = parse_duration_words(tokens) {
+                self.duration = Some(duration);
+                return Some(length);
+            }
+        }
+
+        None
+    }
+}
+
+/// Parses a sentence describing an event, relative to `now` in the timezone of the channel.
+///
+/// The sentence holds the summary, a date and/or a time (or time range) and optionally
+/// a duration, in English or French. Whatever follows the last `@` is the location.
+/// An event without time lasts the whole day and an event without date is on the next
+/// day at that time. Returns `None` without summary, date or time
+pub fn parse_quick_event(text: &str, now: DateTime<Tz>) -> Option<QuickEvent> {
+    let (text, location) = match text.rsplit_once('@') {
+        Some((text, location)) if !location.trim().is_empty() => {
+            (text, Some(location.trim().to_string()))
+        }
+        _ => (text, None),
+    };
+
+    let tokens: Vec<Token> = text.split_whitespace().map(Token::new).collect();
+    let today = now.date_naive();
+    let mut parsed = Parsed::default();
+    let mut summary = vec![];
+    let mut index = 0;
+    while index < tokens.len() {
+        match parsed.read(&tokens[index..], today) {
+            Some(length) => index += length,
+            None => {
+                summary.push(tokens[index].text);
+                index += 1;
+            }
+        }
+    }
+
+    let summary = summary
+        .join(" ")
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, ',' | '-' | ':'))
+        .to_string();
+    if summary.is_empty() {
+        return None;
+    }
+
+    let timezone = now.timezone();
+    let instant = |date: NaiveDate, time: NaiveTime| {
+        date.and_time(time)
+            .and_local_timezone(timezone)
+            .earliest()
+            .map(|date_time| date_time.to_utc())
+    };
+
+    let (start, end) = match (parsed.date, parsed.start) {
+        (None, None) => return None,
+        (Some(date), None) => {
+            // Rounded up to whole days
+            let days = parsed.duration.map_or(1, |duration| {
+                ((duration.num_minutes() + 24 * 60 - 1) / (24 * 60)).max(1)
+            });
+            (
+                EventTime::Date(date),
+                EventTime::Date(date + TimeDelta::days(days)),
+            )
+        }
+        (date, Some(start_time)) => {
+            // Without date, the next time it is this time
+            let date = date.unwrap_or(match start_time > now.time() {
+                true => today,
+                false => today + TimeDelta::days(1),
+            });
+            let start = instant(date, start_time)?;
+            let end = match parsed.end {
+                // Ranges ending before they start end the next day, e.g. 23:00-01:00
+                Some(end_time) if end_time <= start_time => {
+                    instant(date + TimeDelta::days(1), end_time)?
+                }
+                Some(end_time) => instant(date, end_time)?,
+                None => {
+                    start
+                        + parsed
+                            .duration
+                            .unwrap_or(TimeDelta::minutes(DEFAULT_DURATION_MINUTES))
+                }
+            };
+            (EventTime::DateTime(start), EventTime::DateTime(end))
+        }
+    };
+
+    Some(QuickEvent {
+        summary,
+        location,
+        start,
+        end,
+    })
+}
+
+/// Parses a day on its own, e.g. `friday`, `20.10` or `le 20 octobre`, relative to `today`
+pub fn parse_day(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let tokens: Vec<Token> = text.split_whitespace().map(Token::new).collect();
+    match with_prefixes(&tokens, &DATE_PREFIXES, false, |tokens| {
+        parse_date(tokens, today)
+    }) {
+        Some((length, date)) if length == tokens.len() => Some(date),
+        _ => None,
+    }
+}
+
+/// Parses a time range on its own, e.g. `09:00-17:00`, `9h à 17h` or `9am to 5pm`
+pub fn parse_hours(text: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let tokens: Vec<Token> = text.split_whitespace().map(Token::new).collect();
+    match with_prefixes(&tokens, &TIME_PREFIXES, false, parse_time_range) {
+        Some((length, hours)) if length == tokens.len() => Some(hours),
+        _ => None,
+    }
+}
+
+/// Applies a parser after up to two prefixes, which are required when `required` is set
+fn with_prefixes<T>(
+    tokens: &[Token],
+    prefixes: &[&str],
+    required: bool,
+    parser: impl Fn(&[Token]) -> Option<(usize, T)>,
+) -> Option<(usize, T)> {
+    let start = if required { 1 } else { 0 };
+    (start..=2)
+        .take_while(|&skip| {
+            tokens.len() > skip
+                && tokens[..skip]
+                    .iter()
+                    .all(|token| prefixes.contains(&token.word.as_str()))
+        })
+        .find_map(|skip| parser(&tokens[skip..]).map(|(length, value)| (skip + length, value)))
+}
+
+fn word<'a>(tokens: &'a [Token], index: usize) -> Option<&'a str> {
+    tokens.get(index).map(|token| token.word.as_str())
+}
+
+/// Reads a number written in digits or in words, e.g. "3", "a" or "une"
+fn number(word: &str) -> Option<i64> {
+    match word {
+        "a" | "an" | "one" | "un" | "une" => Some(1),
+        "two" | "deux" => Some(2),
+        "three" | "trois" => Some(3),
+        word => word.parse::<u16>().ok().map(i64::from),
+    }
+}
+
+/// Reads a time like `14:00`, `14h`, `14h30`, `2pm`, `2:30 pm`, `noon` or `midi`
+fn parse_time(tokens: &[Token]) -> Option<(usize, Time)> {
+    static TIME_REGEX: &str = r"^(\d{1,2})(?:(:)(\d{2})|(h)(\d{2})?)?(am|pm)?$";
+
+    let first = word(tokens, 0)?;
+    match first {
+        "noon" | "midi" => {
+            return Some((1, Time::new(NaiveTime::from_hms_opt(12, 0, 0)?)));
+        }
+        "midnight" | "minuit" => return Some((1, Time::new(NaiveTime::MIN))),
+        _ => {}
+    }
+
+    let re = Regex::new(TIME_REGEX).unwrap();
+    let captures = re.captures(first)?;
+    let hour: u32 = captures[1].parse().ok()?;
+    let minute: u32 = captures
+        .get(3)
+        .or(captures.get(5))
+        .map_or(Some(0), |minute| minute.as_str().parse().ok())?;
+    let separated = captures.get(2).is_some() || captures.get(4).is_some();
+
+    // The meridiem can be a word of its own, e.g. "2 pm"
+    let (length, meridiem) = match (captures.get(6), word(tokens, 1)) {
+        (Some(meridiem), _) => (1, Some(meridiem.as_str())),
+        (None, Some(meridiem @ ("am" | "pm"))) => (2, Some(meridiem)),
+        _ => (1, None),
+    };
+    if !separated && meridiem.is_none() {
+        return None;
+    }
+
+    let time = Time::from_hour(hour, minute, meridiem)?;
+    Some((length, time))
+}
+
+/// Reads a bare hour, e.g. the "5" of "at 5"
+fn parse_hour(tokens: &[Token]) -> Option<(usize, NaiveTime)> {
+    let hour: u32 = word(tokens, 0)?.parse().ok()?;
+    Some((1, NaiveTime::from_hms_opt(hour, 0, 0)?))
+}
+
+impl Time {
+    fn new(time: NaiveTime) -> Self {
+        Time {
+            time,
+            meridiem: false,
+        }
+    }
+
+    fn from_hour(hour: u32, minute: u32, meridiem: Option<&str>) -> Option<Self> {
+        let hour = match meridiem {
+            None => hour,
+            Some(_) if !(1..=12).contains(&hour) => return None,
+            Some("am") => hour % 12,
+            Some(_) => hour % 12 + 12,
+        };
+        Some(Time {
+            time: NaiveTime::from_hms_opt(hour, minute, 0)?,
+            meridiem: meridiem.is_some(),
+        })
+    }
+}
+
+/// Reads a range like `14:00-15:30`, `2-3pm`, `14h to 15h` or `de 14h à 15h30`
+fn parse_time_range(tokens: &[Token]) -> Option<(usize, (NaiveTime, NaiveTime))> {
+    // A single word, e.g. "14:00-15:30"
+    let joined = word(tokens, 0)?
+        .split_once('-')
+        .filter(|(start, end)| !start.is_empty() && !end.is_empty());
+    let (length, start, end) = match joined {
+        Some((start, end)) => {
+            let mut range = vec![Token::new(start), Token::new(end)];
+            // "2-3 pm"
+            if let Some(meridiem @ ("am" | "pm")) = word(tokens, 1) {
+                range.push(Token::new(meridiem));
+            }
+            let start = range_start(&range[..1])?;
+            let (end_length, end) = parse_time(&range[1..])?;
+            (end_length, start, end)
+        }
+        None => {
+            let (start_length, start) = match parse_time(tokens) {
+                Some((length, time)) => (length, Some(time)),
+                None => (1, None),
+            };
+            if !RANGE_SEPARATORS.contains(&word(tokens, start_length)?) {
+                return None;
+            }
+            let (end_length, end) = parse_time(&tokens[start_length + 1..])?;
+            let start = match start {
+                Some(start) => start,
+                None => range_start(tokens)?,
+            };
+            (start_length + 1 + end_length, start, end)
+        }
+    };
+
+    // The meridiem of the end applies to the start, "2-3pm" starts at 14:00
+    let start = match (start.meridiem, end.meridiem) {
+        (false, true)
+            if start.time.hour() < 12 && start.time + TimeDelta::hours(12) <= end.time =>
+        {
+            start.time + TimeDelta::hours(12)
+        }
+        _ => start.time,
+    };
+    Some((length, (start, end.time)))
+}
+
+/// Reads the start of a range, which can be a bare hour, e.g. the "2" of "2-3pm"
+fn range_start(tokens: &[Token]) -> Option<Time> {
+    match parse_time(tokens) {
+        Some((1, time)) => Some(time),
+        Some(_) => None,
+        None => parse_hour(tokens).map(|(_, time)| Time::new(time)),
+    }
+}
+
+/// Parses a duration like `90`, `45m`, `1h30`, `2d`, `90 minutes`, `2 hours` or `3 jours`,
+/// plain numbers are minutes
+pub fn parse_duration(value: &str) -> Option<TimeDelta> {
+    static DURATION_REGEX: &str = r"^(?:(\d{1,4})\s*(?:days?|jours?|d)\s*)?(?:(\d{1,4})\s*(?:hours?|heures?|hrs?|h)\s*)?(?:(\d{1,4})\s*(?:minutes?|mins?|mn|m)?)?$";
+
+    let re = Regex::new(DURATION_REGEX).unwrap();
+    let value = value.trim().to_lowercase();
+    let captures = re.captures(&value)?;
+    let part = |index: usize| {
+        captures
+            .get(index)
+            .and_then(|part| part.as_str().parse::<i64>().ok())
+            .unwrap_or(0)
+    };
+
+    let duration =
+        TimeDelta::days(part(1)) + TimeDelta::hours(part(2)) + TimeDelta::minutes(part(3));
+    Some(duration).filter(|duration| *duration > TimeDelta::zero())
+}
+
+/// Reads a duration in a sentence, where a number needs a unit, e.g. `2h` or `45 minutes`
+fn parse_duration_words(tokens: &[Token]) -> Option<(usize, TimeDelta)> {
+    let first = word(tokens, 0)?;
+    if !first.chars().all(|c| c.is_ascii_digit()) {
+        return parse_duration(first).map(|duration| (1, duration));
+    }
+
+    // The unit can be a word of its own, e.g. "90 minutes"
+    let unit = word(tokens, 1)?;
+    if unit.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    parse_duration(&format!("{} {}", first, unit)).map(|duration| (2, duration))
+}
+
+/// Next day falling on a weekday, today included
+fn next_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    today + TimeDelta::days(i64::from(days))
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    WEEKDAYS
+        .iter()
+        .find(|(name, _)| *name == word)
+        .map(|(_, weekday)| *weekday)
+}
+
+fn month(word: &str) -> Option<u32> {
+    MONTHS
+        .iter()
+        .find(|(name, _)| *name == word.trim_end_matches('.'))
+        .map(|(_, month)| *month)
+}
+
+/// Reads a day of the month like `20`, `20th` or `1er`
+fn day(word: &str) -> Option<u32> {
+    let day = word.trim_end_matches(['s', 't', 'n', 'd', 'r', 'h', 'e']);
+    if day.is_empty() || !day.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    day.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+/// Builds a date without year, in the current year unless it is already over
+fn upcoming_date(today: NaiveDate, month: u32, day: u32, year: Option<i32>) -> Option<NaiveDate> {
+    match year {
+        Some(year) => NaiveDate::from_ymd_opt(year, month, day),
+        None => NaiveDate::from_ymd_opt(today.year(), month, day)
+            .filter(|date| *date >= today)
+            .or_else(|| NaiveDate::from_ymd_opt(today.year() + 1, month, day)),
+    }
+}
+
+/// Reads a date: relative days (`tomorrow`, `après-demain`, `in 3 days`), weekdays
+/// (`friday`, `next friday`, `vendredi prochain`), `next week`, or dates
+/// (`2026-10-20`, `20.10`, `20/10/2026`, `20 octobre`, `October 20th 2026`)
+fn parse_date(tokens: &[Token], today: NaiveDate) -> Option<(usize, NaiveDate)> {
+    static NUMERIC_DATE_REGEX: &str = r"^(\d{1,2})[./](\d{1,2})(?:[./](\d{2}|\d{4}))?\.?$";
+
+    let days = |days: i64| today + TimeDelta::days(days);
+    let first = word(tokens, 0)?;
+    let second = word(tokens, 1);
+    let third = word(tokens, 2);
+
+    match (first, second, third) {
+        ("today" | "aujourd'hui" | "aujourdhui", _, _) => return Some((1, today)),
+        ("tomorrow" | "demain", _, _) => return Some((1, days(1))),
+        ("apres-demain", _, _) => return Some((1, days(2))),
+        ("apres", Some("demain"), _) => return Some((2, days(2))),
+        ("day", Some("after"), Some("tomorrow")) => return Some((3, days(2))),
+        ("next", Some("week"), _) | ("semaine", Some("prochaine"), _) => {
+            return Some((2, next_weekday(days(1), Weekday::Mon)));
+        }
+        // Skips today, "next friday" on a friday is in a week
+        ("next", Some(name), _) => {
+            if let Some(weekday) = weekday(name) {
+                return Some((2, next_weekday(days(1), weekday)));
+            }
+        }
+        ("in" | "dans", Some(count), Some(unit)) => {
+            let count = number(count)?;
+            let date = match unit {
+                "day" | "days" | "jour" | "jours" => days(count),
+                "week" | "weeks" | "semaine" | "semaines" => days(7 * count),
+                _ => return None,
+            };
+            return Some((3, date));
+        }
+        _ => {}
+    }
+
+    if let Some(weekday) = weekday(first) {
+        return match second {
+            Some("prochain" | "prochaine") => Some((2, next_weekday(days(1), weekday))),
+            _ => Some((1, next_weekday(today, weekday))),
+        };
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(first, "%Y-%m-%d") {
+        return Some((1, date));
+    }
+    let re = Regex::new(NUMERIC_DATE_REGEX).unwrap();
+    if let Some(captures) = re.captures(first) {
+        let year = match captures.get(3) {
+            Some(year) if year.len() == 2 => Some(2000 + year.as_str().parse::<i32>().ok()?),
+            Some(year) => Some(year.as_str().parse().ok()?),
+            None => None,
+        };
+        // Day first, as written in French and most of Europe
+        let date = upcoming_date(
+            today,
+            captures[2].parse().ok()?,
+            captures[1].parse().ok()?,
+            year,
+        )?;
+        return Some((1, date));
+    }
+
+    let year = |word: Option<&str>| {
+        word.filter(|word| word.len() == 4)
+            .and_then(|word| word.parse::<i32>().ok())
+    };
+    // "20 octobre 2026" or "October 20th, 2026"
+    let (length, month, day) = match (day(first), second.and_then(month)) {
+        (Some(day), Some(month)) => (2, month, day),
+        _ => match (month(first), second.and_then(day)) {
+            (Some(month), Some(day)) => (2, month, day),
+            _ => return None,
+        },
+    };
+    match year(third) {
+        Some(year) => Some((3, upcoming_date(today, month, day, Some(year))?)),
+        None => Some((length, upcoming_date(today, month, day, None)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use google_calendar3::chrono::TimeZone;
+
+    const TIMEZONE: Tz = chrono_tz::Europe::Zurich;
+
+    /// Wednesday 14 October 2026, 10:00 in Zurich
+    fn now() -> DateTime<Tz> {
+        TIMEZONE.with_ymd_and_hms(2026, 10, 14, 10, 0, 0).unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> EventTime {
+        EventTime::DateTime(
+            TIMEZONE
+                .with_ymd_and_hms(year, month, day, hour, minute, 0)
+                .unwrap()
+                .to_utc(),
+        )
+    }
+
+    fn on(year: i32, month: u32, day: u32) -> EventTime {
+        EventTime::Date(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+    }
+
+    fn parse(text: &str) -> QuickEvent {
+        parse_quick_event(text, now()).unwrap()
+    }
+
+    #[test]
+    fn time_range_and_location() {
+        let event = parse("Team retro next friday 14:00-15:30 @ room B");
+        assert_eq!(event.summary, "Team retro");
+        assert_eq!(event.location.as_deref(), Some("room B"));
+        assert_eq!(event.start, at(2026, 10, 16, 14, 0));
+        assert_eq!(event.end, at(2026, 10, 16, 15, 30));
+    }
+
+    #[test]
+    fn french_time_range() {
+        let event = parse("Rétro d'équipe vendredi prochain de 14h à 15h30 @ salle B");
+        assert_eq!(event.summary, "Rétro d'équipe");
+        assert_eq!(event.location.as_deref(), Some("salle B"));
+        assert_eq!(event.start, at(2026, 10, 16, 14, 0));
+        assert_eq!(event.end, at(2026, 10, 16, 15, 30));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("45m"), Some(TimeDelta::minutes(45)));
+        assert_eq!(parse_duration("90 minutes"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("1h30"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("1h 30min"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("2 Hours"), Some(TimeDelta::hours(2)));
+        assert_eq!(parse_duration("2d"), Some(TimeDelta::days(2)));
+        assert_eq!(parse_duration("3 jours"), Some(TimeDelta::days(3)));
+        assert_eq!(
+            parse_duration("1d 2h"),
+            Some(TimeDelta::days(1) + TimeDelta::hours(2))
+        );
+
+        assert_eq!(parse_duration("0"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("10am"), None);
+        assert_eq!(parse_duration("2 people"), None);
+    }
+
+    #[test]
+    fn duration() {
+        let event = parse("Lunch tomorrow at noon for 90 minutes");
+        assert_eq!(event.summary, "Lunch");
+        assert_eq!(event.start, at(2026, 10, 15, 12, 0));
+        assert_eq!(event.end, at(2026, 10, 15, 13, 30));
+
+        let event = parse("Atelier demain à 9h pendant 2h");
+        assert_eq!(event.summary, "Atelier");
+        assert_eq!(event.start, at(2026, 10, 15, 9, 0));
+        assert_eq!(event.end, at(2026, 10, 15, 11, 0));
+
+        let event = parse("Review today 16:00 45min");
+        assert_eq!(event.summary, "Review");
+        assert_eq!(event.end, at(2026, 10, 14, 16, 45));
+    }
+
+    #[test]
+    fn time_without_date() {
+        // 9:30 is over, the event is tomorrow
+        let event = parse("Standup 9:30");
+        assert_eq!(event.start, at(2026, 10, 15, 9, 30));
+        assert_eq!(event.end, at(2026, 10, 15, 10, 30));
+
+        let event = parse("Call 2-3pm");
+        assert_eq!(event.summary, "Call");
+        assert_eq!(event.start, at(2026, 10, 14, 14, 0));
+        assert_eq!(event.end, at(2026, 10, 14, 15, 0));
+    }
+
+    #[test]
+    fn meridiem() {
+        let event = parse("Breakfast on 2026-11-02 at 8 am");
+        assert_eq!(event.summary, "Breakfast");
+        assert_eq!(event.start, at(2026, 11, 2, 8, 0));
+
+        let event = parse("Drinks friday from 5 to 7:30pm");
+        assert_eq!(event.summary, "Drinks");
+        assert_eq!(event.start, at(2026, 10, 16, 17, 0));
+        assert_eq!(event.end, at(2026, 10, 16, 19, 30));
+
+        let event = parse("Lunch 12pm");
+        assert_eq!(event.start, at(2026, 10, 14, 12, 0));
+    }
+
+    #[test]
+    fn all_day() {
+        let event = parse("Offsite 20 octobre");
+        assert_eq!(event.summary, "Offsite");
+        assert_eq!(event.start, on(2026, 10, 20));
+        assert_eq!(event.end, on(2026, 10, 21));
+
+        let event = parse("Offsite on October 20th for 2 days");
+        assert_eq!(event.start, on(2026, 10, 20));
+        assert_eq!(event.end, on(2026, 10, 22));
+    }
+
+    #[test]
+    fn relative_dates() {
+        assert_eq!(
+            parse("Dîner dans 3 jours à 19h").start,
+            at(2026, 10, 17, 19, 0)
+        );
+        assert_eq!(parse("Trip in 2 weeks").start, on(2026, 10, 28));
+        assert_eq!(parse("Move après-demain").start, on(2026, 10, 16));
+        assert_eq!(parse("Move the day after tomorrow").start, on(2026, 10, 16));
+        assert_eq!(parse("Planning next week").start, on(2026, 10, 19));
+        assert_eq!(
+            parse("Planning la semaine prochaine").start,
+            on(2026, 10, 19)
+        );
+    }
+
+    #[test]
+    fn weekdays() {
+        // Today is a wednesday
+        assert_eq!(parse("Sync wednesday 15h").start, at(2026, 10, 14, 15, 0));
+        assert_eq!(
+            parse("Sync next wednesday 15h").start,
+            at(2026, 10, 21, 15, 0)
+        );
+        assert_eq!(parse("Sync lundi 15h").start, at(2026, 10, 19, 15, 0));
+    }
+
+    #[test]
+    fn numeric_dates() {
+        assert_eq!(parse("Review 20.10").start, on(2026, 10, 20));
+        assert_eq!(parse("Review 20/10/2027").start, on(2027, 10, 20));
+        // The 1st of September is over this year
+        assert_eq!(parse("Review 1/9").start, on(2027, 9, 1));
+    }
+
+    #[test]
+    fn days() {
+        let today = now().date_naive();
+        assert_eq!(
+            parse_day("le 20 octobre", today),
+            NaiveDate::from_ymd_opt(2026, 10, 20)
+        );
+        assert_eq!(
+            parse_day("Friday", today),
+            NaiveDate::from_ymd_opt(2026, 10, 16)
+        );
+        assert_eq!(parse_day("friday lunch", today), None);
+        assert_eq!(parse_day("", today), None);
+    }
+
+    #[test]
+    fn hours() {
+        let time = |hour: u32, minute: u32| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        assert_eq!(parse_hours("09:00-17:30"), Some((time(9, 0), time(17, 30))));
+        assert_eq!(parse_hours("de 8h à 12h"), Some((time(8, 0), time(12, 0))));
+        assert_eq!(parse_hours("9am to 5pm"), Some((time(9, 0), time(17, 0))));
+        assert_eq!(parse_hours("9am to 5pm daily"), None);
+    }
+
+    #[test]
+    fn overnight_range() {
+        let event = parse("Party friday 23:00-01:00");
+        assert_eq!(event.start, at(2026, 10, 16, 23, 0));
+        assert_eq!(event.end, at(2026, 10, 17, 1, 0));
+    }
+
+    #[test]
+    fn prefixes_are_kept_in_the_summary() {
+        let event = parse("Meet the team at the office tomorrow 10:00");
+        assert_eq!(event.summary, "Meet the team at the office");
+        assert_eq!(event.start, at(2026, 10, 15, 10, 0));
+    }
+
+    #[test]
+    fn incomplete_sentences() {
+        assert_eq!(parse_quick_event("Nothing planned", now()), None);
+        assert_eq!(parse_quick_event("tomorrow 14:00", now()), None);
+        assert_eq!(parse_quick_event("", now()), None);
+    }
+}
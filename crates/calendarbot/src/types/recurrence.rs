@@ -0,0 +1,567 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::types::EventTime;
+use anyhow::{anyhow, Result};
+use chrono_tz::Tz;
+use google_calendar3::chrono::{
+    DateTime, Datelike, Months, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc, Weekday,
+};
+
+/// Upper bound of periods walked by a rule, protects against rules that never match
+const MAX_PERIODS: usize = 50_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A RRULE (RFC 5545 3.3.10), sub-daily frequencies and BYHOUR/BYMINUTE are not supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<EventTime>,
+    /// Weekdays with an optional ordinal, e.g. `-1FR` for the last friday
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub week_start: Weekday,
+}
+
+/// Recurrence of a series of events (RRULE, RDATE and EXDATE)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub rules: Vec<RecurrenceRule>,
+    pub rdates: Vec<EventTime>,
+    pub exdates: Vec<EventTime>,
+    /// Timezone of the series, occurrences keep their local time across DST transitions
+    pub timezone: Tz,
+}
+
+/// Converts a local time to UTC, nonexistent local times (DST gaps) are shifted forward by an hour
+pub fn localize(timezone: Tz, date_time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    timezone
+        .from_local_datetime(&date_time)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(date_time + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map(|date_time| date_time.with_timezone(&Utc))
+}
+
+/// Identifier of an occurrence of a series, same format as Google Calendar instances
+pub fn instance_id(series_id: &str, start: &EventTime) -> String {
+    match start {
+        EventTime::Date(date) => format!("{}_{}", series_id, date.format("%Y%m%d")),
+        EventTime::DateTime(date_time) => {
+            format!("{}_{}", series_id, date_time.format("%Y%m%dT%H%M%SZ"))
+        }
+    }
+}
+
+impl Recurrence {
+    /// Start of every occurrence of a series starting at `start`, before `until`
+    pub fn occurrences(&self, start: EventTime, until: DateTime<Utc>) -> Vec<EventTime> {
+        let mut occurrences = vec![start];
+        for rule in &self.rules {
+            occurrences.extend(rule.occurrences(start, self.timezone, until));
+        }
+        occurrences.extend(self.rdates.iter().copied());
+
+        occurrences.retain(|occurrence| {
+            occurrence.to_utc(&self.timezone) < until && !self.is_excluded(occurrence)
+        });
+        occurrences.sort_by_key(|occurrence| occurrence.to_utc(&self.timezone));
+        occurrences.dedup();
+
+        occurrences
+    }
+
+    fn is_excluded(&self, occurrence: &EventTime) -> bool {
+        self.exdates
+            .iter()
+            .any(|exdate| match (exdate, occurrence) {
+                (EventTime::DateTime(exdate), EventTime::DateTime(occurrence)) => {
+                    exdate == occurrence
+                }
+                (exdate, occurrence) => {
+                    exdate.date_naive(&self.timezone) == occurrence.date_naive(&self.timezone)
+                }
+            })
+    }
+}
+
+impl RecurrenceRule {
+    /// Parses a RRULE value, a floating UNTIL is interpreted in `timezone`
+    pub fn parse(value: &str, timezone: Tz) -> Result<Self> {
+        let mut frequency = None;
+        let mut rule = RecurrenceRule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+            by_set_pos: vec![],
+            week_start: Weekday::Mon,
+        };
+
+        for part in value.trim().split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid rule part {}", part))?;
+
+            match name.to_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => return Err(anyhow!("Unsupported frequency {}", other)),
+                    })
+                }
+                "INTERVAL" => rule.interval = value.parse::<u32>()?.max(1),
+                "COUNT" => rule.count = Some(value.parse()?),
+                "UNTIL" => rule.until = Some(parse_until(value, timezone)?),
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(parse_weekday_num)
+                        .collect::<Result<_>>()?
+                }
+                "BYMONTHDAY" => rule.by_month_day = parse_list(value)?,
+                "BYMONTH" => rule.by_month = parse_list(value)?,
+                "BYSETPOS" => rule.by_set_pos = parse_list(value)?,
+                "WKST" => rule.week_start = parse_weekday(value)?,
+                "BYHOUR" | "BYMINUTE" | "BYSECOND" | "BYYEARDAY" | "BYWEEKNO" => {
+                    return Err(anyhow!("Unsupported rule part {}", name))
+                }
+                // Unknown parts are ignored (RSCALE, SKIP, ...)
+                _ => {}
+            }
+        }
+
+        rule.frequency = frequency.ok_or_else(|| anyhow!("Rule without frequency"))?;
+        Ok(rule)
+    }
+
+    /// Occurrences generated by this rule, `start` always being the first one
+    fn occurrences(&self, start: EventTime, timezone: Tz, until: DateTime<Utc>) -> Vec<EventTime> {
+        let (first_date, time) = match start {
+            EventTime::Date(date) => (date, None),
+            EventTime::DateTime(date_time) => {
+                let local = date_time.with_timezone(&timezone).naive_local();
+                (local.date(), Some(local.time()))
+            }
+        };
+
+        let mut occurrences = vec![start];
+        let mut period = self.first_period(first_date);
+
+        for _ in 0..MAX_PERIODS {
+            for date in self.period_dates(period, first_date) {
+                if date <= first_date {
+                    continue;
+                }
+                let occurrence = match time {
+                    None => EventTime::Date(date),
+                    Some(time) => match localize(timezone, date.and_time(time)) {
+                        Some(date_time) => EventTime::DateTime(date_time),
+                        None => continue,
+                    },
+                };
+
+                if self
+                    .count
+                    .is_some_and(|count| occurrences.len() >= count as usize)
+                    || self.is_after_until(&occurrence, timezone)
+                    || occurrence.to_utc(&timezone) >= until
+                {
+                    return occurrences;
+                }
+                occurrences.push(occurrence);
+            }
+
+            match self.next_period(period) {
+                Some(next) => period = next,
+                None => break,
+            }
+        }
+
+        occurrences
+    }
+
+    fn is_after_until(&self, occurrence: &EventTime, timezone: Tz) -> bool {
+        match self.until {
+            None => false,
+            Some(EventTime::Date(until)) => occurrence.date_naive(&timezone) > until,
+            Some(EventTime::DateTime(until)) => occurrence.to_utc(&timezone) > until,
+        }
+    }
+
+    fn first_period(&self, date: NaiveDate) -> NaiveDate {
+        match self.frequency {
+            Frequency::Daily => date,
+            Frequency::Weekly => {
+                let offset = (date.weekday().num_days_from_monday() + 7
+                    - self.week_start.num_days_from_monday())
+                    % 7;
+                date - TimeDelta::days(offset as i64)
+            }
+            Frequency::Monthly => date.with_day(1).unwrap_or(date),
+            Frequency::Yearly => date.with_ordinal(1).unwrap_or(date),
+        }
+    }
+
+    fn next_period(&self, period: NaiveDate) -> Option<NaiveDate> {
+        match self.frequency {
+            Frequency::Daily => period.checked_add_signed(TimeDelta::days(self.interval as i64)),
+            Frequency::Weekly => period.checked_add_signed(TimeDelta::weeks(self.interval as i64)),
+            Frequency::Monthly => period.checked_add_months(Months::new(self.interval)),
+            Frequency::Yearly => {
+                period.checked_add_months(Months::new(self.interval.checked_mul(12)?))
+            }
+        }
+    }
+
+    /// Sorted dates matching the rule in the period starting at `period`
+    fn period_dates(&self, period: NaiveDate, first_date: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates: Vec<NaiveDate> = match self.frequency {
+            Frequency::Daily => vec![period],
+            Frequency::Weekly => (0..7)
+                .map(|i| period + TimeDelta::days(i))
+                .filter(|date| match self.by_day.is_empty() {
+                    true => date.weekday() == first_date.weekday(),
+                    false => self.by_day.iter().any(|(_, day)| *day == date.weekday()),
+                })
+                .collect(),
+            Frequency::Monthly => self.month_dates(period.year(), period.month(), first_date),
+            Frequency::Yearly => {
+                if self.by_month.is_empty()
+                    && self.by_month_day.is_empty()
+                    && !self.by_day.is_empty()
+                {
+                    // BYDAY alone applies to the whole year, e.g. 20MO is the 20th monday
+                    let last = period
+                        .with_month(12)
+                        .and_then(|date| date.with_day(31))
+                        .unwrap_or(period);
+                    self.by_day
+                        .iter()
+                        .flat_map(|(ordinal, day)| weekdays_between(period, last, *ordinal, *day))
+                        .collect()
+                } else {
+                    let months = match self.by_month.is_empty() {
+                        true => vec![first_date.month()],
+                        false => self.by_month.clone(),
+                    };
+                    months
+                        .into_iter()
+                        .flat_map(|month| self.month_dates(period.year(), month, first_date))
+                        .collect()
+                }
+            }
+        };
+
+        dates.retain(|date| self.by_month.is_empty() || self.by_month.contains(&date.month()));
+        if self.frequency == Frequency::Daily {
+            dates.retain(|date| {
+                (self.by_month_day.is_empty()
+                    || month_days(date.year(), date.month()).is_some_and(|days| {
+                        self.by_month_day
+                            .iter()
+                            .any(|day| resolve_month_day(*day, days) == Some(date.day()))
+                    }))
+                    && (self.by_day.is_empty()
+                        || self.by_day.iter().any(|(_, day)| *day == date.weekday()))
+            });
+        }
+        dates.sort();
+        dates.dedup();
+
+        if self.by_set_pos.is_empty() {
+            return dates;
+        }
+
+        let mut selected: Vec<NaiveDate> = self
+            .by_set_pos
+            .iter()
+            .filter_map(|pos| {
+                let index = match *pos {
+                    pos if pos > 0 => pos as usize - 1,
+                    pos => dates.len().checked_sub(pos.unsigned_abs() as usize)?,
+                };
+                dates.get(index).copied()
+            })
+            .collect();
+        selected.sort();
+        selected.dedup();
+        selected
+    }
+
+    /// Dates of a month matching BYMONTHDAY and BYDAY, or the day of the first occurrence
+    fn month_dates(&self, year: i32, month: u32, first_date: NaiveDate) -> Vec<NaiveDate> {
+        let Some(days) = month_days(year, month) else {
+            return vec![];
+        };
+        let date = |day: u32| NaiveDate::from_ymd_opt(year, month, day);
+
+        if !self.by_month_day.is_empty() {
+            self.by_month_day
+                .iter()
+                .filter_map(|day| resolve_month_day(*day, days).and_then(date))
+                .filter(|date| {
+                    self.by_day.is_empty()
+                        || self.by_day.iter().any(|(_, day)| *day == date.weekday())
+                })
+                .collect()
+        } else if !self.by_day.is_empty() {
+            let (Some(first), Some(last)) = (date(1), date(days)) else {
+                return vec![];
+            };
+            self.by_day
+                .iter()
+                .flat_map(|(ordinal, day)| weekdays_between(first, last, *ordinal, *day))
+                .collect()
+        } else {
+            // Months without this day are skipped (RFC 5545 3.3.10)
+            date(first_date.day()).into_iter().collect()
+        }
+    }
+}
+
+fn month_days(year: i32, month: u32) -> Option<u32> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next = first.checked_add_months(Months::new(1))?;
+    Some((next - first).num_days() as u32)
+}
+
+/// Resolves a possibly negative BYMONTHDAY, -1 being the last day of the month
+fn resolve_month_day(day: i32, days: u32) -> Option<u32> {
+    let day = match day {
+        day if day > 0 => day,
+        day => days as i32 + day + 1,
+    };
+    (1..=days as i32).contains(&day).then_some(day as u32)
+}
+
+/// Every `weekday` between `first` and `last`, or only the nth one with an ordinal
+fn weekdays_between(
+    first: NaiveDate,
+    last: NaiveDate,
+    ordinal: Option<i32>,
+    weekday: Weekday,
+) -> Vec<NaiveDate> {
+    let dates: Vec<NaiveDate> = first
+        .iter_days()
+        .take_while(|date| *date <= last)
+        .filter(|date| date.weekday() == weekday)
+        .collect();
+
+    match ordinal {
+        None => dates,
+        Some(n) if n > 0 => dates.get(n as usize - 1).copied().into_iter().collect(),
+        Some(n) => dates
+            .len()
+            .checked_sub(n.unsigned_abs() as usize)
+            .and_then(|index| dates.get(index).copied())
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn parse_until(value: &str, timezone: Tz) -> Result<EventTime> {
+    if value.len() == 8 {
+        return Ok(EventTime::Date(NaiveDate::parse_from_str(value, "%Y%m%d")?));
+    }
+
+    let local = value.strip_suffix('Z');
+    let date_time = NaiveDateTime::parse_from_str(local.unwrap_or(value), "%Y%m%dT%H%M%S")?;
+    match local {
+        Some(_) => Ok(EventTime::DateTime(date_time.and_utc())),
+        None => localize(timezone, date_time)
+            .map(EventTime::DateTime)
+            .ok_or_else(|| anyhow!("Invalid UNTIL {}", value)),
+    }
+}
+
+fn parse_list<T: std::str::FromStr>(value: &str) -> Result<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .split(',')
+        .map(|item| Ok(item.trim().parse::<T>()?))
+        .collect()
+}
+
+fn parse_weekday(value: &str) -> Result<Weekday> {
+    match value.trim().to_uppercase().as_str() {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        other => Err(anyhow!("Invalid weekday {}", other)),
+    }
+}
+
+/// Parses a BYDAY item, e.g. `MO`, `2TU` or `-1FR`
+fn parse_weekday_num(value: &str) -> Result<(Option<i32>, Weekday)> {
+    let value = value.trim();
+    // Splits before the last two characters, feeds can hold anything there
+    let split = value
+        .char_indices()
+        .rev()
+        .nth(1)
+        .map_or(0, |(index, _)| index);
+    let (ordinal, day) = value.split_at(split);
+    let ordinal = match ordinal.trim_start_matches('+') {
+        "" => None,
+        ordinal => Some(ordinal.parse::<i32>()?).filter(|n| *n != 0),
+    };
+
+    Ok((ordinal, parse_weekday(day)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEZONE: Tz = chrono_tz::Europe::Zurich;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> EventTime {
+        EventTime::DateTime(
+            TIMEZONE
+                .with_ymd_and_hms(year, month, day, hour, minute, 0)
+                .unwrap()
+                .to_utc(),
+        )
+    }
+
+    fn on(year: i32, month: u32, day: u32) -> EventTime {
+        EventTime::Date(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+    }
+
+    fn recurrence(rule: &str) -> Recurrence {
+        Recurrence {
+            rules: vec![RecurrenceRule::parse(rule, TIMEZONE).unwrap()],
+            rdates: vec![],
+            exdates: vec![],
+            timezone: TIMEZONE,
+        }
+    }
+
+    fn until() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn local_time_is_kept_across_dst() {
+        // Summer time ends on Sunday 25 October 2026 in Zurich
+        let occurrences =
+            recurrence("FREQ=DAILY;COUNT=3").occurrences(at(2026, 10, 24, 9, 0), until());
+        assert_eq!(
+            occurrences,
+            vec![
+                at(2026, 10, 24, 9, 0),
+                at(2026, 10, 25, 9, 0),
+                at(2026, 10, 26, 9, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn nonexistent_times_are_shifted() {
+        // 02:30 doesn't exist on Sunday 29 March 2026 in Zurich
+        let occurrences =
+            recurrence("FREQ=DAILY;COUNT=3").occurrences(at(2026, 3, 28, 2, 30), until());
+        assert_eq!(
+            occurrences,
+            vec![
+                at(2026, 3, 28, 2, 30),
+                at(2026, 3, 29, 3, 30),
+                at(2026, 3, 30, 2, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn set_position() {
+        // Last weekday of the month
+        let occurrences = recurrence("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;COUNT=3")
+            .occurrences(on(2026, 10, 30), until());
+        assert_eq!(
+            occurrences,
+            vec![on(2026, 10, 30), on(2026, 11, 30), on(2026, 12, 31)]
+        );
+    }
+
+    #[test]
+    fn count_and_until() {
+        let start = on(2026, 10, 5);
+        assert_eq!(
+            recurrence("FREQ=WEEKLY;COUNT=2").occurrences(start, until()),
+            vec![on(2026, 10, 5), on(2026, 10, 12)]
+        );
+        // UNTIL is inclusive
+        assert_eq!(
+            recurrence("FREQ=WEEKLY;UNTIL=20261019").occurrences(start, until()),
+            vec![on(2026, 10, 5), on(2026, 10, 12), on(2026, 10, 19)]
+        );
+        // The first limit reached wins
+        assert_eq!(
+            recurrence("FREQ=WEEKLY;COUNT=10;UNTIL=20261012").occurrences(start, until()),
+            vec![on(2026, 10, 5), on(2026, 10, 12)]
+        );
+    }
+
+    #[test]
+    fn exdates_and_rdates() {
+        let recurrence = Recurrence {
+            exdates: vec![at(2026, 10, 13, 9, 0), on(2026, 10, 15)],
+            rdates: vec![at(2026, 10, 17, 11, 0)],
+            ..recurrence("FREQ=DAILY;COUNT=5")
+        };
+        assert_eq!(
+            recurrence.occurrences(at(2026, 10, 12, 9, 0), until()),
+            vec![
+                at(2026, 10, 12, 9, 0),
+                at(2026, 10, 14, 9, 0),
+                at(2026, 10, 16, 9, 0),
+                at(2026, 10, 17, 11, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert_eq!(parse_weekday_num("-1FR").unwrap(), (Some(-1), Weekday::Fri));
+        assert!(parse_weekday_num("1é").is_err());
+        assert!(parse_weekday_num("é").is_err());
+        assert!(parse_weekday_num("éa").is_err());
+        assert!(parse_weekday_num("").is_err());
+
+        // Too large to be walked, but never panics
+        let rule = recurrence("FREQ=YEARLY;INTERVAL=4000000000");
+        assert_eq!(
+            rule.occurrences(on(2026, 10, 5), until()),
+            vec![on(2026, 10, 5)]
+        );
+    }
+}
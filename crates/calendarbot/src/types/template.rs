@@ -0,0 +1,249 @@
+/*
+Calendarbot  Copyright (C) 2023 Zbinden Yohan
+
+This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
+This is free software, and you are welcome to redistribute it
+ */
+use crate::i18n::{replace_placeholders, Locale, Text};
+use crate::models::GuildCalendar;
+use crate::types::EMBED_COLOR;
+use anyhow::{anyhow, Result};
+use google_calendar3::chrono::format::{Item, StrftimeItems};
+use google_calendar3::chrono::NaiveDate;
+use poise::serenity_prelude as serenity;
+use regex::Regex;
+use std::fmt::Write;
+
+pub const DEFAULT_DAY_FORMAT: &str = "**%A** - %e %B";
+
+/// Discord limits of the embed title and footer
+pub const MAX_TITLE_LENGTH: usize = 256;
+pub const MAX_FOOTER_LENGTH: usize = 2048;
+pub const MAX_DAY_FORMAT_LENGTH: usize = 64;
+pub const MAX_EVENT_FORMAT_LENGTH: usize = 256;
+
+/// Placeholders that can be used in the event line template
+pub const EVENT_PLACEHOLDERS: [&str; 7] = [
+    "time",
+    "start",
+    "end",
+    "summary",
+    "location",
+    "description",
+    "link",
+];
+
+/// How the embeds of a channel look
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EmbedTemplate {
+    /// Defaults to "Events" in the language of the guild
+    pub title: Option<String>,
+    pub color: u32,
+    /// Format of the day headers, the default one shows the date when the year differs
+    pub day_format: Option<String>,
+    /// Replaces the default event lines
+    pub event_format: Option<String>,
+    pub footer: Option<String>,
+}
+
+impl Default for EmbedTemplate {
+    fn default() -> Self {
+        Self {
+            title: None,
+            color: serenity::Colour::from(EMBED_COLOR).0,
+            day_format: None,
+            event_format: None,
+            footer: None,
+        }
+    }
+}
+
+impl From<&GuildCalendar> for EmbedTemplate {
+    fn from(guild_calendar: &GuildCalendar) -> Self {
+        let default = Self::default();
+        Self {
+            title: guild_calendar.embedTitle.clone(),
+            color: guild_calendar
+                .embedColor
+                .map_or(default.color, |color| color as u32),
+            day_format: guild_calendar.dayFormat.clone(),
+            event_format: guild_calendar.eventFormat.clone(),
+            footer: guild_calendar.embedFooter.clone(),
+        }
+    }
+}
+
+/// Values of the placeholders of an event line
+pub struct EventPlaceholders<'a> {
+    pub time: &'a str,
+    pub start: &'a str,
+    pub end: &'a str,
+    pub summary: &'a str,
+    pub location: &'a str,
+    pub description: &'a str,
+    pub link: &'a str,
+}
+
+impl EmbedTemplate {
+    /// Replaces the placeholders of the event line template
+    pub fn format_event(&self, values: &EventPlaceholders) -> Option<String> {
+        let format = self.event_format.as_ref()?;
+        let line = replace_placeholders(format, |name| {
+            let value = match name {
+                "time" => values.time,
+                "start" => values.start,
+                "end" => values.end,
+                "summary" => values.summary,
+                "location" => values.location,
+                "description" => values.description,
+                "link" => values.link,
+                _ => return None,
+            };
+            Some(value.to_string())
+        });
+
+        Some(format!("{}\n", line))
+    }
+}
+
+pub fn validate_title(title: &str, locale: Locale) -> Result<String> {
+    let title = title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+        return Err(anyhow!(
+            Text::TitleLength.format(locale, &[("max", &MAX_TITLE_LENGTH)])
+        ));
+    }
+    Ok(title.to_string())
+}
+
+/// Parses a hexadecimal colour, e.g. `#b74700`
+pub fn parse_color(color: &str, locale: Locale) -> Result<u32> {
+    let hex = color.trim().trim_start_matches('#');
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(color),
+        _ => Err(anyhow!(
+            Text::InvalidColor.format(locale, &[("color", &color)])
+        )),
+    }
+}
+
+/// Checks that chrono understands every specifier of the day header format
+pub fn validate_day_format(format: &str, locale: Locale) -> Result<String> {
+    if format.trim().is_empty() || format.chars().count() > MAX_DAY_FORMAT_LENGTH {
+        return Err(anyhow!(
+            Text::DayFormatLength.format(locale, &[("max", &MAX_DAY_FORMAT_LENGTH)])
+        ));
+    }
+    // Time specifiers like `%H` are parsed but can't be written with a date
+    let mut sample = String::new();
+    let sample_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap_or_default();
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+        || write!(sample, "{}", sample_date.format(format)).is_err()
+    {
+        return Err(anyhow!(
+            Text::InvalidDayFormat.format(locale, &[("format", &format)])
+        ));
+    }
+    Ok(format.to_string())
+}
+
+/// Checks that the event line template only uses known placeholders
+pub fn validate_event_format(format: &str, locale: Locale) -> Result<String> {
+    static PLACEHOLDER_REGEX: &str = r"\{([^{}]*)\}";
+
+    if format.trim().is_empty() || format.chars().count() > MAX_EVENT_FORMAT_LENGTH {
+        return Err(anyhow!(
+            Text::EventFormatLength.format(locale, &[("max", &MAX_EVENT_FORMAT_LENGTH)])
+        ));
+    }
+
+    let re = Regex::new(PLACEHOLDER_REGEX).unwrap();
+    if let Some(unknown) = re
+        .captures_iter(format)
+        .map(|captures| captures[1].to_string())
+        .find(|name| !EVENT_PLACEHOLDERS.contains(&name.as_str()))
+    {
+        let placeholders = EVENT_PLACEHOLDERS
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(anyhow!(Text::UnknownPlaceholder.format(
+            locale,
+            &[
+                ("placeholder", &format!("{{{}}}", unknown)),
+                ("placeholders", &placeholders)
+            ]
+        )));
+    }
+    Ok(format.to_string())
+}
+
+pub fn validate_footer(footer: &str, locale: Locale) -> Result<String> {
+    let footer = footer.trim();
+    if footer.is_empty() || footer.chars().count() > MAX_FOOTER_LENGTH {
+        return Err(anyhow!(
+            Text::FooterLength.format(locale, &[("max", &MAX_FOOTER_LENGTH)])
+        ));
+    }
+    Ok(footer.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_in_values_are_kept() {
+        let template = EmbedTemplate {
+            event_format: Some("{time} {summary} {unknown} {link}".to_string()),
+            ..Default::default()
+        };
+        let values = EventPlaceholders {
+            time: "10:00",
+            start: "",
+            end: "",
+            summary: "Talk about {link} and {time}",
+            location: "",
+            description: "",
+            link: "https://example.com",
+        };
+        assert_eq!(
+            template.format_event(&values).as_deref(),
+            Some("10:00 Talk about {link} and {time} {unknown} https://example.com\n")
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let locale = Locale::default();
+        assert_eq!(parse_color("#b74700", locale).unwrap(), 0xb74700);
+        assert_eq!(parse_color(" B74700 ", locale).unwrap(), 0xb74700);
+        assert!(parse_color("#b747", locale).is_err());
+        assert!(parse_color("#b7470000", locale).is_err());
+        assert!(parse_color("orange", locale).is_err());
+    }
+
+    #[test]
+    fn day_formats() {
+        let locale = Locale::default();
+        assert_eq!(
+            validate_day_format("%A %-d %B", locale).unwrap(),
+            "%A %-d %B"
+        );
+        // Days have no time to show
+        assert!(validate_day_format("%A %H:%M", locale).is_err());
+        assert!(validate_day_format("%Q", locale).is_err());
+        assert!(validate_day_format(" ", locale).is_err());
+    }
+
+    #[test]
+    fn event_formats() {
+        let locale = Locale::default();
+        assert!(validate_event_format("{time} **{summary}** {location}", locale).is_ok());
+
+        let error = validate_event_format("{time} {title}", locale).unwrap_err();
+        assert!(error.to_string().contains("{title}"));
+        assert!(validate_event_format("", locale).is_err());
+    }
+}
@@ -4,67 +4,154 @@ Calendarbot  Copyright (C) 2023 Zbinden Yohan
 This program comes with ABSOLUTELY NO WARRANTY; for details type `show w'.
 This is free software, and you are welcome to redistribute it
  */
-use core::fmt;
-#[macro_export]
-macro_rules! timezone {
-    ($($name:ident => $value:expr,)*) => {
-        #[derive(Debug, poise::ChoiceParameter)]
-        pub enum TimezoneChoices {
-            $(
-                #[name = $value]
-                $name,
-            )*
-        }
-        impl fmt::Display for TimezoneChoices {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                match self {
-                    $(
-                        TimezoneChoices::$name => write!(f, $value),
-                    )*
-                }
-            }
-        }
-        impl TimezoneChoices {
-            pub fn to_normalized_string(&self) -> String {
-                let re = regex::Regex::new(r"([A-Z][a-z]+)").expect("Unable to create regex pattern");
-                let mut index = 0;
-                let mut result = String::new();
-                let name = match self {
-                    $(
-                        TimezoneChoices::$name => stringify!($name),
-                    )*
-                };
-                for field in re.find_iter(name) {
-                    if index == 1 {
-                        result.push_str("/")
-                    } else if index > 1 {
-                        result.push_str("_")
-                    }
-                    result.push_str(&field.as_str());
-                    index += 1;
-                }
-                result
-            }
-        }
-    };
+use crate::ApplicationContext;
+use chrono_tz::{Tz, TZ_VARIANTS};
+use google_calendar3::chrono::{Offset, Utc};
+use poise::serenity_prelude as serenity;
+
+/// Discord doesn't show more than 25 suggestions
+const MAX_SUGGESTIONS: usize = 25;
+
+/// Finds an IANA timezone by name, ignoring case
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    let name = name.trim();
+    TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// Returns the current UTC offset of a timezone, e.g. `+02:00`
+pub fn utc_offset(timezone: Tz) -> String {
+    let seconds = Utc::now()
+        .with_timezone(&timezone)
+        .offset()
+        .fix()
+        .local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
 }
 
-timezone!(
-    EtcUtc => "UTC (+00:00)",
-    AfricaLagos => "Africa/Lagos (+01:00)",
-    AfricaCairo => "Africa/Cairo (+02:00/ +03:00)",
-    AfricaCeuta => "Africa/Ceuta (+01:00 / +02:00)",
-    AfricaElAaiun => "Africa/Casablanca (+01:00 / +00:00)",
-    AfricaKhartoum => "Africa/Khartoum (+02:00)",
-    AfricaNairobi => "Africa/Nairobi (+03:00)",
-    AmericaAdak => "America/Adak (-10:00, -9:00)",
-    AmericaAnchorage => "America/Anchorage (-09:00, -8:00)",
-    AmericaAraguaina => "America/Araguaina (-03:00)",
-    AmericaAsuncion => "America/Asuncion (-04:00 /-03:00)",
-    AmericaBarbados => "America/Barbados (-04:00)",
-    AmericaBahiaBanderas => "America/Bahia_Banderas (-06:00)",
-    AmericaBogota => "America/Bogota (-05:00)",
-    AmericaBoise => "America/Boise (-07:00 / -06:00)",
-    EuropeZurich => "Europe/Zurich (+01:00 / +02:00)",
-    EuropeBucharest => "Europe/Bucharest (+02:00 / +03:00)",
-);
+/// Lowercases a timezone name or a query and replaces separators by spaces
+fn normalize(value: &str) -> String {
+    value.trim().to_lowercase().replace(['_', '-'], " ")
+}
+
+/// Ranks how well a timezone matches a query, lower is better
+fn match_score(name: &str, query: &str) -> Option<u8> {
+    let name = normalize(name);
+    let city = name.rsplit('/').next().unwrap_or(&name);
+
+    if name == query || city == query {
+        Some(0)
+    } else if city.starts_with(query) {
+        Some(1)
+    } else if name.split(['/', ' ']).any(|word| word.starts_with(query)) {
+        Some(2)
+    } else if name.contains(query) {
+        Some(3)
+    } else {
+        // Letters in order, so typos like "bnos aires" still match
+        let mut chars = name.chars();
+        query
+            .chars()
+            .filter(|c| *c != ' ')
+            .all(|c| chars.any(|n| n == c))
+            .then_some(4)
+    }
+}
+
+/// Timezones matching a partial name, best matches first
+pub fn search_timezones(query: &str) -> Vec<Tz> {
+    let query = normalize(query);
+
+    let mut matches: Vec<(u8, Tz)> = TZ_VARIANTS
+        .iter()
+        .filter_map(|tz| Some((match_score(tz.name(), &query)?, *tz)))
+        .collect();
+    // Legacy aliases without an area (GB, NZ, EST, ...) come after the canonical names
+    matches
+        .sort_by_key(|(score, tz)| (*score, !tz.name().contains('/'), tz.name().len(), tz.name()));
+
+    matches
+        .into_iter()
+        .map(|(_, tz)| tz)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// Suggests timezones with their current offset, e.g. `Europe/Zurich (UTC+02:00)`
+pub async fn autocomplete_timezone(
+    _ctx: ApplicationContext<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    search_timezones(partial)
+        .into_iter()
+        .map(|tz| {
+            serenity::AutocompleteChoice::new(
+                format!("{} (UTC{})", tz.name(), utc_offset(tz)),
+                tz.name(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(query: &str) -> Vec<&'static str> {
+        search_timezones(query)
+            .into_iter()
+            .map(|tz| tz.name())
+            .collect()
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(match_score("Europe/Zurich", "europe/zurich"), Some(0));
+        assert_eq!(match_score("Europe/Zurich", "zurich"), Some(0));
+        assert_eq!(match_score("America/Port-au-Prince", "port au"), Some(1));
+        assert_eq!(
+            match_score("America/Argentina/Buenos_Aires", "argentina"),
+            Some(2)
+        );
+        assert_eq!(
+            match_score("America/Argentina/Buenos_Aires", "nos ai"),
+            Some(3)
+        );
+        assert_eq!(
+            match_score("America/Argentina/Buenos_Aires", "bnos aires"),
+            Some(4)
+        );
+        assert_eq!(match_score("Europe/Zurich", "paris"), None);
+    }
+
+    #[test]
+    fn search() {
+        assert_eq!(names("port-au-prince")[0], "America/Port-au-Prince");
+        assert_eq!(names("Port au")[0], "America/Port-au-Prince");
+        // The legacy America/Buenos_Aires alias is the same timezone
+        assert!(names("buenos_aires")[..2].contains(&"America/Argentina/Buenos_Aires"));
+        assert!(names("bnos aires").contains(&"America/Argentina/Buenos_Aires"));
+        // Canonical names before the legacy aliases
+        assert_eq!(names("utc")[0], "Etc/UTC");
+        assert!(names("").len() <= MAX_SUGGESTIONS);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_timezone(" america/argentina/buenos_aires "),
+            Some(chrono_tz::America::Argentina::Buenos_Aires)
+        );
+        assert_eq!(
+            parse_timezone("America/Port-au-Prince"),
+            Some(chrono_tz::America::PortauPrince)
+        );
+        assert_eq!(parse_timezone("Etc/Utc"), Some(chrono_tz::Etc::UTC));
+        assert_eq!(parse_timezone("Buenos Aires"), None);
+    }
+}